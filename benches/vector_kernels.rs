@@ -0,0 +1,87 @@
+//! How much faster the SIMD kernels are than the loops in `extras.cpp`
+//!
+//! Run with `cargo bench`. For each size of vector, this times the plain
+//! loops of `sql_dot()` and `sql_cossim()`, then the kernels with each
+//! instruction set this CPU supports, and prints the time per call and the
+//! speedup over `extras.cpp`. Every kernel is checked against the others
+//! first, since the point is that they all agree.
+extern crate sqlite3_extras;
+
+use sqlite3_extras::vector::simd::Isa;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Common embedding sizes
+const DIMS: [usize; 4] = [128, 384, 768, 1536];
+
+/// How long to time each loop for
+const BUDGET: Duration = Duration::from_millis(300);
+
+/// The loop of `sql_dot()`, adding the products one by one
+fn extras_dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).fold(0.0, |end, (x, y)| end + x * y)
+}
+
+/// The three loops of `sql_cossim()`
+fn extras_cossim(a: &[f64], b: &[f64]) -> f64 {
+    let asq = extras_dot(a, a);
+    let bsq = extras_dot(b, b);
+    let absq = extras_dot(a, b);
+    absq / (asq.sqrt() * bsq.sqrt())
+}
+
+/// The loop of `sql_dot()`, for the squared Euclidean distance
+fn extras_l2_squared(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).fold(0.0, |end, (x, y)| end + (x - y) * (x - y))
+}
+
+fn cossim(isa: Isa, a: &[f64], b: &[f64]) -> f64 {
+    isa.dot(a, b) / (isa.dot(a, a).sqrt() * isa.dot(b, b).sqrt())
+}
+
+/// Pseudo-random numbers from -1 to 1, the same every run
+fn random(n: usize, seed: u64) -> Vec<f64> {
+    let mut state = seed;
+    (0..n).map(|_| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }).collect()
+}
+
+/// Nanoseconds per call of `f`, averaged over BUDGET
+fn time<F: FnMut() -> f64>(mut f: F) -> f64 {
+    let mut calls = 0u64;
+    let start = Instant::now();
+    while start.elapsed() < BUDGET {
+        for _ in 0..100 {
+            black_box(f());
+        }
+        calls += 100;
+    }
+    start.elapsed().as_secs_f64() * 1e9 / calls as f64
+}
+
+/// Time `extras.cpp`'s loop and each kernel, and print a line for each
+fn compare<E, K>(name: &str, a: &[f64], b: &[f64], extras: E, kernel: K)
+    where E: Fn(&[f64], &[f64]) -> f64, K: Fn(Isa, &[f64], &[f64]) -> f64
+{
+    let expected = kernel(Isa::Scalar, a, b);
+    let baseline = time(|| extras(black_box(a), black_box(b)));
+    println!("{:<8} {:>5}  {:<10} {:>9.1} ns", name, a.len(), "extras.cpp", baseline);
+    for &isa in Isa::ALL.iter().rev().filter(|isa| isa.is_supported()) {
+        assert_eq!(kernel(isa, a, b).to_bits(), expected.to_bits(), "{} {} differs", name, isa.name());
+        let ns = time(|| kernel(isa, black_box(a), black_box(b)));
+        println!("{:<8} {:>5}  {:<10} {:>9.1} ns {:>6.2}x", name, a.len(), isa.name(), ns, baseline / ns);
+    }
+}
+
+fn main() {
+    println!("best instruction set: {}", Isa::best().name());
+    for &dims in DIMS.iter() {
+        let a = random(dims, 1);
+        let b = random(dims, 2);
+        compare("dot", &a, &b, extras_dot, |isa, a, b| isa.dot(a, b));
+        compare("cossim", &a, &b, extras_cossim, cossim);
+        compare("l2sq", &a, &b, extras_l2_squared, |isa, a, b| isa.l2_squared(a, b));
+    }
+}
@@ -0,0 +1,109 @@
+//! Aggregate and window functions
+//!
+//! Implement `Aggregate` and register it with `create_aggregate!`. SQLite
+//! keeps one `Aggregate` per group (or per window), created with
+//! `Default` when the group's first row arrives, and drops it when the
+//! group is done. Groups without any rows get a fresh default value too,
+//! so `value()` decides what an empty group gives.
+//!
+//! With SQLite 3.25 or later every aggregate is also a window function.
+//! Frames which only grow, like the default `ROWS BETWEEN UNBOUNDED
+//! PRECEDING AND CURRENT ROW`, use `step()` and `value()`. Frames which
+//! slide, like `ROWS BETWEEN 6 PRECEDING AND CURRENT ROW`, also call
+//! `inverse()` with the oldest row in the frame as it leaves.
+use sqlite3_raw::*;
+use dynamics::*;
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+/// The state of an aggregate function for one group
+pub trait Aggregate: Default {
+    /// Add a row to the group
+    fn step(&mut self, args: &[SQLiteValue]) -> Result<(), String>;
+    /// Remove the oldest row from a window frame
+    ///
+    /// The arguments are the same as they were for that row's `step()`.
+    fn inverse(&mut self, args: &[SQLiteValue]) -> Result<(), String>;
+    /// The result for the rows so far
+    fn value(&self) -> Result<SQLiteReturn, String>;
+}
+
+/// Register an aggregate function
+///
+/// Use `create_aggregate!` instead, which names it with a `const_cstr!`.
+///
+/// # Safety
+/// `db` must be an open connection, the extension API must be loaded and
+/// `name` must be a nul-terminated string.
+pub unsafe fn create<A: Aggregate>(db: *mut sqlite3, name: *const c_char, argc: c_int) -> c_int {
+    let flags = SQLITE_UTF8 | SQLITE_DETERMINISTIC;
+    if sql_call!(libversion_number)() >= 3025000 {
+        sql_call!(create_window_function)(db, name, argc, flags, ptr::null_mut(),
+            Some(step::<A>), Some(finalize::<A>), Some(value::<A>), Some(inverse::<A>), None)
+    } else {
+        sql_call!(create_function)(db, name, argc, flags, ptr::null_mut(),
+            None, Some(step::<A>), Some(finalize::<A>))
+    }
+}
+
+/// The group's state, which SQLite keeps a pointer to
+///
+/// Returns None if the group has no state yet and `create` is false, or if
+/// SQLite couldn't allocate the pointer.
+unsafe fn state<'a, A: Aggregate>(ctx: *mut sqlite3_context, create: bool) -> Option<&'a mut A> {
+    let size = if create { mem::size_of::<*mut A>() as c_int } else { 0 };
+    let slot = sql_call!(aggregate_context)(ctx, size) as *mut *mut A;
+    if slot.is_null() {
+        return None;
+    }
+    // SQLite zeroes the slot when it allocates it
+    if (*slot).is_null() {
+        if !create {
+            return None;
+        }
+        *slot = Box::into_raw(Box::new(A::default()));
+    }
+    Some(&mut **slot)
+}
+
+unsafe extern "C" fn step<A: Aggregate>(ctx: *mut sqlite3_context, argc: c_int, argv: *mut *mut sqlite3_value) {
+    let args = SQLiteValue::from_raw_slice(argv, argc);
+    match state::<A>(ctx, true) {
+        Some(state) => if let Err(message) = state.step(args) { push_error(ctx, &message) },
+        None => sql_call!(result_error_nomem)(ctx)
+    }
+}
+
+unsafe extern "C" fn inverse<A: Aggregate>(ctx: *mut sqlite3_context, argc: c_int, argv: *mut *mut sqlite3_value) {
+    let args = SQLiteValue::from_raw_slice(argv, argc);
+    if let Some(state) = state::<A>(ctx, false) {
+        if let Err(message) = state.inverse(args) {
+            push_error(ctx, &message);
+        }
+    }
+}
+
+unsafe extern "C" fn value<A: Aggregate>(ctx: *mut sqlite3_context) {
+    let result = match state::<A>(ctx, false) {
+        Some(state) => state.value(),
+        None => A::default().value()
+    };
+    match result {
+        Ok(result) => result.push_to(ctx),
+        Err(message) => push_error(ctx, &message)
+    }
+}
+
+unsafe extern "C" fn finalize<A: Aggregate>(ctx: *mut sqlite3_context) {
+    let slot = sql_call!(aggregate_context)(ctx, 0) as *mut *mut A;
+    let state = if slot.is_null() || (*slot).is_null() { A::default() } else {
+        let state = *Box::from_raw(*slot);
+        *slot = ptr::null_mut();
+        state
+    };
+    match state.value() {
+        Ok(result) => result.push_to(ctx),
+        Err(message) => push_error(ctx, &message)
+    }
+}
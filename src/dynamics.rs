@@ -1,8 +1,10 @@
 //! SQLite Dynamic Type Wrappers
 use sqlite3_raw::*;
+use std::ffi::CStr;
 use std::os::raw::c_void;
 use std::string::FromUtf8Error;
 use std::slice;
+use std::ptr;
 // The following two are for bookkeeping with in-flight Strings taken from Rust
 // into SQLite-world
 use std::sync::Mutex;
@@ -21,11 +23,28 @@ use std::collections::HashMap;
 /// See [SQLite Documentation on values](https://sqlite.org/c3ref/value_blob.html)
 /// and [SQLite Documentation on columns](https://sqlite.org/c3ref/column_blob.html)
 /// for more details.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
 pub struct SQLiteValue(*mut sqlite3_value);
 impl SQLiteValue {
     pub unsafe fn from_raw_unchecked(ptr: *mut sqlite3_value) -> SQLiteValue {
         SQLiteValue(ptr)
     }
+    /// View a function's argument array as values, without copying
+    ///
+    /// # Safety
+    /// `argv` must point to `argc` values that outlive the slice.
+    pub unsafe fn from_raw_slice<'a>(argv: *mut *mut sqlite3_value, argc: i32) -> &'a [SQLiteValue] {
+        if argc <= 0 { &[] }
+        else { slice::from_raw_parts(argv as *const SQLiteValue, argc as usize) }
+    }
+    /// The storage class of the value, such as `SQLITE_NULL` or `SQLITE_BLOB`
+    pub fn value_type(&self) -> i32 {
+        unsafe { sql_call!(value_type)(self.0) }
+    }
+    pub fn is_null(&self) -> bool {
+        self.value_type() == SQLITE_NULL
+    }
 }
 impl From<SQLiteValue> for isize {
     fn from(val: SQLiteValue) -> isize {
@@ -131,30 +150,32 @@ impl SQLiteReturn {
                 SQLiteReturn::SQLiteNull => { sql_call!(result_null)(ctx); },
                 SQLiteReturn::SQLiteFloat(x) => { sql_call!(result_double)(ctx, x); },
                 SQLiteReturn::SQLiteInt(x) => { sql_call!(result_int64)(ctx, x); },
+                // Give ownership to the bookkeeping map before SQLite sees the
+                // pointer, and don't hold the lock while calling SQLite,
+                // because setting a result can free the previous one (through
+                // the destructor) or even this one.
                 SQLiteReturn::SQLiteText(x) => {
-                    let mut strings = SQLITE_STRINGS_IN_FLIGHT.lock().unwrap();
                     let cptr = x.as_ptr() as *const i8;
+                    let len = x.len() as u64;
+                    SQLITE_STRINGS_IN_FLIGHT.lock().unwrap().insert(cptr as usize, x);
                     sql_call!(result_text64)(
                         ctx,
                         cptr,
-                        x.len() as u64,
+                        len,
                         Some(string_destructor),
                         SQLITE_UTF8 as u8 // In C these types are more flexible
                         );
-                    // Insert it last since we are giving ownership away
-                    strings.insert(cptr as usize, x);
                 },
                 SQLiteReturn::SQLiteBlob(x) => {
-                    let mut blobs = SQLITE_BLOBS_IN_FLIGHT.lock().unwrap();
                     let cptr = x.as_ptr() as *const u8;
+                    let len = x.len() as u64;
+                    SQLITE_BLOBS_IN_FLIGHT.lock().unwrap().insert(cptr as usize, x);
                     sql_call!(result_blob64)(
                         ctx,
                         cptr as *const c_void,
-                        x.len() as u64,
+                        len,
                         Some(blob_destructor)
                         );
-                    // Insert it last since we are giving ownership away
-                    blobs.insert(cptr as usize, x);
                 }
             }
         }
@@ -173,13 +194,99 @@ impl From<Vec<u8>> for SQLiteReturn {
     fn from(x: Vec<u8>) -> SQLiteReturn { SQLiteReturn::SQLiteBlob(x) }
 }
 
+/// Report an error from a function instead of returning a value
+///
+/// SQLite copies the message, so it doesn't need to outlive the call.
+pub fn push_error(ctx: *mut sqlite3_context, message: &str) {
+    unsafe {
+        sql_call!(result_error)(ctx, message.as_ptr() as *const i8, message.len() as i32);
+    }
+}
+
+/// Run a query on a connection, calling `f` with the values of each row
+///
+/// The values are only valid during the call, and an error from `f` stops
+/// the query early.
+///
+/// # Safety
+/// `db` must be an open connection.
+pub unsafe fn query_each<F>(db: *mut sqlite3, sql: &str, mut f: F) -> Result<(), String>
+    where F: FnMut(&[SQLiteValue]) -> Result<(), String>
+{
+    let mut stmt = ptr::null_mut();
+    let rc = sql_call!(prepare_v2)(db, sql.as_ptr() as *const i8, sql.len() as i32, &mut stmt, ptr::null_mut());
+    if rc != SQLITE_OK {
+        return Err(errmsg(db));
+    }
+    let mut result = Ok(());
+    let mut values = vec![];
+    loop {
+        match sql_call!(step)(stmt) {
+            SQLITE_ROW => {
+                values.clear();
+                for i in 0..sql_call!(column_count)(stmt) {
+                    values.push(SQLiteValue::from_raw_unchecked(sql_call!(column_value)(stmt, i)));
+                }
+                result = f(&values);
+                if result.is_err() {
+                    break;
+                }
+            },
+            SQLITE_DONE => break,
+            _ => {
+                result = Err(errmsg(db));
+                break;
+            }
+        }
+    }
+    sql_call!(finalize)(stmt);
+    result
+}
+
+/// Run a statement which returns no rows, such as an INSERT, with
+/// `params` bound to its `?` parameters in order
+///
+/// # Safety
+/// `db` must be an open connection.
+pub unsafe fn execute(db: *mut sqlite3, sql: &str, params: &[SQLiteReturn]) -> Result<(), String> {
+    let mut stmt = ptr::null_mut();
+    let rc = sql_call!(prepare_v2)(db, sql.as_ptr() as *const i8, sql.len() as i32, &mut stmt, ptr::null_mut());
+    if rc != SQLITE_OK {
+        return Err(errmsg(db));
+    }
+    // The parameters outlive the statement, so SQLite needn't copy them
+    for (i, param) in params.iter().enumerate() {
+        let i = i as i32 + 1;
+        match *param {
+            SQLiteReturn::SQLiteNull => sql_call!(bind_null)(stmt, i),
+            SQLiteReturn::SQLiteFloat(x) => sql_call!(bind_double)(stmt, i, x),
+            SQLiteReturn::SQLiteInt(x) => sql_call!(bind_int64)(stmt, i, x),
+            SQLiteReturn::SQLiteText(ref x) =>
+                sql_call!(bind_text)(stmt, i, x.as_ptr() as *const i8, x.len() as i32, None),
+            SQLiteReturn::SQLiteBlob(ref x) =>
+                sql_call!(bind_blob)(stmt, i, x.as_ptr() as *const c_void, x.len() as i32, None)
+        };
+    }
+    let result = match sql_call!(step)(stmt) {
+        SQLITE_DONE | SQLITE_ROW => Ok(()),
+        _ => Err(errmsg(db))
+    };
+    sql_call!(finalize)(stmt);
+    result
+}
+
+/// The message for the latest error on a connection
+unsafe fn errmsg(db: *mut sqlite3) -> String {
+    CStr::from_ptr(sql_call!(errmsg)(db)).to_string_lossy().into_owned()
+}
+
 pub unsafe extern "C" fn string_destructor(cptr: *mut c_void) {
     let mut strings = SQLITE_STRINGS_IN_FLIGHT.lock().unwrap();
     strings.remove(&(cptr as usize));
 }
 pub unsafe extern "C" fn blob_destructor(cptr: *mut c_void) {
-    let mut strings = SQLITE_STRINGS_IN_FLIGHT.lock().unwrap();
-    strings.remove(&(cptr as usize));
+    let mut blobs = SQLITE_BLOBS_IN_FLIGHT.lock().unwrap();
+    blobs.remove(&(cptr as usize));
 }
 
 lazy_static! {
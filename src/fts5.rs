@@ -0,0 +1,207 @@
+//! A regex tokenizer for full-text search
+//!
+//! FTS5's own tokenizers split text on whitespace and punctuation, which
+//! breaks identifiers like `parse_args` or `Vec::new` apart. `extras_regex`
+//! instead makes a token of every match of a regex:
+//!
+//! ```sql
+//! CREATE VIRTUAL TABLE docs USING fts5(body, tokenize="extras_regex '[A-Za-z0-9_]+'");
+//! SELECT * FROM docs WHERE docs MATCH 'parse_args';
+//! ```
+//!
+//! The pattern may be followed by options, as pairs of a name and a value:
+//!
+//! | Option      | Default | Meaning                                            |
+//! |-------------|---------|----------------------------------------------------|
+//! | `lowercase` | `1`     | Lowercase tokens, so searches ignore case          |
+//! | `fold`      | `0`     | Remove diacritics, so `café` matches `cafe`, and   |
+//! |             |         | turn compatibility characters like `ﬁ` into `fi`   |
+//! | `flags`     | `''`    | Flags for the pattern, as for the regex functions  |
+//!
+//! Empty matches aren't tokens. Queries are tokenized the same way, so a
+//! query term that the pattern splits apart becomes a phrase.
+use regexp;
+use regexp::engine::Pattern;
+use sqlite3_raw::*;
+use std::borrow::Cow;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::slice;
+use std::str;
+use std::sync::Arc;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+// sqlite3ext.h doesn't include fts5.h, so these mirror the parts of it we use
+
+/// A tokenizer instance, opaque to SQLite
+#[allow(non_camel_case_types)]
+pub enum Fts5Tokenizer {}
+
+/// The callback SQLite passes to `xTokenize` to receive each token
+#[allow(non_camel_case_types)]
+type xToken = Option<unsafe extern "C" fn(*mut c_void, c_int, *const c_char, c_int, c_int, c_int) -> c_int>;
+
+#[repr(C)]
+#[allow(non_camel_case_types, non_snake_case)]
+pub struct fts5_tokenizer {
+    xCreate: Option<unsafe extern "C" fn(*mut c_void, *mut *const c_char, c_int, *mut *mut Fts5Tokenizer) -> c_int>,
+    xDelete: Option<unsafe extern "C" fn(*mut Fts5Tokenizer)>,
+    xTokenize: Option<unsafe extern "C" fn(*mut Fts5Tokenizer, *mut c_void, c_int, *const c_char, c_int, xToken) -> c_int>
+}
+
+#[repr(C)]
+#[allow(non_camel_case_types, non_snake_case)]
+pub struct fts5_api {
+    iVersion: c_int,
+    xCreateTokenizer: Option<unsafe extern "C" fn(*mut fts5_api, *const c_char, *mut c_void, *mut fts5_tokenizer, Option<unsafe extern "C" fn(*mut c_void)>) -> c_int>,
+    // xFindTokenizer and xCreateFunction follow, but we don't call them
+}
+
+/// Register the `extras_regex` tokenizer
+///
+/// Does nothing if SQLite was built without FTS5.
+///
+/// # Safety
+/// `db` must be an open connection and the extension API must be initialized.
+pub unsafe fn register(db: *mut sqlite3) -> i32 {
+    let api = match fts5_api_from_db(db) {
+        Some(api) => api,
+        None => return SQLITE_OK
+    };
+    let mut tokenizer = fts5_tokenizer {
+        xCreate: Some(tokenizer_create),
+        xDelete: Some(tokenizer_delete),
+        xTokenize: Some(tokenizer_tokenize)
+    };
+    // FTS5 copies the tokenizer, so it needn't outlive this call
+    match (*api).xCreateTokenizer {
+        Some(create) => create(api, const_cstr!("extras_regex").as_ptr(), ptr::null_mut(), &mut tokenizer, None),
+        None => SQLITE_OK
+    }
+}
+
+/// Get the FTS5 API with `SELECT fts5(?1)`, passing it a pointer to fill in
+unsafe fn fts5_api_from_db(db: *mut sqlite3) -> Option<*mut fts5_api> {
+    let mut api: *mut fts5_api = ptr::null_mut();
+    let mut stmt = ptr::null_mut();
+    let sql = const_cstr!("SELECT fts5(?1)");
+    if sql_call!(prepare_v2)(db, sql.as_ptr(), -1, &mut stmt, ptr::null_mut()) != SQLITE_OK {
+        // There is no fts5() function without FTS5
+        return None;
+    }
+    sql_call!(bind_pointer)(stmt, 1, &mut api as *mut *mut fts5_api as *mut c_void,
+        const_cstr!("fts5_api_ptr").as_ptr(), None);
+    sql_call!(step)(stmt);
+    sql_call!(finalize)(stmt);
+    if api.is_null() { None } else { Some(api) }
+}
+
+struct RegexTokenizer {
+    pattern: Arc<Pattern>,
+    lowercase: bool,
+    fold: bool
+}
+impl RegexTokenizer {
+    /// Parse the arguments from the `tokenize` option
+    fn new(args: &[&str]) -> Result<RegexTokenizer, String> {
+        let (pattern, options) = match args.split_first() {
+            Some(split) => split,
+            None => return Err("extras_regex needs a pattern".into())
+        };
+        if options.len() % 2 != 0 {
+            return Err("extras_regex options should be pairs of a name and a value".into());
+        }
+        let mut lowercase = true;
+        let mut fold = false;
+        let mut flags = "";
+        for option in options.chunks(2) {
+            match option[0] {
+                "lowercase" => lowercase = parse_bool(option[0], option[1])?,
+                "fold" => fold = parse_bool(option[0], option[1])?,
+                "flags" => flags = option[1],
+                name => return Err(format!("extras_regex has no option {}", name))
+            }
+        }
+        Ok(RegexTokenizer {
+            pattern: regexp::compile("extras_regex", pattern, flags)?,
+            lowercase,
+            fold
+        })
+    }
+
+    /// The text to index for one match
+    fn normalize<'t>(&self, token: &'t str) -> Cow<'t, str> {
+        let mut token = Cow::Borrowed(token);
+        if self.fold {
+            token = Cow::Owned(token.nfkd().filter(|&c| !is_combining_mark(c)).collect());
+        }
+        if self.lowercase {
+            token = Cow::Owned(token.to_lowercase());
+        }
+        token
+    }
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "0" => Ok(false),
+        "1" => Ok(true),
+        _ => Err(format!("extras_regex option {} should be 0 or 1", name))
+    }
+}
+
+unsafe extern "C" fn tokenizer_create(_: *mut c_void, argv: *mut *const c_char, argc: c_int, out: *mut *mut Fts5Tokenizer) -> c_int {
+    let mut args = vec![];
+    for i in 0..argc.max(0) as usize {
+        match CStr::from_ptr(*argv.add(i)).to_str() {
+            Ok(arg) => args.push(arg),
+            Err(_) => return SQLITE_ERROR
+        }
+    }
+    // FTS5 only reports that the constructor failed, so the message is lost
+    match RegexTokenizer::new(&args) {
+        Ok(tokenizer) => {
+            *out = Box::into_raw(Box::new(tokenizer)) as *mut Fts5Tokenizer;
+            SQLITE_OK
+        },
+        Err(_) => SQLITE_ERROR
+    }
+}
+
+unsafe extern "C" fn tokenizer_delete(tokenizer: *mut Fts5Tokenizer) {
+    drop(Box::from_raw(tokenizer as *mut RegexTokenizer));
+}
+
+unsafe extern "C" fn tokenizer_tokenize(tokenizer: *mut Fts5Tokenizer, ctx: *mut c_void, _flags: c_int,
+        text: *const c_char, len: c_int, x_token: xToken) -> c_int {
+    let tokenizer = &*(tokenizer as *const RegexTokenizer);
+    let x_token = match x_token {
+        Some(x_token) => x_token,
+        None => return SQLITE_ERROR
+    };
+    if len <= 0 {
+        return SQLITE_OK;
+    }
+    let text = match str::from_utf8(slice::from_raw_parts(text as *const u8, len as usize)) {
+        Ok(text) => text,
+        Err(_) => return SQLITE_ERROR
+    };
+    let spans = match tokenizer.pattern.find_all(text) {
+        Ok(spans) => spans,
+        Err(_) => return SQLITE_ERROR
+    };
+    for (start, end) in spans {
+        if start == end {
+            continue;
+        }
+        // The offsets are into the original text, so highlight() still works
+        let token = tokenizer.normalize(&text[start..end]);
+        let rc = x_token(ctx, 0, token.as_ptr() as *const c_char, token.len() as c_int, start as c_int, end as c_int);
+        if rc != SQLITE_OK {
+            return rc;
+        }
+    }
+    SQLITE_OK
+}
@@ -1,11 +1,94 @@
 //! SQLite3 extras: powerups for the world's favorite database
 //! String Operations
 //! =================
-//! 
+//! See `regexp` for more detail, including the optional flags argument
+//! every function takes last
+//!
+//! - `match(pattern, subject)`: Whether the pattern matches the whole subject
+//! - `search(pattern, subject)`: Whether the pattern matches anywhere in the subject
+//! - `sub(pattern, format, subject)`: Replace every match with the format string
+//! - `regexp(pattern, subject)`: The same as `search()`, so that `subject REGEXP pattern` works
+//! - `regex_capture(pattern, subject, group)`: One group (by number or name) of the first match
+//! - `regex_find(pattern, subject)`: The text of the first match
+//! - `regex_find_pos(pattern, subject[, unit])`: Where the first match starts, like `instr()`
+//! - `regex_captures_json(pattern, subject)`: The named groups of the first match as JSON
+//! - `regex_split_part(pattern, subject, n)`: The nth piece of the subject between matches
+//! - `regex_match_bytes(pattern, blob)`, `regex_find_bytes(pattern, blob)` and
+//!   `regex_replace_bytes(pattern, format, blob)`: Like `match()`, `regex_find()`
+//!   and `sub()` but on raw bytes, returning BLOBs
+//! - `regex_any(subject, pattern1, pattern2, ...)`: Whether any of the patterns match
+//! - `regex_which(subject, pattern1, pattern2, ...)`: Which patterns match, as JSON
+//! - `regex_classify(subject, patterns_table[, flags])`: Which patterns from a
+//!   table match, as JSON
+//! - `regex_cache_config(setting[, value])`: Read or change the `capacity`,
+//!   `size_limit`, `dfa_size_limit` or `backtrack_limit` of the shared cache
+//!   of compiled patterns
+//! - `regex_cache_stats()`: The size of the shared pattern cache and how often
+//!   it has been used, as JSON
+//!
+//! Vectors
+//! =======
+//! See `vector` for the BLOB format
+//!
+//! - `vec_f32(x1, x2, ...)`, `vec_f64(x1, x2, ...)`: A vector of the numbers,
+//!   or given a vector, the same vector with another element type
+//! - `vec_type(v)`: The element type, one of `f32`, `f64`, `i8`, `bit` or `q8`
+//! - `vec_dims(v)`: The number of dimensions
+//! - `vec_import(blob, type)`: Read a headerless vector from `extras.cpp`
+//! - `vzero(n[, type])`, `vone(n[, type])`: A vector of n zeros or ones
+//! - `vread(text[, type])`: Read a vector from whitespace separated numbers
+//! - `vshow(v)`: Write a vector as space separated numbers
+//! - `vec_from_json(json[, type])`, `vec_to_json(v)`: Convert a vector to
+//!   or from a JSON array
+//! - `add(a, b)`, `subtract(a, b)`, `mult(a, b)`, `div(a, b)`, `pow(a, b)`:
+//!   Element-wise arithmetic on any mix of vectors and numbers
+//! - `vmin(a, b)`, `vmax(a, b)`: The element-wise smaller or larger value
+//! - `vabs(a)`: The absolute value of each element
+//! - `vclip(a, lo, hi)`: Each element limited to between lo and hi
+//! - `vec_dot(a, b)`, `vec_cosine_similarity(a, b)`,
+//!   `vec_cosine_distance(a, b)`: The dot product, the cosine similarity
+//!   and 1 minus it
+//! - `vec_l2(a, b)`, `vec_l2_squared(a, b)`, `vec_l1(a, b)`,
+//!   `vec_linf(a, b)`: The Euclidean, squared Euclidean, Manhattan and
+//!   Chebyshev distances
+//! - `vec_hamming(a, b)`, `vec_jaccard(a, b)`: The number of elements that
+//!   differ, and the Jaccard distance, mostly for bit vectors
+//! - `vec_slice(v, start, end)`, `vec_concat(a, b, ...)`: Part of a
+//!   vector, counting from 1, or vectors joined end to end
+//! - `vec_sort(v)`: The elements from smallest to largest
+//! - `vec_norm(v[, p])`, `vec_normalize(v)`: The p-norm, or the Euclidean
+//!   length, and the vector scaled to length 1
+//! - `vec_softmax(v)`: exp(xᵢ) / Σ exp(xⱼ) for each element
+//! - `vec_argmax(v)`, `vec_argmin(v)`, `vec_topk_indices(v, k)`: The
+//!   position of the largest or smallest element, and a JSON array of the
+//!   positions of the k largest
+//! - `vec_quantize_i8(v[, min, max])`: A q8 vector, storing each element in
+//!   one byte as one of 256 values from min to max
+//! - `vec_quantize_binary(v)`: A bit vector of which elements are positive
+//! - `vec_dequantize(v)`: The f32 values a quantized vector stands for
+//! - `mat_from_json(json[, type])`, `mat_to_json(m)`: Convert a matrix to or
+//!   from a JSON array of rows
+//! - `mat_identity(n[, type])`: The n by n identity matrix
+//! - `mat_transpose(m)`, `mat_inverse(m)`, `mat_det(m)`: The transpose,
+//!   inverse (NULL if there isn't one) and determinant
+//! - `mat_mul(a, b)`, `mat_vec_mul(m, v)`: The product of two matrices, or
+//!   of a matrix and a vector
+//! - `vec_nearest_centroid(v, centroids)`: The row of the matrix nearest
+//!   the vector, counting from 1, like a cluster from `vec_kmeans()`
+//!
+//! Aggregate and window functions, which skip NULLs and give NULL for no rows:
+//!
+//! - `vec_sum(v)`, `vec_avg(v)`: The element-wise sum or mean
+//! - `vec_min(v)`, `vec_max(v)`: The element-wise minimum or maximum
+//! - `vec_collect(x[, type])`: A vector of the numbers in the group, in order,
+//!   where a NULL is an error
+//! - `vec_kmeans(v, k[, iterations, seed])`: A matrix of the centroids of k
+//!   clusters of the vectors, found with k-means
 //!
 //! Trigonometrics and Exponentials
 //! ========
-//! See Rust's builtin f64 for more detail
+//! See Rust's builtin f64 for more detail. Each of these also works
+//! element-wise on a vector, giving a vector of the same element type.
 //! 
 //! - `sin()`
 //! - `asin(x)`
@@ -31,20 +114,55 @@
 //! - `sqrt(x)`
 //! - `cbrt(x)`
 //!
+//! Table-valued Functions
+//! ======================
+//! - `range(start, stop, step)`: Integers from start up to (not including) stop
+//! - `random_series(count, distribution, params..., seed)`: Reproducible
+//!   pseudo-random samples. See `virtual_table::random_series` for the
+//!   distributions.
+//! - `regex_matches(pattern, subject[, flags])`: Every match of a regex, with its
+//!   position and groups
+//! - `regex_split(pattern, subject[, flags])`: The pieces of the subject between
+//!   matches of a regex
+//! - `vec_each(v)`: The elements of a vector, with their positions
+//! - `vec_knn(table, column, query, k[, metric])`: The k rows of a table with
+//!   the vectors nearest the query, with their distances. See
+//!   `virtual_table::vec_knn` for the metrics.
+//!
+//! Vector Indexes
+//! ==============
+//! - `vec_hnsw(dim=N[, metric=l2, m=16, ef_construction=200, ef_search=40])`:
+//!   A virtual table of vectors indexed for approximate nearest neighbour
+//!   search, with `WHERE embedding MATCH :query AND k = 10`. See
+//!   `virtual_table::vec_hnsw`.
+//!
+//! Full-text Search
+//! ================
+//! - `extras_regex`: An FTS5 tokenizer which makes a token of every match of a
+//!   regex. See `fts5` for its options.
+//!
 mod sqlite3_raw;
 #[macro_use] mod macros;
 pub mod virtual_table;
+pub mod aggregate;
 pub mod dynamics;
+pub mod fts5;
+pub mod random;
+pub mod regexp;
+pub mod vector;
 
 #[macro_use] extern crate const_cstr;
 #[macro_use] extern crate lazy_static;
+extern crate fancy_regex;
 extern crate libc;
 extern crate nodrop;
+extern crate regex;
+extern crate serde_json;
 extern crate smallvec;
+extern crate unicode_normalization;
 
 use std::ptr;
 use std::os::raw::*;
-use std::slice;
 use sqlite3_raw::*;
 use dynamics::*;
 
@@ -81,6 +199,8 @@ pub unsafe extern "C" fn sqlite3_extension_init(db: *mut sqlite3, err: *mut *mut
     create_unop!(db, to_radians, f64::to_radians);
     create_unop!(db, sqrt, f64::sqrt);
     create_unop!(db, cbrt, f64::cbrt);
+    or_die!(regexp::register(db));
+    or_die!(vector::register(db));
     
 //    
 //    def_plain(const_cstr!("is_finite"), sql_is_finite);
@@ -88,12 +208,22 @@ pub unsafe extern "C" fn sqlite3_extension_init(db: *mut sqlite3, err: *mut *mut
 //    def_plain(const_cstr!("is_normal"), sql_is_infinite);
     
     
-    if sqlite3_libversion_number() < 3008012 {
-        *err = sqlite3_mprintf(const_cstr!("range() requires SQLite 3.8.12 or later").as_ptr());
+    if sql_call!(libversion_number)() < 3008012 {
+        *err = sql_call!(mprintf)(const_cstr!("range() requires SQLite 3.8.12 or later").as_ptr());
         return SQLITE_ERROR;
-    } else {
-        return assert_ok!(sql_call!(create_module)(db, const_cstr!("range").as_ptr(), &virtual_table::range::RANGE_MODULE, ptr::null_mut()));
     }
+    or_die!(sql_call!(create_module)(db, const_cstr!("range").as_ptr(), &virtual_table::range::RANGE_MODULE, ptr::null_mut()));
+    or_die!(sql_call!(create_module)(db, const_cstr!("random_series").as_ptr(), &virtual_table::random_series::RANDOM_SERIES_MODULE, ptr::null_mut()));
+    or_die!(sql_call!(create_module)(db, const_cstr!("regex_matches").as_ptr(), &virtual_table::regex_matches::REGEX_MATCHES_MODULE, ptr::null_mut()));
+    or_die!(sql_call!(create_module)(db, const_cstr!("regex_split").as_ptr(), &virtual_table::regex_split::REGEX_SPLIT_MODULE, ptr::null_mut()));
+    or_die!(sql_call!(create_module)(db, const_cstr!("vec_each").as_ptr(), &virtual_table::vec_each::VEC_EACH_MODULE, ptr::null_mut()));
+    or_die!(sql_call!(create_module)(db, const_cstr!("vec_knn").as_ptr(), &virtual_table::vec_knn::VEC_KNN_MODULE, ptr::null_mut()));
+    or_die!(sql_call!(create_module)(db, const_cstr!("vec_hnsw").as_ptr(), &virtual_table::vec_hnsw::VEC_HNSW_MODULE, ptr::null_mut()));
+    // Finding the FTS5 API needs pointer passing, from SQLite 3.20
+    if sql_call!(libversion_number)() >= 3020000 {
+        or_die!(fts5::register(db));
+    }
+    SQLITE_OK
 }
 
 //
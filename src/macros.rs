@@ -34,22 +34,54 @@ macro_rules! sql_call {
     } }
 }
 
+/// Register a math function of one argument, like `sin`
+///
+/// It works element-wise on vectors too, see `vector::arith::unop()`.
 macro_rules! create_unop {
-    ($db: expr, $name:ident, $f:expr) => {
-        extern "C" fn $name(ctx: *mut sqlite3_context, argc: c_int, argv: *mut *mut sqlite3_value) {
-            let args = unsafe{ slice::from_raw_parts(argv, argc as usize) };
-            let arg = unsafe{SQLiteValue::from_raw_unchecked(args[0])};
-            let res: SQLiteReturn = $f(arg.into()).into();
-            res.push_to(ctx);
+    ($db: expr, $name:ident, $f:expr) => { {
+        fn $name(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+            ::vector::arith::unop(stringify!($name), args[0], $f)
+        }
+        create_function!($db, stringify!($name), 1, $name)
+    } }
+}
+
+/// Register an `aggregate::Aggregate` as an SQL aggregate (and window)
+/// function with `$argc` arguments
+macro_rules! create_aggregate {
+    ($db: expr, $name: expr, $argc: expr, $A: ty) => {
+        ::aggregate::create::<$A>($db, const_cstr!($name).as_ptr(), $argc)
+    }
+}
+
+/// Register a Rust function as an SQL function with `$argc` arguments
+///
+/// `$f` must be a path to a function taking the call's context and its
+/// arguments, returning either a value or an error message for SQLite.
+/// Use an `$argc` of -1 to accept any number of arguments.
+///
+/// Functions are deterministic unless other `$flags` are given, such as
+/// plain `SQLITE_UTF8` for a function with side effects.
+macro_rules! create_function {
+    ($db: expr, $name: expr, $argc: expr, $f: path) => {
+        create_function!($db, $name, $argc, $f, SQLITE_UTF8 | SQLITE_DETERMINISTIC)
+    };
+    ($db: expr, $name: expr, $argc: expr, $f: path, $flags: expr) => { {
+        extern "C" fn shim(ctx: *mut sqlite3_context, argc: c_int, argv: *mut *mut sqlite3_value) {
+            let args = unsafe{ SQLiteValue::from_raw_slice(argv, argc) };
+            match $f(ctx, args) {
+                Ok(res) => res.push_to(ctx),
+                Err(message) => push_error(ctx, &message)
+            }
         }
         sql_call!(create_function)(
             $db,
-            const_cstr!(stringify!($name)).as_ptr(),
-            1,
-            (SQLITE_UTF8 | SQLITE_DETERMINISTIC) as i32,
+            const_cstr!($name).as_ptr(),
+            $argc,
+            ($flags) as i32,
             ptr::null_mut(),
-            Some($name),
+            Some(shim),
             None,
-            None);
-    }
-}
\ No newline at end of file
+            None)
+    } }
+}
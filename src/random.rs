@@ -0,0 +1,309 @@
+//! Seeded pseudo-random numbers
+//!
+//! SQLite's `random()` can't be seeded, so anything that needs reproducible
+//! randomness uses this instead. Generators are keyed by a seed and a row
+//! number, so the value of any one row never depends on which rows were read
+//! before it, or in what order.
+//!
+//! None of this is suitable for cryptography.
+use std::f64::consts::PI;
+
+/// SplitMix64, a tiny, fast generator with a 64-bit state
+///
+/// See [Steele, Lea and Flood, "Fast Splittable Pseudorandom Number
+/// Generators"](https://doi.org/10.1145/2714064.2660195).
+pub struct SplitMix64(u64);
+impl SplitMix64 {
+    pub fn new(seed: i64) -> SplitMix64 {
+        SplitMix64(seed as u64)
+    }
+
+    /// Create an independent generator for one row of a seeded sequence
+    pub fn for_row(seed: i64, rowid: i64) -> SplitMix64 {
+        let key = SplitMix64::new(seed).next_u64();
+        SplitMix64(mix(key ^ mix(rowid as u64)))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix(self.0)
+    }
+
+    /// A uniform float in [0, 1) with 53 random bits
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+/// The SplitMix64 output function
+fn mix(x: u64) -> u64 {
+    let x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+/// Probability distributions that can be sampled from a `SplitMix64`
+///
+/// Parameters are checked when the distribution is created, and any
+/// precomputation happens there too, so sampling never fails.
+pub enum Distribution {
+    Uniform { low: f64, high: f64 },
+    Normal { mean: f64, std_dev: f64 },
+    Exponential { rate: f64 },
+    Poisson { lambda: f64 },
+    Binomial { trials: i64, p: f64 },
+    Zipf(Zipf)
+}
+impl Distribution {
+    /// The number of parameters each distribution takes, by name
+    pub fn arity(name: &str) -> Option<usize> {
+        match name {
+            "uniform" | "normal" | "binomial" | "zipf" => Some(2),
+            "exponential" | "poisson" => Some(1),
+            _ => None
+        }
+    }
+
+    /// Create a distribution from its name and parameters
+    pub fn new(name: &str, params: &[f64]) -> Result<Distribution, String> {
+        match Distribution::arity(name) {
+            None => return Err(format!(
+                "unknown distribution '{}': expected one of uniform, normal, \
+                exponential, poisson, binomial or zipf", name)),
+            Some(n) if n != params.len() => return Err(format!(
+                "the {} distribution takes {} parameters but {} were given",
+                name, n, params.len())),
+            _ => ()
+        }
+        if params.iter().any(|x| !x.is_finite()) {
+            return Err(format!("the {} distribution's parameters must be finite", name));
+        }
+        match name {
+            "uniform" if params[0] <= params[1] =>
+                Ok(Distribution::Uniform { low: params[0], high: params[1] }),
+            "uniform" => Err("uniform(low, high) requires low <= high".into()),
+            "normal" if params[1] >= 0.0 =>
+                Ok(Distribution::Normal { mean: params[0], std_dev: params[1] }),
+            "normal" => Err("normal(mean, std_dev) requires std_dev >= 0".into()),
+            "exponential" if params[0] > 0.0 =>
+                Ok(Distribution::Exponential { rate: params[0] }),
+            "exponential" => Err("exponential(rate) requires rate > 0".into()),
+            "poisson" if params[0] >= 0.0 =>
+                Ok(Distribution::Poisson { lambda: params[0] }),
+            "poisson" => Err("poisson(lambda) requires lambda >= 0".into()),
+            "binomial" if params[0] >= 0.0 && params[0].fract() == 0.0
+                && params[1] >= 0.0 && params[1] <= 1.0 =>
+                Ok(Distribution::Binomial { trials: params[0] as i64, p: params[1] }),
+            "binomial" => Err("binomial(n, p) requires a whole number n >= 0 and 0 <= p <= 1".into()),
+            "zipf" if params[0] >= 1.0 && params[0].fract() == 0.0 && params[1] > 0.0 =>
+                Ok(Distribution::Zipf(Zipf::new(params[0] as i64, params[1]))),
+            "zipf" => Err("zipf(n, s) requires a whole number n >= 1 and s > 0".into()),
+            _ => unreachable!()
+        }
+    }
+
+    /// Whether samples are whole numbers (and should be returned as integers)
+    pub fn is_discrete(&self) -> bool {
+        matches!(*self,
+            Distribution::Poisson{..} | Distribution::Binomial{..} | Distribution::Zipf(_))
+    }
+
+    pub fn sample(&self, rng: &mut SplitMix64) -> f64 {
+        match *self {
+            Distribution::Uniform { low, high } =>
+                low + (high - low) * rng.next_f64(),
+            Distribution::Normal { mean, std_dev } =>
+                mean + std_dev * standard_normal(rng),
+            Distribution::Exponential { rate } =>
+                -(1.0 - rng.next_f64()).ln() / rate,
+            Distribution::Poisson { lambda } =>
+                poisson(rng, lambda) as f64,
+            Distribution::Binomial { trials, p } =>
+                binomial(rng, trials, p) as f64,
+            Distribution::Zipf(ref zipf) =>
+                zipf.sample(rng) as f64
+        }
+    }
+}
+
+/// Box-Muller transform, using only the cosine half
+fn standard_normal(rng: &mut SplitMix64) -> f64 {
+    let u1 = 1.0 - rng.next_f64(); // (0, 1], so the log is finite
+    let u2 = rng.next_f64();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+/// Poisson samples, by multiplication for small means and otherwise by
+/// Hörmann's transformed rejection with squeeze (PTRS)
+fn poisson(rng: &mut SplitMix64, lambda: f64) -> i64 {
+    if lambda < 10.0 {
+        let limit = (-lambda).exp();
+        let mut k = 0;
+        let mut product = rng.next_f64();
+        while product > limit {
+            k += 1;
+            product *= rng.next_f64();
+        }
+        return k;
+    }
+    let slam = lambda.sqrt();
+    let log_lambda = lambda.ln();
+    let b = 0.931 + 2.53 * slam;
+    let a = -0.059 + 0.02483 * b;
+    let inv_alpha = 1.1239 + 1.1328 / (b - 3.4);
+    let v_r = 0.9277 - 3.6224 / (b - 2.0);
+    loop {
+        let u = rng.next_f64() - 0.5;
+        let v = rng.next_f64();
+        let us = 0.5 - u.abs();
+        let k = ((2.0 * a / us + b) * u + lambda + 0.43).floor();
+        if us >= 0.07 && v <= v_r {
+            return k as i64;
+        }
+        if k < 0.0 || (us < 0.013 && v > us) {
+            continue;
+        }
+        if (v * inv_alpha / (a / (us * us) + b)).ln()
+            <= -lambda + k * log_lambda - ln_gamma(k + 1.0) {
+            return k as i64;
+        }
+    }
+}
+
+/// Binomial samples, by inversion for small means and otherwise by
+/// Hörmann's transformed rejection (BTRS)
+fn binomial(rng: &mut SplitMix64, trials: i64, p: f64) -> i64 {
+    if p > 0.5 {
+        return trials - binomial(rng, trials, 1.0 - p);
+    }
+    let n = trials as f64;
+    let q = 1.0 - p;
+    if n * p < 10.0 {
+        // Count the geometric gaps between successes
+        if p == 0.0 {
+            return 0;
+        }
+        let log_q = q.ln();
+        let mut successes = 0;
+        let mut position = 0.0;
+        loop {
+            position += ((1.0 - rng.next_f64()).ln() / log_q).floor() + 1.0;
+            if position > n {
+                return successes;
+            }
+            successes += 1;
+        }
+    }
+    let spq = (n * p * q).sqrt();
+    let b = 1.15 + 2.53 * spq;
+    let a = -0.0873 + 0.0248 * b + 0.01 * p;
+    let c = n * p + 0.5;
+    let alpha = (2.83 + 5.1 / b) * spq;
+    let v_r = 0.92 - 4.2 / b;
+    let log_odds = (p / q).ln();
+    let m = ((n + 1.0) * p).floor();
+    let h = ln_gamma(m + 1.0) + ln_gamma(n - m + 1.0);
+    loop {
+        let u = rng.next_f64() - 0.5;
+        let v = rng.next_f64();
+        let us = 0.5 - u.abs();
+        let k = ((2.0 * a / us + b) * u + c).floor();
+        if k < 0.0 || k > n {
+            continue;
+        }
+        if us >= 0.07 && v <= v_r {
+            return k as i64;
+        }
+        let v = (v * alpha / (a / (us * us) + b)).ln();
+        if v <= h - ln_gamma(k + 1.0) - ln_gamma(n - k + 1.0) + (k - m) * log_odds {
+            return k as i64;
+        }
+    }
+}
+
+/// Zipf samples over 1..=n with exponent s, using Hörmann and Derflinger's
+/// rejection-inversion, so it takes constant time and memory for any n.
+pub struct Zipf {
+    n: f64,
+    exponent: f64,
+    h_integral_x1: f64,
+    h_integral_n: f64,
+    squeeze: f64
+}
+impl Zipf {
+    fn new(n: i64, exponent: f64) -> Zipf {
+        let mut zipf = Zipf {
+            n: n as f64,
+            exponent,
+            h_integral_x1: 0.0,
+            h_integral_n: 0.0,
+            squeeze: 0.0
+        };
+        zipf.h_integral_x1 = zipf.h_integral(1.5) - 1.0;
+        zipf.h_integral_n = zipf.h_integral(zipf.n + 0.5);
+        zipf.squeeze = 2.0 - zipf.h_integral_inverse(zipf.h_integral(2.5) - zipf.h(2.0));
+        zipf
+    }
+
+    fn sample(&self, rng: &mut SplitMix64) -> i64 {
+        loop {
+            let u = self.h_integral_n + rng.next_f64() * (self.h_integral_x1 - self.h_integral_n);
+            let x = self.h_integral_inverse(u);
+            let k = (x + 0.5).floor().max(1.0).min(self.n);
+            if k - x <= self.squeeze || u >= self.h_integral(k + 0.5) - self.h(k) {
+                return k as i64;
+            }
+        }
+    }
+
+    fn h(&self, x: f64) -> f64 {
+        (-self.exponent * x.ln()).exp()
+    }
+
+    fn h_integral(&self, x: f64) -> f64 {
+        let log_x = x.ln();
+        helper2((1.0 - self.exponent) * log_x) * log_x
+    }
+
+    fn h_integral_inverse(&self, x: f64) -> f64 {
+        let t = (x * (1.0 - self.exponent)).max(-1.0);
+        (helper1(t) * x).exp()
+    }
+}
+
+/// `ln(1 + x) / x`, accurate near 0
+fn helper1(x: f64) -> f64 {
+    if x.abs() > 1e-8 { x.ln_1p() / x }
+    else { 1.0 - x * (0.5 - x * (1.0 / 3.0 - 0.25 * x)) }
+}
+
+/// `(exp(x) - 1) / x`, accurate near 0
+fn helper2(x: f64) -> f64 {
+    if x.abs() > 1e-8 { x.exp_m1() / x }
+    else { 1.0 + x * 0.5 * (1.0 + x / 3.0 * (1.0 + 0.25 * x)) }
+}
+
+/// The log of the gamma function, by the Lanczos approximation (g = 7)
+pub fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7
+    ];
+    if x < 0.5 {
+        // Reflection formula
+        (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x)
+    } else {
+        let x = x - 1.0;
+        let t = x + 7.5;
+        let sum = COEFFICIENTS[1..].iter().enumerate()
+            .fold(COEFFICIENTS[0], |acc, (i, c)| acc + c / (x + i as f64 + 1.0));
+        0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+    }
+}
@@ -0,0 +1,108 @@
+//! Regular expressions over BLOBs
+//!
+//! These work on raw bytes, so the subject doesn't have to be UTF-8 and the
+//! results are BLOBs. Text subjects are searched as their UTF-8 bytes.
+//! Unicode is off unless the `u` flag is given, so `.` matches any one byte
+//! and `\xNN` matches the byte `NN`. Patterns themselves must be UTF-8, so
+//! other bytes have to be written that way:
+//!
+//! ```sql
+//! SELECT regex_find_bytes('\x7eE[\x00-\xff]{2}', packet) FROM captures;
+//! ```
+use sqlite3_raw::*;
+use dynamics::*;
+use regex::bytes::Regex;
+use regexp::{cache, Template};
+use std::os::raw::c_void;
+use std::sync::Arc;
+
+/// Read the arguments of a function as bytes, or None if any are NULL
+fn blob_args(args: &[SQLiteValue]) -> Option<Vec<Vec<u8>>> {
+    args.iter()
+        .map(|&arg| if arg.is_null() { None }
+            else { Some({let x: Option<Vec<u8>> = arg.into(); x}.unwrap_or_default()) })
+        .collect()
+}
+
+/// A compiled pattern kept as SQLite auxiliary data, with its flags
+struct Compiled {
+    flags: String,
+    regex: Arc<Regex>
+}
+
+/// Run `f` with the compiled pattern from argument 0, like `with_regex()`
+fn with_bytes_regex<T, F>(ctx: *mut sqlite3_context, function: &str, pattern: &[u8], flags: &[u8], f: F)
+    -> Result<T, String>
+    where F: FnOnce(&Regex) -> T
+{
+    // Replacing bad bytes with U+FFFD would quietly change what matches
+    let pattern = std::str::from_utf8(pattern).map_err(|_| format!("{}(): the pattern isn't UTF-8; \
+        write the bytes to match as escapes like \\xFF instead", function))?;
+    let flags = String::from_utf8_lossy(flags);
+    let cached = unsafe { sql_call!(get_auxdata)(ctx, 0) as *const Compiled };
+    match unsafe { cached.as_ref() } {
+        Some(cached) if cached.flags == flags => return Ok(f(&cached.regex)),
+        _ => ()
+    }
+    let regex = cache::get_bytes(pattern, &flags).map_err(|e| format!("{}(): {}", function, e))?;
+    let compiled = Compiled { flags: flags.into_owned(), regex };
+    let result = f(&compiled.regex);
+    unsafe {
+        sql_call!(set_auxdata)(ctx, 0, Box::into_raw(Box::new(compiled)) as *mut c_void, Some(drop_compiled));
+    }
+    Ok(result)
+}
+
+unsafe extern "C" fn drop_compiled(compiled: *mut c_void) {
+    drop(Box::from_raw(compiled as *mut Compiled));
+}
+
+/// `regex_match_bytes(pattern, subject[, flags])`: 1 if the pattern matches
+/// all of the subject's bytes, like `match()`
+pub fn regex_match_bytes(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match blob_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    let flags = args.get(2).map_or(&[][..], |flags| &flags[..]);
+    let mut anchored = br"\A(?:".to_vec();
+    anchored.extend_from_slice(&args[0]);
+    // With the x flag a trailing comment would swallow the closing anchor
+    anchored.extend_from_slice(if flags.contains(&b'x') { b"\n)\\z" } else { br")\z" });
+    with_bytes_regex(ctx, "regex_match_bytes", &anchored, flags,
+        |regex| (regex.is_match(&args[1]) as i64).into())
+}
+
+/// `regex_find_bytes(pattern, subject[, flags])`: The bytes of the first
+/// match as a BLOB, or NULL
+pub fn regex_find_bytes(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match blob_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    let flags = args.get(2).map_or(&[][..], |flags| &flags[..]);
+    with_bytes_regex(ctx, "regex_find_bytes", &args[0], flags,
+        |regex| regex.find(&args[1])
+            .map_or(SQLiteReturn::SQLiteNull, |m| m.as_bytes().to_vec().into()))
+}
+
+/// `regex_replace_bytes(pattern, format, subject[, flags])`: Replace every
+/// match in the subject, returning a BLOB
+///
+/// The format is the same as for `sub()`, and can also use `\xNN` to
+/// insert any byte.
+pub fn regex_replace_bytes(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match blob_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    let flags = args.get(3).map_or(&[][..], |flags| &flags[..]);
+    let template = Template::parse_bytes(&args[1], true);
+    with_bytes_regex(ctx, "regex_replace_bytes", &args[0], flags, |regex| {
+        let subject = &args[2];
+        let mut result = Vec::with_capacity(subject.len());
+        let mut last = 0;
+        for caps in regex.captures_iter(subject) {
+            let whole = caps.get(0).unwrap();
+            result.extend_from_slice(&subject[last..whole.start()]);
+            template.expand(
+                |i| caps.get(i).map(|m| m.as_bytes()),
+                |name| regex.capture_names().position(|n| n == Some(name)),
+                &mut result);
+            last = whole.end();
+        }
+        result.extend_from_slice(&subject[last..]);
+        result.into()
+    })
+}
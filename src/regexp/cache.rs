@@ -0,0 +1,268 @@
+//! A process-wide cache of compiled regular expressions
+//!
+//! Compiling a pattern costs far more than running it on one short row, and
+//! SQLite's auxiliary data only keeps a regex for the life of one statement.
+//! This cache keeps the most recently used regexes for every function and
+//! every connection in the process, so a pattern used by many statements is
+//! compiled once.
+//!
+//! Patterns often come from user input, so every compile is bounded: the
+//! compiled program can't exceed `size_limit` bytes and each regex's lazy DFA
+//! can't use more than `dfa_size_limit` bytes. Together with `capacity`, that
+//! bounds the memory the cache can hold. Patterns using the backtracking
+//! engine are also limited to `backtrack_limit` steps per search.
+use fancy_regex;
+use regex::RegexBuilder;
+use regex::bytes;
+use regexp::engine::Pattern;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// How many compiled regexes to keep by default
+pub const DEFAULT_CAPACITY: usize = 256;
+/// The default limit on the size of one compiled regex, in bytes
+pub const DEFAULT_SIZE_LIMIT: usize = 2 << 20;
+/// The default limit on the lazy DFA's memory for one regex, in bytes
+pub const DEFAULT_DFA_SIZE_LIMIT: usize = 2 << 20;
+/// The default limit on backtracking steps in one search
+pub const DEFAULT_BACKTRACK_LIMIT: usize = 1_000_000;
+
+/// The settings that can be changed with `regex_cache_config()`
+#[derive(Clone, Copy)]
+pub struct Limits {
+    pub capacity: usize,
+    pub size_limit: usize,
+    pub dfa_size_limit: usize,
+    pub backtrack_limit: usize
+}
+impl Limits {
+    /// Whether regexes compiled under either set of limits are the same
+    fn compile_alike(&self, other: &Limits) -> bool {
+        self.size_limit == other.size_limit
+            && self.dfa_size_limit == other.dfa_size_limit
+            && self.backtrack_limit == other.backtrack_limit
+    }
+}
+
+/// Regexes are cached by their pattern and flags together, and whether they
+/// search text or bytes
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    pattern: String,
+    flags: String,
+    bytes: bool
+}
+
+#[derive(Clone)]
+enum Compiled {
+    Text(Arc<Pattern>),
+    Bytes(Arc<bytes::Regex>)
+}
+
+/// A least recently used cache
+///
+/// `recency` orders the keys by when they were last used, so the oldest is
+/// always first and gets evicted when the cache is over capacity.
+struct Cache {
+    limits: Limits,
+    entries: HashMap<Key, (Compiled, u64)>,
+    recency: BTreeMap<u64, Key>,
+    clock: u64,
+    hits: u64,
+    misses: u64
+}
+impl Cache {
+    fn get(&mut self, key: &Key) -> Option<Compiled> {
+        self.clock += 1;
+        let clock = self.clock;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                let key = self.recency.remove(&entry.1).unwrap();
+                self.recency.insert(clock, key);
+                entry.1 = clock;
+                self.hits += 1;
+                Some(entry.0.clone())
+            },
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, key: Key, regex: Compiled) {
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.insert(key.clone(), (regex, self.clock)) {
+            // Another thread compiled the same pattern at the same time
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.clock, key);
+        self.trim();
+    }
+
+    /// Evict the least recently used regexes until the cache fits
+    fn trim(&mut self) {
+        while self.entries.len() > self.limits.capacity {
+            let oldest = *self.recency.keys().next().unwrap();
+            let key = self.recency.remove(&oldest).unwrap();
+            self.entries.remove(&key);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+lazy_static! {
+    static ref REGEX_CACHE: Mutex<Cache> = Mutex::new(Cache {
+        limits: Limits {
+            capacity: DEFAULT_CAPACITY,
+            size_limit: DEFAULT_SIZE_LIMIT,
+            dfa_size_limit: DEFAULT_DFA_SIZE_LIMIT,
+            backtrack_limit: DEFAULT_BACKTRACK_LIMIT
+        },
+        entries: HashMap::new(),
+        recency: BTreeMap::new(),
+        clock: 0,
+        hits: 0,
+        misses: 0
+    });
+}
+
+/// Get the compiled regex for a pattern, compiling it if it isn't cached
+///
+/// The flags are any of `i` (case insensitive), `m` (multi-line), `s` (`.`
+/// matches newlines), `x` (ignore whitespace and allow comments), `u`
+/// (Unicode, which is already on by default) and `b` (use the backtracking
+/// engine, see [`engine`](../engine/index.html)).
+pub fn get(pattern: &str, flags: &str) -> Result<Arc<Pattern>, String> {
+    match get_compiled(pattern, flags, false)? {
+        Compiled::Text(regex) => Ok(regex),
+        Compiled::Bytes(_) => unreachable!()
+    }
+}
+
+/// Get the compiled regex for searching bytes instead of text
+///
+/// The flags are the same as for `get()`, except that `b` isn't supported
+/// and Unicode is off unless the `u` flag is given. So by default `.` matches
+/// any one byte and `\xFF` matches the byte 0xFF.
+pub fn get_bytes(pattern: &str, flags: &str) -> Result<Arc<bytes::Regex>, String> {
+    match get_compiled(pattern, flags, true)? {
+        Compiled::Bytes(regex) => Ok(regex),
+        Compiled::Text(_) => unreachable!()
+    }
+}
+
+fn get_compiled(pattern: &str, flags: &str, bytes: bool) -> Result<Compiled, String> {
+    let key = Key { pattern: pattern.to_string(), flags: flags.to_string(), bytes };
+    let limits = {
+        let mut cache = REGEX_CACHE.lock().unwrap();
+        if let Some(regex) = cache.get(&key) {
+            return Ok(regex);
+        }
+        cache.limits
+    };
+    // Compile without holding the lock, since it can take a while
+    let regex = if bytes { Compiled::Bytes(Arc::new(build_bytes(pattern, flags, &limits)?)) }
+        else { Compiled::Text(Arc::new(build(pattern, flags, &limits)?)) };
+    let mut cache = REGEX_CACHE.lock().unwrap();
+    // Don't keep a regex compiled under limits that have since changed
+    if cache.limits.compile_alike(&limits) {
+        cache.insert(key, regex.clone());
+    }
+    Ok(regex)
+}
+
+/// Split the flags into those both engines understand, and whether to use
+/// the backtracking engine
+pub fn parse_flags(flags: &str) -> Result<(String, bool), String> {
+    let mut inline = String::new();
+    let mut backtracking = false;
+    for flag in flags.chars() {
+        match flag {
+            'i' | 'm' | 's' | 'x' | 'u' => inline.push(flag),
+            'b' => backtracking = true,
+            _ => return Err(format!(
+                "unknown flag '{}', expected some of i, m, s, x, u and b", flag))
+        }
+    }
+    Ok((inline, backtracking))
+}
+
+fn build(pattern: &str, flags: &str, limits: &Limits) -> Result<Pattern, String> {
+    let (inline, backtracking) = parse_flags(flags)?;
+    if backtracking {
+        let pattern = if inline.is_empty() { pattern.to_string() }
+            else { format!("(?{}){}", inline, pattern) };
+        fancy_regex::RegexBuilder::new(&pattern)
+            .delegate_size_limit(limits.size_limit)
+            .delegate_dfa_size_limit(limits.dfa_size_limit)
+            .backtrack_limit(limits.backtrack_limit)
+            .build()
+            .map(Pattern::Backtracking)
+            .map_err(|e| e.to_string())
+    } else {
+        let mut builder = RegexBuilder::new(pattern);
+        builder.size_limit(limits.size_limit)
+            .dfa_size_limit(limits.dfa_size_limit)
+            .case_insensitive(inline.contains('i'))
+            .multi_line(inline.contains('m'))
+            .dot_matches_new_line(inline.contains('s'))
+            .ignore_whitespace(inline.contains('x'));
+        builder.build()
+            .map(Pattern::Linear)
+            .map_err(|e| {
+                let message = e.to_string();
+                if message.contains("look-around") || message.contains("backreferences") {
+                    format!("{}\nUse the 'b' flag to allow this with the backtracking engine.", message)
+                } else {
+                    message
+                }
+            })
+    }
+}
+
+fn build_bytes(pattern: &str, flags: &str, limits: &Limits) -> Result<bytes::Regex, String> {
+    let (inline, backtracking) = parse_flags(flags)?;
+    if backtracking {
+        return Err("the b flag isn't supported for BLOBs".into());
+    }
+    bytes::RegexBuilder::new(pattern)
+        .size_limit(limits.size_limit)
+        .dfa_size_limit(limits.dfa_size_limit)
+        .unicode(inline.contains('u'))
+        .case_insensitive(inline.contains('i'))
+        .multi_line(inline.contains('m'))
+        .dot_matches_new_line(inline.contains('s'))
+        .ignore_whitespace(inline.contains('x'))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// The current settings
+pub fn limits() -> Limits {
+    REGEX_CACHE.lock().unwrap().limits
+}
+
+/// Change the settings
+///
+/// Shrinking the capacity evicts the least recently used regexes. Changing
+/// any other limit empties the cache, so every regex in it obeys the new
+/// limits.
+pub fn set_limits(limits: Limits) {
+    let mut cache = REGEX_CACHE.lock().unwrap();
+    if !cache.limits.compile_alike(&limits) {
+        cache.clear();
+    }
+    cache.limits = limits;
+    cache.trim();
+}
+
+/// The number of cached regexes, hits and misses
+pub fn stats() -> (usize, u64, u64) {
+    let cache = REGEX_CACHE.lock().unwrap();
+    (cache.entries.len(), cache.hits, cache.misses)
+}
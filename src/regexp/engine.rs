@@ -0,0 +1,132 @@
+//! The two regex engines, behind one interface
+//!
+//! Most patterns use the [regex crate](https://docs.rs/regex), which always
+//! runs in linear time but can't support lookaround or backreferences. The
+//! `b` flag compiles the pattern with [fancy-regex](https://docs.rs/fancy-regex)
+//! instead, a backtracking engine which supports both, like Boost did. It
+//! hands as much of the pattern as it can to the regex crate, and gives up
+//! with an error once it has taken too many backtracking steps, so a
+//! catastrophic pattern fails instead of hanging the query.
+use fancy_regex;
+use regex;
+
+/// A compiled pattern for either engine
+pub enum Pattern {
+    Linear(regex::Regex),
+    Backtracking(fancy_regex::Regex)
+}
+
+/// The groups of one match, as byte ranges of the subject
+pub struct Captures<'t> {
+    subject: &'t str,
+    spans: Vec<Option<(usize, usize)>>
+}
+impl<'t> Captures<'t> {
+    /// The start and end of a group, if it took part in the match
+    pub fn span(&self, i: usize) -> Option<(usize, usize)> {
+        self.spans.get(i).cloned().and_then(|span| span)
+    }
+
+    /// The text of a group, if it took part in the match
+    pub fn get(&self, i: usize) -> Option<&'t str> {
+        self.span(i).map(|(start, end)| &self.subject[start..end])
+    }
+}
+
+impl Pattern {
+    pub fn is_match(&self, subject: &str) -> Result<bool, String> {
+        match *self {
+            Pattern::Linear(ref regex) => Ok(regex.is_match(subject)),
+            Pattern::Backtracking(ref regex) => regex.is_match(subject).map_err(|e| e.to_string())
+        }
+    }
+
+    /// The byte range of the first match
+    pub fn find(&self, subject: &str) -> Result<Option<(usize, usize)>, String> {
+        match *self {
+            Pattern::Linear(ref regex) =>
+                Ok(regex.find(subject).map(|m| (m.start(), m.end()))),
+            Pattern::Backtracking(ref regex) => regex.find(subject)
+                .map(|found| found.map(|m| (m.start(), m.end())))
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    /// The byte ranges of every match, left to right
+    pub fn find_all(&self, subject: &str) -> Result<Vec<(usize, usize)>, String> {
+        match *self {
+            Pattern::Linear(ref regex) =>
+                Ok(regex.find_iter(subject).map(|m| (m.start(), m.end())).collect()),
+            Pattern::Backtracking(ref regex) => regex.find_iter(subject)
+                .map(|found| found.map(|m| (m.start(), m.end())).map_err(|e| e.to_string()))
+                .collect()
+        }
+    }
+
+    /// The groups of the first match
+    pub fn captures<'t>(&self, subject: &'t str) -> Result<Option<Captures<'t>>, String> {
+        match *self {
+            Pattern::Linear(ref regex) =>
+                Ok(regex.captures(subject).map(|caps| Captures {
+                    subject,
+                    spans: caps.iter().map(|m| m.map(|m| (m.start(), m.end()))).collect()
+                })),
+            Pattern::Backtracking(ref regex) => regex.captures(subject)
+                .map(|found| found.map(|caps| Captures {
+                    subject,
+                    spans: caps.iter().map(|m| m.map(|m| (m.start(), m.end()))).collect()
+                }))
+                .map_err(|e| e.to_string())
+        }
+    }
+
+    /// The groups of every match, left to right
+    pub fn captures_all<'t>(&self, subject: &'t str) -> Result<Vec<Captures<'t>>, String> {
+        match *self {
+            Pattern::Linear(ref regex) =>
+                Ok(regex.captures_iter(subject).map(|caps| Captures {
+                    subject,
+                    spans: caps.iter().map(|m| m.map(|m| (m.start(), m.end()))).collect()
+                }).collect()),
+            Pattern::Backtracking(ref regex) => regex.captures_iter(subject)
+                .map(|found| found.map(|caps| Captures {
+                    subject,
+                    spans: caps.iter().map(|m| m.map(|m| (m.start(), m.end()))).collect()
+                }).map_err(|e| e.to_string()))
+                .collect()
+        }
+    }
+
+    /// The pieces of the subject between matches, like `str::split()`
+    pub fn split<'t>(&self, subject: &'t str) -> Result<Vec<&'t str>, String> {
+        let mut pieces = vec![];
+        let mut last = 0;
+        for (start, end) in self.find_all(subject)? {
+            pieces.push(&subject[last..start]);
+            last = end;
+        }
+        pieces.push(&subject[last..]);
+        Ok(pieces)
+    }
+
+    /// The number of groups, including the whole match
+    pub fn captures_len(&self) -> usize {
+        match *self {
+            Pattern::Linear(ref regex) => regex.captures_len(),
+            Pattern::Backtracking(ref regex) => regex.captures_len()
+        }
+    }
+
+    /// The name of every group in order, including the unnamed whole match
+    pub fn capture_names(&self) -> Vec<Option<&str>> {
+        match *self {
+            Pattern::Linear(ref regex) => regex.capture_names().collect(),
+            Pattern::Backtracking(ref regex) => regex.capture_names().collect()
+        }
+    }
+
+    /// The number of the group with a name
+    pub fn group_index(&self, name: &str) -> Option<usize> {
+        self.capture_names().iter().position(|&n| n == Some(name))
+    }
+}
@@ -0,0 +1,452 @@
+//! Regular Expressions
+//!
+//! These replace the Boost-based functions from `extras.cpp` with the
+//! [regex crate](https://docs.rs/regex), which guarantees linear time
+//! matching. The syntax is Perl-like, see the
+//! [syntax reference](https://docs.rs/regex/1/regex/#syntax).
+//!
+//! Every function takes an optional flags string as its last argument
+//! (except `regex_any()` and `regex_which()`, which take any number of
+//! patterns and so need flags set inside them, like `(?i)`). The flags are
+//! any of these:
+//!
+//! | flag | meaning                                                         |
+//! |------|-----------------------------------------------------------------|
+//! | `i`  | Case insensitive                                                |
+//! | `m`  | `^` and `$` match at the start and end of every line            |
+//! | `s`  | `.` matches newlines too                                        |
+//! | `x`  | Ignore whitespace in the pattern and allow `#` comments         |
+//! | `u`  | Unicode aware classes like `\w` (on by default)                 |
+//! | `b`  | Use the backtracking engine, for lookaround and backreferences  |
+//!
+//! See [`engine`](engine/index.html) for the limits of the backtracking engine.
+//!
+//! All of the functions return NULL if any of their arguments are NULL,
+//! and report invalid patterns as SQL errors. Compiled patterns are shared
+//! between functions and connections through the [`cache`](cache/index.html).
+use sqlite3_raw::*;
+use dynamics::*;
+use serde_json::Value;
+use std::fmt;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::str;
+use std::sync::Arc;
+
+pub mod bytes;
+pub mod cache;
+pub mod engine;
+pub mod set;
+use self::engine::{Captures, Pattern};
+
+/// Register every regular expression function with a connection
+///
+/// # Safety
+/// `db` must be an open connection and the extension API must be loaded.
+pub unsafe fn register(db: *mut sqlite3) -> i32 {
+    // Each function is registered once without flags and once with them
+    or_die!(create_function!(db, "match", 2, regex_match));
+    or_die!(create_function!(db, "match", 3, regex_match));
+    or_die!(create_function!(db, "search", 2, regex_search));
+    or_die!(create_function!(db, "search", 3, regex_search));
+    or_die!(create_function!(db, "sub", 3, regex_sub));
+    or_die!(create_function!(db, "sub", 4, regex_sub));
+    or_die!(create_function!(db, "regexp", 2, regexp));
+    or_die!(create_function!(db, "regexp", 3, regexp));
+    or_die!(create_function!(db, "regex_capture", 3, regex_capture));
+    or_die!(create_function!(db, "regex_capture", 4, regex_capture));
+    or_die!(create_function!(db, "regex_find", 2, regex_find));
+    or_die!(create_function!(db, "regex_find", 3, regex_find));
+    or_die!(create_function!(db, "regex_find_pos", 2, regex_find_pos));
+    or_die!(create_function!(db, "regex_find_pos", 3, regex_find_pos));
+    or_die!(create_function!(db, "regex_find_pos", 4, regex_find_pos));
+    or_die!(create_function!(db, "regex_captures_json", 2, regex_captures_json));
+    or_die!(create_function!(db, "regex_captures_json", 3, regex_captures_json));
+    or_die!(create_function!(db, "regex_split_part", 3, regex_split_part));
+    or_die!(create_function!(db, "regex_split_part", 4, regex_split_part));
+    or_die!(create_function!(db, "regex_match_bytes", 2, bytes::regex_match_bytes));
+    or_die!(create_function!(db, "regex_match_bytes", 3, bytes::regex_match_bytes));
+    or_die!(create_function!(db, "regex_find_bytes", 2, bytes::regex_find_bytes));
+    or_die!(create_function!(db, "regex_find_bytes", 3, bytes::regex_find_bytes));
+    or_die!(create_function!(db, "regex_replace_bytes", 3, bytes::regex_replace_bytes));
+    or_die!(create_function!(db, "regex_replace_bytes", 4, bytes::regex_replace_bytes));
+    or_die!(create_function!(db, "regex_any", -1, set::regex_any));
+    or_die!(create_function!(db, "regex_which", -1, set::regex_which));
+    // These read a table, so they aren't deterministic
+    or_die!(create_function!(db, "regex_classify", 2, set::regex_classify, SQLITE_UTF8));
+    or_die!(create_function!(db, "regex_classify", 3, set::regex_classify, SQLITE_UTF8));
+    // Only top-level SQL may change the limits, not a database's triggers or views
+    or_die!(create_function!(db, "regex_cache_config", 1, regex_cache_config, SQLITE_UTF8 | SQLITE_DIRECTONLY));
+    or_die!(create_function!(db, "regex_cache_config", 2, regex_cache_config, SQLITE_UTF8 | SQLITE_DIRECTONLY));
+    or_die!(create_function!(db, "regex_cache_stats", 0, regex_cache_stats, SQLITE_UTF8));
+    SQLITE_OK
+}
+
+/// Read the text arguments of a function, or None if any are NULL
+fn text_args(args: &[SQLiteValue]) -> Option<Vec<String>> {
+    args.iter().map(|&arg| arg.into()).collect()
+}
+
+/// The flags argument at position `i`, if it was given
+fn flags_at(args: &[String], i: usize) -> &str {
+    args.get(i).map_or("", |flags| flags.as_str())
+}
+
+/// Compile a pattern (or fetch it from the cache), naming the function in
+/// the error message
+pub fn compile(function: &str, pattern: &str, flags: &str) -> Result<Arc<Pattern>, String> {
+    cache::get(pattern, flags).map_err(|e| format!("{}(): {}", function, e))
+}
+
+/// A compiled pattern kept as SQLite auxiliary data, with its flags
+struct Compiled {
+    flags: String,
+    pattern: Arc<Pattern>
+}
+
+/// Run `f` with the compiled pattern from argument 0, compiling it only if
+/// needed, and name the function in any error message
+///
+/// The pattern is kept as SQLite auxiliary data, so it's reused without even
+/// looking in the cache for as long as the argument (and the flags) stay the
+/// same. For a literal pattern that means the whole statement. SQLite may
+/// free the pattern as soon as it's handed over, so `f` runs first.
+fn with_regex<T, F>(ctx: *mut sqlite3_context, function: &str, pattern: &str, flags: &str, f: F) -> Result<T, String>
+    where F: FnOnce(&Pattern) -> Result<T, String>
+{
+    let cached = unsafe { sql_call!(get_auxdata)(ctx, 0) as *const Compiled };
+    let name_errors = |e| format!("{}(): {}", function, e);
+    match unsafe { cached.as_ref() } {
+        Some(cached) if cached.flags == flags => return f(&cached.pattern).map_err(name_errors),
+        _ => ()
+    }
+    let compiled = Compiled { flags: flags.to_string(), pattern: compile(function, pattern, flags)? };
+    let result = f(&compiled.pattern).map_err(name_errors);
+    unsafe {
+        sql_call!(set_auxdata)(ctx, 0, Box::into_raw(Box::new(compiled)) as *mut c_void, Some(drop_compiled));
+    }
+    result
+}
+
+unsafe extern "C" fn drop_compiled(compiled: *mut c_void) {
+    drop(Box::from_raw(compiled as *mut Compiled));
+}
+
+/// `match(pattern, subject[, flags])`: 1 if the pattern matches the whole subject
+///
+/// SQLite rewrites `subject MATCH pattern` into this call.
+fn regex_match(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match text_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    let flags = flags_at(&args, 2);
+    // With the x flag a trailing comment would swallow the closing anchor
+    let anchored = if flags.contains('x') { format!("\\A(?:{}\n)\\z", args[0]) }
+        else { format!(r"\A(?:{})\z", args[0]) };
+    with_regex(ctx, "match", &anchored, flags,
+        |regex| Ok((regex.is_match(&args[1])? as i64).into()))
+}
+
+/// `search(pattern, subject[, flags])`: 1 if the pattern matches anywhere in the subject
+fn regex_search(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match text_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    with_regex(ctx, "search", &args[0], flags_at(&args, 2),
+        |regex| Ok((regex.is_match(&args[1])? as i64).into()))
+}
+
+/// `regexp(pattern, subject[, flags])`: 1 if the pattern matches anywhere in the subject
+///
+/// SQLite rewrites `subject REGEXP pattern` into this call but doesn't
+/// provide an implementation itself.
+fn regexp(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match text_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    with_regex(ctx, "regexp", &args[0], flags_at(&args, 2),
+        |regex| Ok((regex.is_match(&args[1])? as i64).into()))
+}
+
+/// `sub(pattern, format, subject[, flags])`: Replace every match in the subject
+///
+/// See `Template` for the format syntax.
+fn regex_sub(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match text_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    let template = Template::parse(&args[1]);
+    with_regex(ctx, "sub", &args[0], flags_at(&args, 3),
+        |regex| Ok(template.replace_all(regex, &args[2])?.into()))
+}
+
+/// `regex_capture(pattern, subject, group[, flags])`: The text of one group
+/// of the first match
+///
+/// The group is either a number (0 for the whole match) or a name. Returns
+/// NULL if nothing matched or if the group didn't take part in the match.
+fn regex_capture(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let pattern : String = {let x: Option<String> = args[0].into(); x}.unwrap_or_default();
+    let subject : String = {let x: Option<String> = args[1].into(); x}.unwrap_or_default();
+    let group = match args[2].value_type() {
+        SQLITE_INTEGER | SQLITE_FLOAT => Group::Index(i64::from(args[2])),
+        _ => Group::Name({let x: Option<String> = args[2].into(); x}.unwrap_or_default())
+    };
+    let flags : String = args.get(3).and_then(|&x| x.into()).unwrap_or_default();
+    with_regex(ctx, "regex_capture", &pattern, &flags, |regex| {
+        let index = match group {
+            Group::Index(i) if i >= 0 && (i as usize) < regex.captures_len() => i as usize,
+            Group::Name(ref name) if regex.group_index(name).is_some() =>
+                regex.group_index(name).unwrap(),
+            _ => return Err(format!("the pattern has no group {}", group))
+        };
+        Ok(regex.captures(&subject)?
+            .and_then(|caps| caps.get(index))
+            .map_or(SQLiteReturn::SQLiteNull, |text| text.to_string().into()))
+    })
+}
+
+/// A capture group, referred to by number or by name
+enum Group {
+    Index(i64),
+    Name(String)
+}
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Group::Index(i) => write!(f, "{}", i),
+            Group::Name(ref name) => write!(f, "'{}'", name)
+        }
+    }
+}
+
+/// `regex_find(pattern, subject[, flags])`: The text of the first match, or NULL
+fn regex_find(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match text_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    with_regex(ctx, "regex_find", &args[0], flags_at(&args, 2),
+        |regex| Ok(regex.find(&args[1])?
+            .map_or(SQLiteReturn::SQLiteNull, |(start, end)| args[1][start..end].to_string().into())))
+}
+
+/// `regex_find_pos(pattern, subject[, unit[, flags]])`: Where the first match starts
+///
+/// Like `instr()`, positions count from 1 and 0 means there was no match.
+/// The unit is either `'char'` (the default, which agrees with `substr()`)
+/// or `'byte'`.
+fn regex_find_pos(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match text_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    let in_bytes = match args.get(2).map(|unit| unit.as_str()) {
+        None | Some("char") => false,
+        Some("byte") => true,
+        Some(unit) => return Err(format!(
+            "regex_find_pos(): unknown unit '{}', expected 'char' or 'byte'", unit))
+    };
+    with_regex(ctx, "regex_find_pos", &args[0], flags_at(&args, 3),
+        |regex| Ok(match regex.find(&args[1])? {
+            None => 0i64,
+            Some((start, _)) if in_bytes => start as i64 + 1,
+            Some((start, _)) => args[1][..start].chars().count() as i64 + 1
+        }.into()))
+}
+
+/// `regex_captures_json(pattern, subject[, flags])`: The named groups of the
+/// first match, as a JSON object
+///
+/// Groups that didn't take part in the match are `null`, and if nothing
+/// matched at all the result is NULL.
+fn regex_captures_json(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args = match text_args(args) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    with_regex(ctx, "regex_captures_json", &args[0], flags_at(&args, 2),
+        |regex| Ok(match regex.captures(&args[1])? {
+            Some(caps) => named_groups_json(regex, &caps).into(),
+            None => SQLiteReturn::SQLiteNull
+        }))
+}
+
+/// Format the named groups of a match as a JSON object, in pattern order
+pub fn named_groups_json(regex: &Pattern, caps: &Captures) -> String {
+    let fields : Vec<String> = regex.capture_names().iter()
+        .enumerate()
+        .filter_map(|(i, name)| name.map(|name| format!("{}:{}",
+            Value::from(name),
+            caps.get(i).map_or(Value::Null, Value::from))))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// `regex_split_part(pattern, subject, n[, flags])`: One piece of the
+/// subject, split on the pattern
+///
+/// Pieces count from 1, or from -1 backwards from the end. Returns NULL if
+/// there are too few pieces. This agrees with the `regex_split()` table.
+fn regex_split_part(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let n = i64::from(args[2]);
+    if n == 0 {
+        return Err("regex_split_part(): pieces count from 1 (or from -1 at the end)".into());
+    }
+    let flags : String = args.get(3).and_then(|&x| x.into()).unwrap_or_default();
+    let args = match text_args(&args[..2]) { Some(x) => x, None => return Ok(SQLiteReturn::SQLiteNull) };
+    with_regex(ctx, "regex_split_part", &args[0], &flags,
+        |regex| {
+            let pieces = regex.split(&args[1])?;
+            let i = if n > 0 { Some(n as usize - 1) }
+                else { pieces.len().checked_sub(n.unsigned_abs() as usize) };
+            Ok(i.and_then(|i| pieces.get(i))
+                .map_or(SQLiteReturn::SQLiteNull, |piece| piece.to_string().into()))
+        })
+}
+
+/// `regex_cache_config(setting[, value])`: Read or change a setting of the
+/// shared regex cache
+///
+/// The settings are `'capacity'` (how many regexes to keep), `'size_limit'`
+/// (the most bytes one compiled regex may use), `'dfa_size_limit'` (the most
+/// bytes one regex's lazy DFA may use) and `'backtrack_limit'` (the most
+/// steps one search with the `b` flag may take). Returns the setting's value,
+/// after changing it if a new value was given. The cache is shared by every
+/// connection in the process, and so are its settings, so it can't be called
+/// from triggers or views, which could come from an untrusted database.
+fn regex_cache_config(_ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let setting : Option<String> = args[0].into();
+    let mut limits = cache::limits();
+    let value = match setting.as_deref() {
+        Some("capacity") => &mut limits.capacity,
+        Some("size_limit") => &mut limits.size_limit,
+        Some("dfa_size_limit") => &mut limits.dfa_size_limit,
+        Some("backtrack_limit") => &mut limits.backtrack_limit,
+        _ => return Err("regex_cache_config(): unknown setting, expected \
+            'capacity', 'size_limit', 'dfa_size_limit' or 'backtrack_limit'".into())
+    };
+    if let Some(&new_value) = args.get(1) {
+        let new_value = match new_value.value_type() {
+            SQLITE_INTEGER if i64::from(new_value) >= 0 => i64::from(new_value),
+            _ => return Err("regex_cache_config(): settings must be integers, at least 0".into())
+        };
+        *value = new_value as usize;
+    }
+    let result = *value as i64;
+    if args.len() > 1 {
+        cache::set_limits(limits);
+    }
+    Ok(result.into())
+}
+
+/// `regex_cache_stats()`: A JSON object describing the shared regex cache
+///
+/// It has the number of `entries` and the `capacity`, plus the number of
+/// `hits` and `misses` since the extension was loaded.
+fn regex_cache_stats(_ctx: *mut sqlite3_context, _args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let (entries, hits, misses) = cache::stats();
+    Ok(format!(r#"{{"entries":{},"capacity":{},"hits":{},"misses":{}}}"#,
+        entries, cache::limits().capacity, hits, misses).into())
+}
+
+/// A parsed replacement format string, using Perl's syntax
+///
+/// - `$0` or `$&`: the whole match
+/// - `$1`, `$2`, ...: a numbered group (all the digits are part of the number)
+/// - `${1}` or `${name}`: a numbered or named group
+/// - `\0`, `\1`, ...: the same as `$0`, `$1`, ... for compatibility with `extras.cpp`
+/// - `$$` and `\\`: a literal `$` or `\`
+/// - `\xNN`: a raw byte, but only in formats for BLOBs
+///
+/// Groups that didn't participate in the match are replaced with nothing,
+/// and anything else is copied literally.
+pub struct Template(Vec<Piece>);
+enum Piece {
+    Literal(Vec<u8>),
+    Group(usize),
+    Named(String)
+}
+impl Template {
+    pub fn parse(format: &str) -> Template {
+        Template::parse_bytes(format.as_bytes(), false)
+    }
+
+    /// Parse a format that might not be UTF-8, optionally with `\xNN` escapes
+    pub fn parse_bytes(format: &[u8], hex_escapes: bool) -> Template {
+        let mut pieces = vec![];
+        let mut literal = vec![];
+        let mut rest = format;
+        while let Some(i) = rest.iter().position(|&c| c == b'$' || c == b'\\') {
+            literal.extend_from_slice(&rest[..i]);
+            let sigil = rest[i];
+            let after = &rest[i + 1..];
+            let digits = after.iter().take_while(|c| c.is_ascii_digit()).count();
+            let closing = after.iter().position(|&c| c == b'}')
+                .filter(|&end| sigil == b'$' && after[0] == b'{' && end > 1);
+            let hex = after.get(1..3)
+                .filter(|_| hex_escapes && sigil == b'\\' && after[0] == b'x')
+                .and_then(|hex| str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            // Work out what follows the sigil and how much of it to consume,
+            // or else which byte it stands for
+            let (group, byte, consumed) = if after.first() == Some(&sigil) {
+                (None, sigil, 1)
+            } else if sigil == b'$' && after.first() == Some(&b'&') {
+                (Some(Piece::Group(0)), sigil, 1)
+            } else if let Some(byte) = hex {
+                (None, byte, 3)
+            } else if let Some(end) = closing {
+                let name = String::from_utf8_lossy(&after[1..end]);
+                let group = name.parse().map(Piece::Group)
+                    .unwrap_or_else(|_| Piece::Named(name.into_owned()));
+                (Some(group), sigil, end + 1)
+            } else if digits > 0 {
+                // Too many digits to be a group means it's just text
+                let group = str::from_utf8(&after[..digits]).unwrap().parse().map(Piece::Group)
+                    .unwrap_or_else(|_| Piece::Literal(rest[i..i + 1 + digits].to_vec()));
+                (Some(group), sigil, digits)
+            } else {
+                (None, sigil, 0)
+            };
+            match group {
+                Some(group) => {
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(literal.split_off(0)));
+                    }
+                    pieces.push(group);
+                },
+                None => literal.push(byte)
+            }
+            rest = &after[consumed..];
+        }
+        literal.extend_from_slice(rest);
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Template(pieces)
+    }
+
+    /// Replace every match of the pattern in the subject
+    pub fn replace_all(&self, regex: &Pattern, subject: &str) -> Result<String, String> {
+        let mut result = Vec::with_capacity(subject.len());
+        let mut last = 0;
+        for caps in regex.captures_all(subject)? {
+            let (start, end) = caps.span(0).unwrap();
+            result.extend_from_slice(&subject.as_bytes()[last..start]);
+            self.expand(
+                |i| caps.get(i).map(str::as_bytes),
+                |name| regex.group_index(name),
+                &mut result);
+            last = end;
+        }
+        result.extend_from_slice(&subject.as_bytes()[last..]);
+        // Text formats only split the text at ASCII, so this is still UTF-8
+        String::from_utf8(result).map_err(|e| e.to_string())
+    }
+
+    /// Append the replacement for one match, given its groups by number and
+    /// how to find a group's number from its name
+    pub fn expand<'t, G, N>(&self, group: G, group_index: N, dst: &mut Vec<u8>)
+        where G: Fn(usize) -> Option<&'t [u8]>,
+              N: Fn(&str) -> Option<usize>
+    {
+        for piece in &self.0 {
+            match *piece {
+                Piece::Literal(ref text) => dst.extend_from_slice(text),
+                Piece::Group(n) => dst.extend_from_slice(group(n).unwrap_or(b"")),
+                Piece::Named(ref name) => dst.extend_from_slice(
+                    group_index(name).and_then(&group).unwrap_or(b""))
+            }
+        }
+    }
+}
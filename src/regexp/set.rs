@@ -0,0 +1,170 @@
+//! Matching many patterns at once
+//!
+//! These compile all their patterns into one
+//! [`RegexSet`](https://docs.rs/regex/1/regex/struct.RegexSet.html), which
+//! finds every pattern that matches in a single scan of the subject, no
+//! matter how many patterns there are. Sets can't tell where each pattern
+//! matched, and only the linear engine supports them, so the `b` flag isn't
+//! allowed here.
+use sqlite3_raw::*;
+use dynamics::*;
+use regex::{RegexSet, RegexSetBuilder};
+use regexp::cache;
+use std::os::raw::{c_int, c_void};
+
+/// A compiled set kept as SQLite auxiliary data, with what it was built from
+struct CompiledSet {
+    /// The patterns, or for a table, its name
+    source: Vec<String>,
+    flags: String,
+    /// What to report for each pattern that matches
+    ids: Vec<i64>,
+    set: RegexSet
+}
+
+/// Compile patterns into a set under the shared cache's size limits
+fn build_set(patterns: &[String], flags: &str) -> Result<RegexSet, String> {
+    let (inline, backtracking) = cache::parse_flags(flags)?;
+    if backtracking {
+        return Err("the b flag isn't supported when matching many patterns at once".into());
+    }
+    let limits = cache::limits();
+    RegexSetBuilder::new(patterns)
+        .size_limit(limits.size_limit)
+        .dfa_size_limit(limits.dfa_size_limit)
+        .case_insensitive(inline.contains('i'))
+        .multi_line(inline.contains('m'))
+        .dot_matches_new_line(inline.contains('s'))
+        .ignore_whitespace(inline.contains('x'))
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Run `f` with the set for argument `arg`, building it only if needed
+///
+/// Like `with_regex()`, the set is reused for as long as SQLite keeps the
+/// argument's auxiliary data, which for literal patterns or a literal table
+/// name is the whole statement.
+fn with_set<T, B, F>(ctx: *mut sqlite3_context, arg: c_int, source: &[String], flags: &str, build: B, f: F)
+    -> Result<T, String>
+    where B: FnOnce() -> Result<(Vec<i64>, RegexSet), String>,
+          F: FnOnce(&CompiledSet) -> T
+{
+    let cached = unsafe { sql_call!(get_auxdata)(ctx, arg) as *const CompiledSet };
+    match unsafe { cached.as_ref() } {
+        Some(cached) if cached.source[..] == *source && cached.flags == flags => return Ok(f(cached)),
+        _ => ()
+    }
+    let (ids, set) = build()?;
+    let compiled = CompiledSet { source: source.to_vec(), flags: flags.to_string(), ids, set };
+    let result = f(&compiled);
+    unsafe {
+        sql_call!(set_auxdata)(ctx, arg, Box::into_raw(Box::new(compiled)) as *mut c_void, Some(drop_set));
+    }
+    Ok(result)
+}
+
+unsafe extern "C" fn drop_set(compiled: *mut c_void) {
+    drop(Box::from_raw(compiled as *mut CompiledSet));
+}
+
+/// The ids of every pattern in the set that matches, as a JSON array
+fn matching_ids(compiled: &CompiledSet, subject: &str) -> String {
+    let ids : Vec<String> = compiled.set.matches(subject).iter()
+        .map(|i| compiled.ids[i].to_string())
+        .collect();
+    format!("[{}]", ids.join(","))
+}
+
+/// Run `f` on a set of the patterns given as arguments after the subject
+fn with_argument_set<T, F>(ctx: *mut sqlite3_context, function: &str, args: &[SQLiteValue], f: F)
+    -> Result<Option<T>, String>
+    where F: FnOnce(&CompiledSet, &str) -> T
+{
+    let args : Vec<String> = match args.iter().map(|&arg| arg.into()).collect() {
+        Some(args) => args,
+        None => return Ok(None)
+    };
+    if args.is_empty() {
+        return Err(format!("{}() requires a subject", function));
+    }
+    let patterns = &args[1..];
+    // With no patterns there's no argument to keep the set with
+    let arg = if patterns.is_empty() { 0 } else { 1 };
+    with_set(ctx, arg, patterns, "",
+        || Ok(((1..=patterns.len() as i64).collect(), build_set(patterns, "")?)),
+        |compiled| f(compiled, &args[0]))
+        .map(Some)
+        .map_err(|e| format!("{}(): {}", function, e))
+}
+
+/// `regex_any(subject, pattern1, pattern2, ...)`: 1 if any pattern matches
+/// anywhere in the subject
+///
+/// Flags can be set inside each pattern, like `(?i)error`.
+pub fn regex_any(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(with_argument_set(ctx, "regex_any", args,
+        |compiled, subject| (compiled.set.is_match(subject) as i64).into())?
+        .unwrap_or(SQLiteReturn::SQLiteNull))
+}
+
+/// `regex_which(subject, pattern1, pattern2, ...)`: Which patterns match
+/// anywhere in the subject
+///
+/// Returns a JSON array of the patterns' positions, counting from 1, so
+/// `regex_which('ab', 'x', 'a', 'b')` is `[2,3]`.
+pub fn regex_which(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(with_argument_set(ctx, "regex_which", args,
+        |compiled, subject| matching_ids(compiled, subject).into())?
+        .unwrap_or(SQLiteReturn::SQLiteNull))
+}
+
+/// `regex_classify(subject, patterns_table[, flags])`: Which patterns from a
+/// table match anywhere in the subject
+///
+/// The table needs a `pattern` column, and the result is a JSON array of the
+/// rowids of the rows whose pattern matches, in rowid order. A name with a
+/// dot, like `aux.classes`, is a table in that schema, so the name of a
+/// table can't contain a dot itself. Rows with a
+/// NULL pattern never match. The table is read once per statement, so
+/// changes to it show up in the next statement.
+///
+/// ```sql
+/// SELECT logs.id, classes.label
+/// FROM logs, json_each(regex_classify(logs.line, 'classes')) AS hit
+/// JOIN classes ON classes.rowid = hit.value;
+/// ```
+pub fn regex_classify(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let args : Vec<String> = match args.iter().map(|&arg| arg.into()).collect() {
+        Some(args) => args,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    let flags = args.get(2).map_or("", |flags| flags.as_str());
+    with_set(ctx, 1, &args[1..2], flags,
+        || {
+            let (ids, patterns) = load_patterns(ctx, &args[1])?;
+            Ok((ids, build_set(&patterns, flags)?))
+        },
+        |compiled| matching_ids(compiled, &args[0]).into())
+        .map_err(|e| format!("regex_classify(): {}", e))
+}
+
+/// Read the rowids and patterns of a table
+fn load_patterns(ctx: *mut sqlite3_context, table: &str) -> Result<(Vec<i64>, Vec<String>), String> {
+    let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+    let table = match table.find('.') {
+        Some(i) => format!("{}.{}", quote(&table[..i]), quote(&table[i + 1..])),
+        None => quote(table)
+    };
+    let sql = format!("SELECT rowid, pattern FROM {} WHERE pattern IS NOT NULL ORDER BY rowid", table);
+    let mut ids = vec![];
+    let mut patterns = vec![];
+    unsafe {
+        query_each(sql_call!(context_db_handle)(ctx), &sql, |row| {
+            ids.push(i64::from(row[0]));
+            patterns.push({let x: Option<String> = row[1].into(); x}.unwrap_or_default());
+            Ok(())
+        })?;
+    }
+    Ok((ids, patterns))
+}
@@ -0,0 +1,249 @@
+//! Aggregates over vectors
+//!
+//! `vec_sum(v)`, `vec_avg(v)`, `vec_min(v)` and `vec_max(v)` work
+//! element-wise, so `vec_avg()` of a group of embeddings is their centroid.
+//! They're window functions too, for rolling sums and averages:
+//!
+//! ```sql
+//! SELECT day, vec_avg(embedding) OVER (ORDER BY day ROWS BETWEEN 6 PRECEDING AND CURRENT ROW)
+//! FROM daily;
+//! ```
+//!
+//! NULL rows are skipped and a group without any vectors gives NULL, like
+//! SQL's `sum()`. Every vector in a group must have the same number of
+//! dimensions. The result has the widest element type in the group, except
+//! that sums and averages of i8 and bit vectors are f64, since they rarely
+//! fit in an i8.
+//!
+//! `vec_collect(value[, type])` is different: it makes a vector of the
+//! numbers in a group, in order, so it undoes `vec_each()`. The element
+//! type is f64 unless it's given. A NULL among them is an error, since a
+//! vector can't hold it.
+//!
+//! Sums use Neumaier's compensated summation, so adding many small
+//! elements to a large one doesn't lose them, and rows leaving a sliding
+//! window frame are subtracted just as accurately.
+use sqlite3_raw::*;
+use aggregate::Aggregate;
+use dynamics::*;
+use std::collections::VecDeque;
+use vector::{type_arg, type_name, vector_arg, ElementType, Vector};
+
+/// The vector in a row, or None if it's NULL, checking it fits the group
+///
+/// `shape` is the widest element type and the dimensions seen so far.
+fn row(function: &str, arg: SQLiteValue, shape: &mut Option<(ElementType, usize)>) -> Result<Option<Vec<f64>>, String> {
+    let v = match vector_arg(function, arg)? {
+        Some(v) => v,
+        None => return Ok(None)
+    };
+    *shape = match *shape {
+        None => Some((v.element_type(), v.dims())),
+        Some((_, dims)) if dims != v.dims() => return Err(format!(
+            "{}(): every vector must have {} dimensions like the first, not {}",
+            function, dims, v.dims())),
+        Some((ty, dims)) => Some((ty.promote(v.element_type()), dims))
+    };
+    Ok(Some(v.to_f64()))
+}
+
+/// Compensated running sums of each element, for `vec_sum()` and `vec_avg()`
+#[derive(Default)]
+pub struct Sums {
+    shape: Option<(ElementType, usize)>,
+    sums: Vec<f64>,
+    compensation: Vec<f64>,
+    count: i64
+}
+impl Sums {
+    /// Add a row, or subtract it when it leaves the window frame
+    fn add(&mut self, function: &str, arg: SQLiteValue, sign: f64) -> Result<(), String> {
+        let values = match row(function, arg, &mut self.shape)? {
+            Some(values) => values,
+            None => return Ok(())
+        };
+        if self.sums.is_empty() {
+            self.sums = vec![0.0; values.len()];
+            self.compensation = vec![0.0; values.len()];
+        }
+        for ((sum, c), x) in self.sums.iter_mut().zip(self.compensation.iter_mut()).zip(values) {
+            let x = sign * x;
+            let total = *sum + x;
+            // Keep the low order bits lost from whichever addend was smaller
+            *c += if sum.abs() >= x.abs() { (*sum - total) + x } else { (x - total) + *sum };
+            *sum = total;
+        }
+        self.count += sign as i64;
+        Ok(())
+    }
+
+    /// The sums divided by `divisor`, or NULL if there are no rows
+    fn result(&self, function: &str, divisor: f64) -> Result<SQLiteReturn, String> {
+        let ty = match self.shape {
+            Some(_) if self.count == 0 => return Ok(SQLiteReturn::SQLiteNull),
+            Some((ElementType::F32, _)) => ElementType::F32,
+            Some(_) => ElementType::F64,
+            None => return Ok(SQLiteReturn::SQLiteNull)
+        };
+        let values: Vec<f64> = self.sums.iter().zip(&self.compensation)
+            .map(|(sum, c)| (sum + c) / divisor)
+            .collect();
+        Ok(Vector::from_f64(ty, &values).map_err(|e| format!("{}(): {}", function, e))?.encode().into())
+    }
+}
+
+/// `vec_sum(v)`: The element-wise sum of the vectors
+#[derive(Default)]
+pub struct VecSum(Sums);
+impl Aggregate for VecSum {
+    fn step(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        self.0.add("vec_sum", args[0], 1.0)
+    }
+    fn inverse(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        self.0.add("vec_sum", args[0], -1.0)
+    }
+    fn value(&self) -> Result<SQLiteReturn, String> {
+        self.0.result("vec_sum", 1.0)
+    }
+}
+
+/// `vec_avg(v)`: The element-wise mean of the vectors
+#[derive(Default)]
+pub struct VecAvg(Sums);
+impl Aggregate for VecAvg {
+    fn step(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        self.0.add("vec_avg", args[0], 1.0)
+    }
+    fn inverse(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        self.0.add("vec_avg", args[0], -1.0)
+    }
+    fn value(&self) -> Result<SQLiteReturn, String> {
+        self.0.result("vec_avg", self.0.count as f64)
+    }
+}
+
+/// The smallest (or largest) value of each element, for `vec_min()` and
+/// `vec_max()`
+///
+/// Rows only ever leave a window frame oldest first, so for each element
+/// this keeps a queue of the values which could still become the extreme:
+/// each newer than the last and more extreme than any after it. The front
+/// of the queue is the extreme. Each value is pushed and popped at most
+/// once, and for unordered data the queues stay short.
+#[derive(Default)]
+pub struct Extremes {
+    shape: Option<(ElementType, usize)>,
+    queues: Vec<VecDeque<(u64, f64)>>,
+    /// How many rows have been added
+    added: u64,
+    /// How many rows have left the window frame
+    removed: u64
+}
+impl Extremes {
+    /// Add a row, where `better(a, b)` says whether a should replace b
+    fn step(&mut self, function: &str, arg: SQLiteValue, better: fn(f64, f64) -> bool) -> Result<(), String> {
+        let values = match row(function, arg, &mut self.shape)? {
+            Some(values) => values,
+            None => return Ok(())
+        };
+        if self.queues.is_empty() {
+            self.queues = vec![VecDeque::new(); values.len()];
+        }
+        for (queue, x) in self.queues.iter_mut().zip(values) {
+            while queue.back().is_some_and(|&(_, last)| !better(last, x)) {
+                queue.pop_back();
+            }
+            queue.push_back((self.added, x));
+        }
+        self.added += 1;
+        Ok(())
+    }
+
+    /// Remove the oldest row, unless it was NULL and so was never added
+    fn inverse(&mut self, arg: SQLiteValue) {
+        if arg.is_null() {
+            return;
+        }
+        let oldest = self.removed;
+        for queue in &mut self.queues {
+            if queue.front().is_some_and(|&(i, _)| i == oldest) {
+                queue.pop_front();
+            }
+        }
+        self.removed += 1;
+    }
+
+    fn result(&self, function: &str) -> Result<SQLiteReturn, String> {
+        match self.shape {
+            Some((ty, _)) if self.added > self.removed => {
+                let values: Vec<f64> = self.queues.iter().map(|queue| queue[0].1).collect();
+                Ok(Vector::from_f64(ty, &values).map_err(|e| format!("{}(): {}", function, e))?.encode().into())
+            },
+            _ => Ok(SQLiteReturn::SQLiteNull)
+        }
+    }
+}
+
+/// `vec_min(v)`: The element-wise minimum of the vectors
+#[derive(Default)]
+pub struct VecMin(Extremes);
+impl Aggregate for VecMin {
+    fn step(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        self.0.step("vec_min", args[0], |a, b| a < b)
+    }
+    fn inverse(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        self.0.inverse(args[0]);
+        Ok(())
+    }
+    fn value(&self) -> Result<SQLiteReturn, String> {
+        self.0.result("vec_min")
+    }
+}
+
+/// `vec_max(v)`: The element-wise maximum of the vectors
+#[derive(Default)]
+pub struct VecMax(Extremes);
+impl Aggregate for VecMax {
+    fn step(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        self.0.step("vec_max", args[0], |a, b| a > b)
+    }
+    fn inverse(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        self.0.inverse(args[0]);
+        Ok(())
+    }
+    fn value(&self) -> Result<SQLiteReturn, String> {
+        self.0.result("vec_max")
+    }
+}
+
+/// `vec_collect(value[, type])`: A vector of the numbers, in order
+#[derive(Default)]
+pub struct VecCollect {
+    values: VecDeque<f64>,
+    element_type: Option<ElementType>
+}
+impl Aggregate for VecCollect {
+    fn step(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        if self.element_type.is_none() {
+            self.element_type = Some(type_arg("vec_collect", args, 1)?);
+        }
+        match args[0].value_type() {
+            SQLITE_INTEGER | SQLITE_FLOAT => self.values.push_back(f64::from(args[0])),
+            other => return Err(format!("vec_collect(): element {} should be a number, not {}",
+                self.values.len() + 1, type_name(other)))
+        }
+        Ok(())
+    }
+    fn inverse(&mut self, _args: &[SQLiteValue]) -> Result<(), String> {
+        self.values.pop_front();
+        Ok(())
+    }
+    fn value(&self) -> Result<SQLiteReturn, String> {
+        let ty = match self.element_type {
+            Some(ty) if !self.values.is_empty() => ty,
+            _ => return Ok(SQLiteReturn::SQLiteNull)
+        };
+        let values: Vec<f64> = self.values.iter().cloned().collect();
+        Ok(Vector::from_f64(ty, &values).map_err(|e| format!("vec_collect(): {}", e))?.encode().into())
+    }
+}
@@ -0,0 +1,171 @@
+//! Element-wise arithmetic on vectors and numbers
+//!
+//! Every argument may be a number or a vector. Numbers are broadcast, so
+//! `add(v, 1)` adds 1 to every element, and given only numbers these work
+//! like ordinary math functions. Vectors must all have the same number of
+//! dimensions; `extras.cpp` silently dropped the extra elements instead.
+//!
+//! The result has the element type of the vectors, or the wider one if
+//! they differ (f64, then f32, then q8, then i8, then bit). Numbers never change the
+//! element type. So if a result doesn't fit in an i8 or bit vector, like
+//! `div(vread('1 2', 'i8'), 2)`, it's an error rather than being rounded;
+//! convert the vector with `vec_f32()` or `vec_f64()` first.
+use sqlite3_raw::*;
+use dynamics::*;
+use vector::{type_name, ElementType, Vector};
+use vector::simd::Isa;
+
+/// An argument that is either a number or a vector
+enum Operand {
+    Scalar(f64),
+    Vector(ElementType, Vec<f64>)
+}
+
+/// Read every argument as a number or a vector, or None if any are NULL
+fn operands(function: &str, args: &[SQLiteValue]) -> Result<Option<Vec<Operand>>, String> {
+    let mut operands = Vec::with_capacity(args.len());
+    for (i, &arg) in args.iter().enumerate() {
+        operands.push(match arg.value_type() {
+            SQLITE_NULL => return Ok(None),
+            SQLITE_INTEGER | SQLITE_FLOAT => Operand::Scalar(arg.into()),
+            SQLITE_BLOB => {
+                let blob: Vec<u8> = {let x: Option<Vec<u8>> = arg.into(); x}.unwrap_or_default();
+                let v = Vector::decode(&blob)
+                    .map_err(|e| format!("{}(): argument {}: {}", function, i + 1, e))?;
+                Operand::Vector(v.element_type(), v.to_f64())
+            },
+            other => return Err(format!(
+                "{}(): argument {} should be a number or a vector, not {} (read text with vread())",
+                function, i + 1, type_name(other)))
+        });
+    }
+    Ok(Some(operands))
+}
+
+/// Apply `f` to each set of corresponding elements, broadcasting numbers
+///
+/// `f` gets one value from each argument, in order.
+pub fn broadcast<F>(function: &str, args: &[SQLiteValue], f: F) -> Result<SQLiteReturn, String>
+    where F: Fn(&[f64]) -> f64
+{
+    combine(function, args, f, None)
+}
+
+/// `broadcast()` for an operator of two arguments, with a SIMD kernel to
+/// use instead of `f` when both are vectors
+fn binop<F>(function: &str, args: &[SQLiteValue], f: F, kernel: Kernel) -> Result<SQLiteReturn, String>
+    where F: Fn(&[f64]) -> f64
+{
+    combine(function, args, f, Some(kernel))
+}
+
+/// An element-wise kernel from `vector::simd`
+type Kernel = fn(&[f64], &[f64]) -> Vec<f64>;
+
+fn combine<F>(function: &str, args: &[SQLiteValue], f: F, kernel: Option<Kernel>) -> Result<SQLiteReturn, String>
+    where F: Fn(&[f64]) -> f64
+{
+    let operands = match operands(function, args)? {
+        Some(operands) => operands,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    // The shape of the result comes from the vectors, if there are any
+    let mut shape: Option<(ElementType, usize, usize)> = None;
+    for (i, operand) in operands.iter().enumerate() {
+        if let Operand::Vector(ty, ref values) = *operand {
+            shape = match shape {
+                None => Some((ty, values.len(), i)),
+                Some((_, dims, first)) if dims != values.len() => return Err(format!(
+                    "{}(): argument {} has {} dimensions but argument {} has {}",
+                    function, i + 1, values.len(), first + 1, dims)),
+                Some((widest, dims, first)) => Some((widest.promote(ty), dims, first))
+            };
+        }
+    }
+    let mut row = vec![0.0; operands.len()];
+    let mut element = |i: usize| {
+        for (x, operand) in row.iter_mut().zip(&operands) {
+            *x = match *operand {
+                Operand::Scalar(x) => x,
+                Operand::Vector(_, ref values) => values[i]
+            };
+        }
+        f(&row)
+    };
+    Ok(match shape {
+        None => element(0).into(),
+        Some((ty, dims, _)) => {
+            let values: Vec<f64> = match (kernel, operands.as_slice()) {
+                (Some(kernel), [Operand::Vector(_, ref a), Operand::Vector(_, ref b)]) => kernel(a, b),
+                _ => (0..dims).map(element).collect()
+            };
+            Vector::from_f64(ty, &values)
+                .map_err(|e| format!("{}(): {}", function, e))?
+                .encode()
+                .into()
+        }
+    })
+}
+
+/// Apply a math function of one argument to a number, or to each element
+/// of a vector
+///
+/// Text is read as a number, as SQLite's own math functions do.
+pub fn unop<F>(function: &str, arg: SQLiteValue, f: F) -> Result<SQLiteReturn, String>
+    where F: Fn(f64) -> f64
+{
+    match arg.value_type() {
+        SQLITE_NULL => Ok(SQLiteReturn::SQLiteNull),
+        SQLITE_BLOB => broadcast(function, &[arg], |x| f(x[0])),
+        _ => Ok(f(arg.into()).into())
+    }
+}
+
+/// `add(a, b)`: a + b
+///
+/// ADD is a keyword, so in SQL the name has to be quoted, like `"add"(a, b)`.
+pub fn add(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    binop("add", args, |x| x[0] + x[1], |a, b| Isa::best().add(a, b))
+}
+
+/// `subtract(a, b)`: a - b
+pub fn subtract(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    binop("subtract", args, |x| x[0] - x[1], |a, b| Isa::best().subtract(a, b))
+}
+
+/// `mult(a, b)`: a * b
+pub fn mult(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    binop("mult", args, |x| x[0] * x[1], |a, b| Isa::best().mult(a, b))
+}
+
+/// `div(a, b)`: a / b
+pub fn div(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    binop("div", args, |x| x[0] / x[1], |a, b| Isa::best().div(a, b))
+}
+
+/// `pow(a, b)`: a raised to the power b
+pub fn pow(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    broadcast("pow", args, |x| x[0].powf(x[1]))
+}
+
+/// `vmin(a, b)`: The smaller of a and b
+pub fn vmin(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    broadcast("vmin", args, |x| x[0].min(x[1]))
+}
+
+/// `vmax(a, b)`: The larger of a and b
+pub fn vmax(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    broadcast("vmax", args, |x| x[0].max(x[1]))
+}
+
+/// `vabs(a)`: The absolute value of a
+pub fn vabs(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    broadcast("vabs", args, |x| x[0].abs())
+}
+
+/// `vclip(a, lo, hi)`: a, but no smaller than lo and no larger than hi
+///
+/// If lo is larger than hi, the result is hi.
+pub fn vclip(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    broadcast("vclip", args, |x| x[0].max(x[1]).min(x[2]))
+}
@@ -0,0 +1,297 @@
+//! Distances and similarities between two vectors
+//!
+//! Both vectors must have the same number of dimensions, but their element
+//! types may differ; everything is computed in f64, with the sums done by
+//! the kernels in `vector::simd`. Bits count as 0 and 1, as they do
+//! everywhere else, including `vec_dequantize()`, so `vec_hamming()` of
+//! two bit vectors counts the differing bits, and `vec_jaccard()` compares
+//! the sets of bits that are set. Dot products, cosines and
+//! Euclidean distances of two q8 vectors with the same range are computed
+//! from sums of their codes, scaled once at the end.
+//!
+//! `extras.cpp` only had `dot()` and `cossim()`, and truncated the longer
+//! vector when the dimensions differed.
+use sqlite3_raw::*;
+use dynamics::*;
+use std::cmp::Ordering;
+use vector::{simd, vector_arg, Vector};
+
+/// The dot product of two slices of the same length
+pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+    simd::dot(a, b)
+}
+
+/// The cosine of the angle between two slices, or None if either is zero
+pub fn cosine_similarity(a: &[f64], b: &[f64]) -> Option<f64> {
+    let norms = dot(a, a).sqrt() * dot(b, b).sqrt();
+    if norms == 0.0 {
+        return None;
+    }
+    // Rounding can take parallel vectors just past 1
+    Some((dot(a, b) / norms).clamp(-1.0, 1.0))
+}
+
+/// The squared Euclidean distance
+pub fn l2_squared(a: &[f64], b: &[f64]) -> f64 {
+    simd::l2_squared(a, b)
+}
+
+/// The Manhattan distance
+pub fn l1(a: &[f64], b: &[f64]) -> f64 {
+    simd::l1(a, b)
+}
+
+/// The Chebyshev distance, the largest difference between two elements
+pub fn linf(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y).abs()).fold(0.0, f64::max)
+}
+
+/// The number of elements that differ
+pub fn hamming(a: &[f64], b: &[f64]) -> usize {
+    a.iter().zip(b).filter(|&(x, y)| x != y).count()
+}
+
+/// The weighted Jaccard distance, 1 - Σ min(aᵢ, bᵢ) / Σ max(aᵢ, bᵢ)
+///
+/// For bits this is the Jaccard distance between the sets of set bits.
+/// Two zero vectors are the same set, so their distance is 0.
+pub fn jaccard(a: &[f64], b: &[f64]) -> Result<f64, String> {
+    let (mut low, mut high) = (0.0, 0.0);
+    for (&x, &y) in a.iter().zip(b) {
+        if x < 0.0 || y < 0.0 {
+            return Err("the Jaccard distance needs elements that aren't negative".into());
+        }
+        low += x.min(y);
+        high += x.max(y);
+    }
+    Ok(if high == 0.0 { 0.0 } else { 1.0 - low / high })
+}
+
+/// A distance to rank vectors by, for searches like `vec_knn`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Metric {
+    L2,
+    L2Squared,
+    L1,
+    Linf,
+    Cosine,
+    /// The negated dot product, so that the largest products come first
+    Dot,
+    Hamming,
+    Jaccard
+}
+impl Metric {
+    /// Every metric, in the order they're listed in error messages
+    const ALL: [Metric; 8] = [Metric::L2, Metric::L2Squared, Metric::L1, Metric::Linf,
+        Metric::Cosine, Metric::Dot, Metric::Hamming, Metric::Jaccard];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::L2 => "l2",
+            Metric::L2Squared => "l2_squared",
+            Metric::L1 => "l1",
+            Metric::Linf => "linf",
+            Metric::Cosine => "cosine",
+            Metric::Dot => "dot",
+            Metric::Hamming => "hamming",
+            Metric::Jaccard => "jaccard"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Metric, String> {
+        Metric::ALL.iter().cloned().find(|m| m.name().eq_ignore_ascii_case(name)).ok_or_else(|| {
+            let names: Vec<&str> = Metric::ALL.iter().map(|m| m.name()).collect();
+            format!("unknown metric '{}', expected one of {}", name, names.join(", "))
+        })
+    }
+
+    /// How far apart two slices of the same length are, or None if the
+    /// metric isn't defined for them, like the cosine distance of zero
+    pub fn distance(self, a: &[f64], b: &[f64]) -> Result<Option<f64>, String> {
+        Ok(Some(match self {
+            Metric::L2 => l2_squared(a, b).sqrt(),
+            Metric::L2Squared => l2_squared(a, b),
+            Metric::L1 => l1(a, b),
+            Metric::Linf => linf(a, b),
+            Metric::Cosine => match cosine_similarity(a, b) {
+                Some(s) => 1.0 - s,
+                None => return Ok(None)
+            },
+            Metric::Dot => -dot(a, b),
+            Metric::Hamming => hamming(a, b) as f64,
+            Metric::Jaccard => jaccard(a, b)?
+        }))
+    }
+}
+
+/// A row and its distance from the query, ordered by distance then rowid
+#[derive(Clone, Copy, Debug)]
+pub struct Neighbour {
+    pub distance: f64,
+    pub rowid: i64
+}
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Neighbour) -> Ordering {
+        self.distance.total_cmp(&other.distance).then(self.rowid.cmp(&other.rowid))
+    }
+}
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Neighbour) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Neighbour) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Neighbour {}
+
+/// Sums over the codes of two q8 vectors with the same range, where each
+/// element is `min + step * u` for the code `u` counted from 0 to 255
+struct CodeSums {
+    len: f64,
+    min: f64,
+    step: f64,
+    a: i64,
+    b: i64,
+    aa: i64,
+    bb: i64,
+    ab: i64
+}
+impl CodeSums {
+    fn new(min: f32, max: f32, a: &[i8], b: &[i8]) -> CodeSums {
+        let mut sums = CodeSums {
+            len: a.len() as f64,
+            min: f64::from(min),
+            step: (f64::from(max) - f64::from(min)) / 255.0,
+            a: 0, b: 0, aa: 0, bb: 0, ab: 0
+        };
+        for (&x, &y) in a.iter().zip(b) {
+            let (x, y) = (i64::from(x) + 128, i64::from(y) + 128);
+            sums.a += x;
+            sums.b += y;
+            sums.aa += x * x;
+            sums.bb += y * y;
+            sums.ab += x * y;
+        }
+        sums
+    }
+
+    /// Σ xᵢyᵢ, from the sums of the codes of x, of y and of their products
+    fn dot_of(&self, x: i64, y: i64, xy: i64) -> f64 {
+        self.len * self.min * self.min + self.min * self.step * (x + y) as f64 + self.step * self.step * xy as f64
+    }
+
+    fn dot(&self) -> f64 {
+        self.dot_of(self.a, self.b, self.ab)
+    }
+
+    fn cosine_similarity(&self) -> Option<f64> {
+        // Rounding can leave the square of a zero vector just below 0
+        let norms = self.dot_of(self.a, self.a, self.aa).max(0.0).sqrt()
+            * self.dot_of(self.b, self.b, self.bb).max(0.0).sqrt();
+        if norms == 0.0 {
+            return None;
+        }
+        Some((self.dot() / norms).clamp(-1.0, 1.0))
+    }
+
+    /// The offsets cancel, leaving Σ (uᵢ - vᵢ)², which is exact
+    fn l2_squared(&self) -> f64 {
+        self.step * self.step * (self.aa - 2 * self.ab + self.bb) as f64
+    }
+}
+
+/// Both vectors, which must have the same dimensions, or None if either
+/// is NULL
+fn vector_pair(function: &str, args: &[SQLiteValue]) -> Result<Option<(Vector, Vector)>, String> {
+    let (a, b) = match (vector_arg(function, args[0])?, vector_arg(function, args[1])?) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Ok(None)
+    };
+    if a.dims() != b.dims() {
+        return Err(format!("{}(): the first vector has {} dimensions but the second has {}",
+            function, a.dims(), b.dims()));
+    }
+    Ok(Some((a, b)))
+}
+
+/// Apply `f` to the elements of both vectors, which must have the same
+/// dimensions, or return NULL if either is NULL
+fn distance<F>(function: &str, args: &[SQLiteValue], f: F) -> Result<SQLiteReturn, String>
+    where F: Fn(&[f64], &[f64]) -> Result<SQLiteReturn, String>
+{
+    match vector_pair(function, args)? {
+        Some((a, b)) => f(&a.to_f64(), &b.to_f64()),
+        None => Ok(SQLiteReturn::SQLiteNull)
+    }
+}
+
+/// Like `distance()`, but two q8 vectors with the same range are given to
+/// `q8` as sums of their codes instead
+fn code_distance<F, G>(function: &str, args: &[SQLiteValue], f: F, q8: G) -> Result<SQLiteReturn, String>
+    where F: Fn(&[f64], &[f64]) -> Result<SQLiteReturn, String>,
+          G: Fn(&CodeSums) -> Result<SQLiteReturn, String>
+{
+    match vector_pair(function, args)? {
+        Some((Vector::Q8 { min, max, codes: ref a }, Vector::Q8 { min: m, max: n, codes: ref b })) if (min, max) == (m, n) =>
+            q8(&CodeSums::new(min, max, a, b)),
+        Some((a, b)) => f(&a.to_f64(), &b.to_f64()),
+        None => Ok(SQLiteReturn::SQLiteNull)
+    }
+}
+
+/// `vec_dot(a, b)`: The dot product, Σ aᵢbᵢ
+pub fn vec_dot(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    code_distance("vec_dot", args, |a, b| Ok(dot(a, b).into()), |q| Ok(q.dot().into()))
+}
+
+/// `vec_cosine_similarity(a, b)`: a·b / (‖a‖‖b‖), from -1 to 1
+///
+/// NULL if either vector is all zeros, since it has no direction.
+pub fn vec_cosine_similarity(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    code_distance("vec_cosine_similarity", args,
+        |a, b| Ok(cosine_similarity(a, b).map_or(SQLiteReturn::SQLiteNull, SQLiteReturn::from)),
+        |q| Ok(q.cosine_similarity().map_or(SQLiteReturn::SQLiteNull, SQLiteReturn::from)))
+}
+
+/// `vec_cosine_distance(a, b)`: 1 - the cosine similarity, from 0 to 2
+pub fn vec_cosine_distance(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    code_distance("vec_cosine_distance", args,
+        |a, b| Ok(cosine_similarity(a, b).map_or(SQLiteReturn::SQLiteNull, |s| (1.0 - s).into())),
+        |q| Ok(q.cosine_similarity().map_or(SQLiteReturn::SQLiteNull, |s| (1.0 - s).into())))
+}
+
+/// `vec_l2(a, b)`: The Euclidean distance, √Σ (aᵢ - bᵢ)²
+pub fn vec_l2(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    code_distance("vec_l2", args, |a, b| Ok(l2_squared(a, b).sqrt().into()), |q| Ok(q.l2_squared().sqrt().into()))
+}
+
+/// `vec_l2_squared(a, b)`: Σ (aᵢ - bᵢ)², which orders vectors like
+/// `vec_l2()` without the square root
+pub fn vec_l2_squared(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    code_distance("vec_l2_squared", args, |a, b| Ok(l2_squared(a, b).into()), |q| Ok(q.l2_squared().into()))
+}
+
+/// `vec_l1(a, b)`: The Manhattan distance, Σ |aᵢ - bᵢ|
+pub fn vec_l1(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    distance("vec_l1", args, |a, b| Ok(l1(a, b).into()))
+}
+
+/// `vec_linf(a, b)`: The Chebyshev distance, max |aᵢ - bᵢ|
+pub fn vec_linf(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    distance("vec_linf", args, |a, b| Ok(linf(a, b).into()))
+}
+
+/// `vec_hamming(a, b)`: The number of elements that differ, usually of
+/// bit vectors
+pub fn vec_hamming(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    distance("vec_hamming", args, |a, b| Ok((hamming(a, b) as i64).into()))
+}
+
+/// `vec_jaccard(a, b)`: The Jaccard distance, from 0 to 1
+pub fn vec_jaccard(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    distance("vec_jaccard", args,
+        |a, b| Ok(jaccard(a, b).map_err(|e| format!("vec_jaccard(): {}", e))?.into()))
+}
@@ -0,0 +1,241 @@
+//! Rearranging and summarizing the elements of a vector
+//!
+//! These are mostly for post-processing model outputs, like picking the
+//! most likely classes from a vector of logits:
+//!
+//! ```sql
+//! SELECT id, vec_argmax(logits), vec_topk_indices(vec_softmax(logits), 5)
+//! FROM predictions;
+//! ```
+//!
+//! Positions count from 1, like the `idx` of `vec_each()`.
+//!
+//! - `vec_slice(v, start, end)` is the elements from position start to
+//!   position end, including both. Negative positions count back from the
+//!   end, so -1 is the last element. Positions past either end are
+//!   clamped, so the slice may be shorter than asked for, or empty.
+//! - `vec_concat(a, b, ...)` joins vectors end to end
+//! - `vec_sort(v)` sorts the elements from smallest to largest, with any
+//!   NaNs last
+//! - `vec_norm(v[, p])` is the p-norm, (Σ |xᵢ|ᵖ)^(1/p). Without p it's
+//!   the Euclidean length, p = 2, and an infinite p like `9e999` gives the
+//!   largest magnitude.
+//! - `vec_normalize(v)` divides a vector by its Euclidean length, so it
+//!   has length 1, or is NULL if the vector is all zeros
+//! - `vec_softmax(v)` is exp(xᵢ) / Σ exp(xⱼ), turning logits into
+//!   probabilities that add up to 1. If the largest element is infinite,
+//!   the elements equal to it share the probability equally.
+//! - `vec_argmax(v)` and `vec_argmin(v)` are the position of the largest
+//!   or smallest element, the first if there's a tie, or NULL if the
+//!   vector is empty
+//! - `vec_topk_indices(v, k)` is a JSON array of the positions of the k
+//!   largest elements, largest first, or all of them if there are fewer
+//!
+//! NaNs are never the largest or smallest element.
+//!
+//! Slices, sorts and joins keep the element type, or the widest one for
+//! joins (see [`arith`](../arith/index.html)). A q8 vector keeps its range
+//! and codes, unless it's joined to one with a different range, when the
+//! result is quantized again to cover both. `vec_normalize()` and
+//! `vec_softmax()` give f32 vectors for f32 vectors and f64 otherwise.
+use sqlite3_raw::*;
+use dynamics::*;
+use std::cmp::Ordering;
+use vector::{integer_arg, simd, type_name, vector_arg, ElementType, Vector};
+
+/// The elements at some positions, counting from 0, keeping the element
+/// type and a q8 vector's range
+fn pick(v: &Vector, positions: &[usize]) -> Vector {
+    match *v {
+        Vector::F32(ref x) => Vector::F32(positions.iter().map(|&i| x[i]).collect()),
+        Vector::F64(ref x) => Vector::F64(positions.iter().map(|&i| x[i]).collect()),
+        Vector::I8(ref x) => Vector::I8(positions.iter().map(|&i| x[i]).collect()),
+        Vector::Bit(ref x) => Vector::Bit(positions.iter().map(|&i| x[i]).collect()),
+        Vector::Q8 { min, max, ref codes } => Vector::Q8 { min, max, codes: positions.iter().map(|&i| codes[i]).collect() }
+    }
+}
+
+/// Order numbers with NaNs after everything else
+fn nans_last(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+/// The type for results that are rarely whole numbers
+fn float_type(ty: ElementType) -> ElementType {
+    if ty == ElementType::F32 { ElementType::F32 } else { ElementType::F64 }
+}
+
+/// `vec_slice(v, start, end)`: The elements from start to end, inclusive
+pub fn vec_slice(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let v = vector_arg("vec_slice", args[0])?.unwrap();
+    let start = integer_arg("vec_slice", args[1], "start")?;
+    let end = integer_arg("vec_slice", args[2], "end")?;
+    if start == 0 || end == 0 {
+        return Err("vec_slice(): positions count from 1, or back from -1 at the end".into());
+    }
+    // As offsets from 0, where the slice is from..to
+    let dims = v.dims() as i64;
+    let from = (if start < 0 { dims + start } else { start - 1 }).max(0).min(dims);
+    let to = (if end < 0 { dims + end + 1 } else { end }).max(from).min(dims);
+    let positions: Vec<usize> = (from as usize..to as usize).collect();
+    Ok(pick(&v, &positions).encode().into())
+}
+
+/// `vec_concat(a, b, ...)`: The vectors one after another
+pub fn vec_concat(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.is_empty() {
+        return Err("vec_concat(): expected at least one vector".into());
+    }
+    let mut vectors = Vec::with_capacity(args.len());
+    for &arg in args {
+        match vector_arg("vec_concat", arg)? {
+            Some(v) => vectors.push(v),
+            None => return Ok(SQLiteReturn::SQLiteNull)
+        }
+    }
+    let ty = vectors.iter().skip(1).fold(vectors[0].element_type(), |ty, v| ty.promote(v.element_type()));
+    // q8 vectors can share their codes if they share their range
+    if let Vector::Q8 { min, max, .. } = vectors[0] {
+        let shared: Option<Vec<Vec<i8>>> = vectors.iter().map(|v| match *v {
+            Vector::Q8 { min: m, max: n, ref codes } if (m, n) == (min, max) => Some(codes.clone()),
+            _ => None
+        }).collect();
+        if let Some(codes) = shared {
+            return Ok(Vector::Q8 { min, max, codes: codes.concat() }.encode().into());
+        }
+    }
+    let values: Vec<f64> = vectors.iter().flat_map(Vector::to_f64).collect();
+    Ok(Vector::from_f64(ty, &values).map_err(|e| format!("vec_concat(): {}", e))?.encode().into())
+}
+
+/// `vec_sort(v)`: The elements from smallest to largest
+pub fn vec_sort(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let v = match vector_arg("vec_sort", args[0])? {
+        Some(v) => v,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    let values = v.to_f64();
+    let mut positions: Vec<usize> = (0..values.len()).collect();
+    positions.sort_by(|&i, &j| nans_last(values[i], values[j]));
+    Ok(pick(&v, &positions).encode().into())
+}
+
+/// `vec_norm(v[, p])`: The p-norm, or the Euclidean length
+pub fn vec_norm(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let values = vector_arg("vec_norm", args[0])?.unwrap().to_f64();
+    let p = match args.get(1) {
+        None => 2.0,
+        Some(&arg) => match arg.value_type() {
+            SQLITE_INTEGER | SQLITE_FLOAT => f64::from(arg),
+            other => return Err(format!("vec_norm(): p should be a number, not {}", type_name(other)))
+        }
+    };
+    let magnitudes = values.iter().map(|x| x.abs());
+    let norm = if p == 2.0 {
+        simd::dot(&values, &values).sqrt()
+    } else if p == 1.0 {
+        magnitudes.sum()
+    } else if p == f64::INFINITY {
+        magnitudes.fold(0.0, f64::max)
+    } else if p > 0.0 {
+        magnitudes.map(|x| x.powf(p)).sum::<f64>().powf(1.0 / p)
+    } else {
+        return Err(format!("vec_norm(): p should be positive, not {}", p));
+    };
+    Ok(norm.into())
+}
+
+/// `vec_normalize(v)`: The vector scaled to length 1
+pub fn vec_normalize(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let v = match vector_arg("vec_normalize", args[0])? {
+        Some(v) => v,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    let values = v.to_f64();
+    let norm = simd::dot(&values, &values).sqrt();
+    if norm == 0.0 {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let unit: Vec<f64> = values.iter().map(|x| x / norm).collect();
+    let v = Vector::from_f64(float_type(v.element_type()), &unit).map_err(|e| format!("vec_normalize(): {}", e))?;
+    Ok(v.encode().into())
+}
+
+/// `vec_softmax(v)`: exp(xᵢ) / Σ exp(xⱼ)
+pub fn vec_softmax(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let v = match vector_arg("vec_softmax", args[0])? {
+        Some(v) => v,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    let values = v.to_f64();
+    let largest = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let probabilities: Vec<f64> = if largest.is_infinite() {
+        // The limit as the largest grows, where inf - inf would be NaN
+        let ties = values.iter().filter(|&&x| x == largest).count();
+        values.iter().map(|&x| if x == largest { 1.0 / ties as f64 } else { 0.0 }).collect()
+    } else {
+        // Subtracting the largest keeps exp() from overflowing, and doesn't
+        // change the result
+        let exps: Vec<f64> = values.iter().map(|x| (x - largest).exp()).collect();
+        let total: f64 = exps.iter().sum();
+        exps.iter().map(|x| x / total).collect()
+    };
+    let v = Vector::from_f64(float_type(v.element_type()), &probabilities).map_err(|e| format!("vec_softmax(): {}", e))?;
+    Ok(v.encode().into())
+}
+
+/// The position of the first element that's `better` than every other,
+/// counting from 1, ignoring NaNs
+fn best_position(values: &[f64], better: Ordering) -> Option<usize> {
+    let mut best: Option<usize> = None;
+    for (i, &x) in values.iter().enumerate() {
+        let beats = match best {
+            Some(b) => nans_last(x, values[b]) == better,
+            None => true
+        };
+        if !x.is_nan() && beats {
+            best = Some(i);
+        }
+    }
+    best.map(|i| i + 1)
+}
+
+/// `vec_argmax(v)`: The position of the largest element
+pub fn vec_argmax(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(match vector_arg("vec_argmax", args[0])? {
+        Some(v) => best_position(&v.to_f64(), Ordering::Greater).map_or(SQLiteReturn::SQLiteNull, |i| (i as i64).into()),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
+
+/// `vec_argmin(v)`: The position of the smallest element
+pub fn vec_argmin(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(match vector_arg("vec_argmin", args[0])? {
+        Some(v) => best_position(&v.to_f64(), Ordering::Less).map_or(SQLiteReturn::SQLiteNull, |i| (i as i64).into()),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
+
+/// `vec_topk_indices(v, k)`: A JSON array of the positions of the k
+/// largest elements, largest first
+pub fn vec_topk_indices(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let values = vector_arg("vec_topk_indices", args[0])?.unwrap().to_f64();
+    let k = integer_arg("vec_topk_indices", args[1], "k")?;
+    if k < 0 {
+        return Err("vec_topk_indices(): k can't be negative".into());
+    }
+    let mut positions: Vec<usize> = (0..values.len()).filter(|&i| !values[i].is_nan()).collect();
+    // The sort is stable, so ties stay in order
+    positions.sort_by(|&i, &j| nans_last(values[j], values[i]));
+    let top: Vec<String> = positions.iter().take(k as usize).map(|i| (i + 1).to_string()).collect();
+    Ok(format!("[{}]", top.join(",")).into())
+}
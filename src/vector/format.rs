@@ -0,0 +1,431 @@
+//! The vector BLOB format
+//!
+//! Every vector starts with an 8 byte header, so vectors can be told apart
+//! from other BLOBs and from each other:
+//!
+//! | bytes | contents                                                      |
+//! |-------|---------------------------------------------------------------|
+//! | 0-1   | The magic number, `XV` in ASCII                               |
+//! | 2     | The format version, currently 1                               |
+//! | 3     | The element type: 1 for f32, 2 for f64, 3 for i8, 4 for bit and 5 for q8 |
+//! | 4-7   | The number of dimensions, as a little-endian u32              |
+//!
+//! The elements follow, packed and little-endian. Bit vectors hold eight
+//! dimensions per byte, starting from the least significant bit, and the
+//! unused bits of the last byte are zero. Wherever elements are used as
+//! numbers, in arithmetic, distances or conversions, bits are 0 and 1.
+//!
+//! q8 vectors are quantized, see `vector::quantize`. Their elements start
+//! with the range they cover, as the f32 minimum then maximum, followed by
+//! one signed byte per element: -128 stands for the minimum, 127 for the
+//! maximum, and the other 254 values are evenly spaced between.
+//!
+//! Matrices have a 12 byte header, and their elements follow row by row,
+//! packed the same way as a vector's:
+//!
+//! | bytes | contents                                                      |
+//! |-------|---------------------------------------------------------------|
+//! | 0-1   | The magic number, `XM` in ASCII                               |
+//! | 2     | The format version, currently 1                               |
+//! | 3     | The element type, as for vectors                              |
+//! | 4-7   | The number of rows, as a little-endian u32                    |
+//! | 8-11  | The number of columns, as a little-endian u32                 |
+//!
+//! Vectors made by `extras.cpp` have no header at all. They hold doubles,
+//! or floats if it was built with `REDUCED_PRECISION`, and nothing in the
+//! BLOB says which, so `Vector::decode_legacy()` has to be told.
+
+/// The first two bytes of every vector
+pub const MAGIC: [u8; 2] = *b"XV";
+/// The newest format version, which is the one written
+pub const VERSION: u8 = 1;
+/// The length of the header before the elements
+pub const HEADER_LEN: usize = 8;
+/// The first two bytes of every matrix
+pub const MATRIX_MAGIC: [u8; 2] = *b"XM";
+/// The length of a matrix's header before the elements
+pub const MATRIX_HEADER_LEN: usize = 12;
+
+/// The type of every element in a vector
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ElementType {
+    F32,
+    F64,
+    I8,
+    Bit,
+    /// 8 bit integers standing for evenly spaced values in a range
+    Q8
+}
+impl ElementType {
+    fn code(self) -> u8 {
+        match self {
+            ElementType::F32 => 1,
+            ElementType::F64 => 2,
+            ElementType::I8 => 3,
+            ElementType::Bit => 4,
+            ElementType::Q8 => 5
+        }
+    }
+
+    fn from_code(code: u8) -> Option<ElementType> {
+        match code {
+            1 => Some(ElementType::F32),
+            2 => Some(ElementType::F64),
+            3 => Some(ElementType::I8),
+            4 => Some(ElementType::Bit),
+            5 => Some(ElementType::Q8),
+            _ => None
+        }
+    }
+
+    /// The name used in SQL, like `'f32'`
+    pub fn name(self) -> &'static str {
+        match self {
+            ElementType::F32 => "f32",
+            ElementType::F64 => "f64",
+            ElementType::I8 => "i8",
+            ElementType::Bit => "bit",
+            ElementType::Q8 => "q8"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<ElementType, String> {
+        match name {
+            "f32" => Ok(ElementType::F32),
+            "f64" => Ok(ElementType::F64),
+            "i8" => Ok(ElementType::I8),
+            "bit" => Ok(ElementType::Bit),
+            "q8" => Ok(ElementType::Q8),
+            _ => Err(format!("unknown element type '{}', expected f32, f64, i8, bit or q8", name))
+        }
+    }
+
+    /// The narrower of two types that can hold every value of both
+    ///
+    /// Bits are 0 or 1, which fit in i8, which fit in f32, which fit in f64.
+    /// q8 vectors can hold anything by choosing their range, but lose
+    /// precision, so they come between i8 and f32.
+    pub fn promote(self, other: ElementType) -> ElementType {
+        fn rank(ty: ElementType) -> u8 {
+            match ty {
+                ElementType::Bit => 0,
+                ElementType::I8 => 1,
+                ElementType::Q8 => 2,
+                ElementType::F32 => 3,
+                ElementType::F64 => 4
+            }
+        }
+        if rank(other) > rank(self) { other } else { self }
+    }
+
+    /// The number of bytes needed for this many elements, or None if
+    /// that's too many to count
+    pub fn data_len(self, dims: usize) -> Option<usize> {
+        match self {
+            ElementType::F32 => dims.checked_mul(4),
+            ElementType::F64 => dims.checked_mul(8),
+            ElementType::I8 => Some(dims),
+            ElementType::Bit => Some(dims.div_ceil(8)),
+            ElementType::Q8 => dims.checked_add(8)
+        }
+    }
+}
+
+/// A decoded vector
+#[derive(Clone, PartialEq, Debug)]
+pub enum Vector {
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+    I8(Vec<i8>),
+    Bit(Vec<bool>),
+    Q8 { min: f32, max: f32, codes: Vec<i8> }
+}
+impl Vector {
+    pub fn element_type(&self) -> ElementType {
+        match *self {
+            Vector::F32(_) => ElementType::F32,
+            Vector::F64(_) => ElementType::F64,
+            Vector::I8(_) => ElementType::I8,
+            Vector::Bit(_) => ElementType::Bit,
+            Vector::Q8 { .. } => ElementType::Q8
+        }
+    }
+
+    /// The number of elements
+    pub fn dims(&self) -> usize {
+        match *self {
+            Vector::F32(ref v) => v.len(),
+            Vector::F64(ref v) => v.len(),
+            Vector::I8(ref v) => v.len(),
+            Vector::Bit(ref v) => v.len(),
+            Vector::Q8 { ref codes, .. } => codes.len()
+        }
+    }
+
+    /// Every element as a double, with bits as 0 or 1 and quantized
+    /// elements as the values they stand for
+    pub fn to_f64(&self) -> Vec<f64> {
+        match *self {
+            Vector::F32(ref v) => v.iter().map(|&x| f64::from(x)).collect(),
+            Vector::F64(ref v) => v.clone(),
+            Vector::I8(ref v) => v.iter().map(|&x| f64::from(x)).collect(),
+            Vector::Bit(ref v) => v.iter().map(|&x| if x { 1.0 } else { 0.0 }).collect(),
+            Vector::Q8 { min, max, ref codes } => {
+                let (min, step) = (f64::from(min), (f64::from(max) - f64::from(min)) / 255.0);
+                codes.iter().map(|&c| min + step * (f64::from(c) + 128.0)).collect()
+            }
+        }
+    }
+
+    /// Make a vector of any element type from doubles
+    ///
+    /// i8 elements must be whole numbers from -128 to 127 and bits must be
+    /// 0 or 1, so nothing is silently rounded or clamped. q8 elements are
+    /// quantized to the range from the smallest value to the largest.
+    pub fn from_f64(ty: ElementType, values: &[f64]) -> Result<Vector, String> {
+        if values.len() > u32::MAX as usize {
+            return Err(format!("vectors can't have more than {} dimensions", u32::MAX));
+        }
+        let invalid = |i: usize| Err(format!("element {} is {}, which isn't {} {}",
+            i + 1, values[i], if ty == ElementType::I8 { "an" } else { "a" }, ty.name()));
+        Ok(match ty {
+            ElementType::F32 => Vector::F32(values.iter().map(|&x| x as f32).collect()),
+            ElementType::F64 => Vector::F64(values.to_vec()),
+            ElementType::I8 => {
+                if let Some(i) = values.iter().position(|&x| x.fract() != 0.0 || !(-128.0..=127.0).contains(&x)) {
+                    return invalid(i);
+                }
+                Vector::I8(values.iter().map(|&x| x as i8).collect())
+            },
+            ElementType::Bit => {
+                if let Some(i) = values.iter().position(|&x| x != 0.0 && x != 1.0) {
+                    return invalid(i);
+                }
+                Vector::Bit(values.iter().map(|&x| x == 1.0).collect())
+            },
+            ElementType::Q8 => {
+                if let Some(i) = values.iter().position(|x| !x.is_finite()) {
+                    return Err(format!("element {} is {}, which can't be quantized", i + 1, values[i]));
+                }
+                let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+                let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                if values.is_empty() {
+                    Vector::Q8 { min: 0.0, max: 0.0, codes: vec![] }
+                } else {
+                    // Round the range outwards so it still covers every value
+                    let (low, high) = (min as f32, max as f32);
+                    let low = if f64::from(low) > min { next_down(low) } else { low };
+                    let high = if f64::from(high) < max { next_up(high) } else { high };
+                    Vector::quantize(values, low, high)?
+                }
+            }
+        })
+    }
+
+    /// Quantize values to q8 elements covering a range, clamping any
+    /// outside it to the nearest end
+    pub fn quantize(values: &[f64], min: f32, max: f32) -> Result<Vector, String> {
+        if !(min.is_finite() && max.is_finite() && min <= max) {
+            return Err(format!("can't quantize to the range from {} to {}", min, max));
+        }
+        if let Some(i) = values.iter().position(|x| x.is_nan()) {
+            return Err(format!("element {} is NaN, which can't be quantized", i + 1));
+        }
+        let (low, high) = (f64::from(min), f64::from(max));
+        let codes = values.iter().map(|&x| {
+            if high == low {
+                return -128;
+            }
+            let code = ((x.max(low).min(high) - low) / (high - low) * 255.0).round();
+            (code as i32 - 128) as i8
+        }).collect();
+        Ok(Vector::Q8 { min, max, codes })
+    }
+
+    /// Write the vector in the current format
+    pub fn encode(&self) -> Vec<u8> {
+        let ty = self.element_type();
+        let mut blob = Vec::with_capacity(HEADER_LEN + ty.data_len(self.dims()).unwrap_or(0));
+        blob.extend_from_slice(&MAGIC);
+        blob.push(VERSION);
+        blob.push(ty.code());
+        blob.extend_from_slice(&(self.dims() as u32).to_le_bytes());
+        self.encode_data(&mut blob);
+        blob
+    }
+
+    /// Write just the elements
+    fn encode_data(&self, blob: &mut Vec<u8>) {
+        match *self {
+            Vector::F32(ref v) => for x in v { blob.extend_from_slice(&x.to_le_bytes()) },
+            Vector::F64(ref v) => for x in v { blob.extend_from_slice(&x.to_le_bytes()) },
+            Vector::I8(ref v) => blob.extend(v.iter().map(|&x| x as u8)),
+            Vector::Bit(ref v) => blob.extend(v.chunks(8).map(|byte| byte.iter()
+                .enumerate()
+                .fold(0u8, |packed, (i, &bit)| packed | ((bit as u8) << i)))),
+            Vector::Q8 { min, max, ref codes } => {
+                blob.extend_from_slice(&min.to_le_bytes());
+                blob.extend_from_slice(&max.to_le_bytes());
+                blob.extend(codes.iter().map(|&x| x as u8));
+            }
+        }
+    }
+
+    /// Read a vector with a header
+    pub fn decode(blob: &[u8]) -> Result<Vector, String> {
+        if blob.len() >= 2 && blob[..2] == MATRIX_MAGIC {
+            return Err("expected a vector, not a matrix".into());
+        }
+        if blob.len() < HEADER_LEN || blob[..2] != MAGIC {
+            return Err("not a vector (vectors from before the header was added can be \
+                read with vec_import())".into());
+        }
+        if blob[2] != VERSION {
+            return Err(format!("vector format version {} isn't supported, only version {}",
+                blob[2], VERSION));
+        }
+        let ty = match ElementType::from_code(blob[3]) {
+            Some(ty) => ty,
+            None => return Err(format!("unknown vector element type {}", blob[3]))
+        };
+        let dims = u32::from_le_bytes([blob[4], blob[5], blob[6], blob[7]]) as usize;
+        Vector::decode_data(ty, dims, &blob[HEADER_LEN..]).map_err(|e| format!("corrupt vector: {}", e))
+    }
+
+    /// Read the elements after a header, checking their length
+    fn decode_data(ty: ElementType, dims: usize, data: &[u8]) -> Result<Vector, String> {
+        match ty.data_len(dims) {
+            Some(len) if len == data.len() => (),
+            Some(len) => return Err(format!("{} {} elements need {} bytes, not {}",
+                dims, ty.name(), len, data.len())),
+            None => return Err(format!("{} {} elements are too many to fit in memory", dims, ty.name()))
+        }
+        let v = Vector::from_data(ty, dims, data);
+        if let Vector::Q8 { min, max, .. } = v {
+            if !(min.is_finite() && max.is_finite() && min <= max) {
+                return Err(format!("the q8 range from {} to {} isn't valid", min, max));
+            }
+        }
+        Ok(v)
+    }
+
+    /// Read a vector without a header, as written by `extras.cpp`
+    pub fn decode_legacy(blob: &[u8], ty: ElementType) -> Result<Vector, String> {
+        let dims = match ty {
+            ElementType::Q8 => return Err("q8 vectors always have a header".into()),
+            ElementType::Bit => blob.len() * 8,
+            _ => blob.len() / ty.data_len(1).unwrap()
+        };
+        if ty.data_len(dims) != Some(blob.len()) {
+            return Err(format!("{} bytes can't hold a whole number of {} elements",
+                blob.len(), ty.name()));
+        }
+        Ok(Vector::from_data(ty, dims, blob))
+    }
+
+    /// Unpack elements whose length has already been checked
+    fn from_data(ty: ElementType, dims: usize, data: &[u8]) -> Vector {
+        match ty {
+            ElementType::F32 => Vector::F32(data.chunks(4)
+                .map(|x| f32::from_le_bytes([x[0], x[1], x[2], x[3]]))
+                .collect()),
+            ElementType::F64 => Vector::F64(data.chunks(8)
+                .map(|x| f64::from_le_bytes([x[0], x[1], x[2], x[3], x[4], x[5], x[6], x[7]]))
+                .collect()),
+            ElementType::I8 => Vector::I8(data.iter().map(|&x| x as i8).collect()),
+            ElementType::Bit => Vector::Bit((0..dims)
+                .map(|i| data[i / 8] & (1 << (i % 8)) != 0)
+                .collect()),
+            ElementType::Q8 => Vector::Q8 {
+                min: f32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                max: f32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+                codes: data[8..].iter().map(|&x| x as i8).collect()
+            }
+        }
+    }
+}
+
+/// A decoded matrix, whose elements are kept row by row in a vector
+#[derive(Clone, PartialEq, Debug)]
+pub struct Matrix {
+    pub rows: usize,
+    pub cols: usize,
+    pub elements: Vector
+}
+impl Matrix {
+    /// Make a matrix from its elements as doubles, row by row
+    pub fn from_f64(ty: ElementType, rows: usize, cols: usize, values: &[f64]) -> Result<Matrix, String> {
+        if rows > u32::MAX as usize || cols > u32::MAX as usize {
+            return Err(format!("matrices can't have more than {} rows or columns", u32::MAX));
+        }
+        if rows.checked_mul(cols) != Some(values.len()) {
+            return Err(format!("a {}x{} matrix can't have {} elements", rows, cols, values.len()));
+        }
+        Ok(Matrix { rows, cols, elements: Vector::from_f64(ty, values)? })
+    }
+
+    pub fn element_type(&self) -> ElementType {
+        self.elements.element_type()
+    }
+
+    /// Every element as a double, row by row
+    pub fn to_f64(&self) -> Vec<f64> {
+        self.elements.to_f64()
+    }
+
+    /// The size, like `2x3` for 2 rows and 3 columns
+    pub fn shape(&self) -> String {
+        format!("{}x{}", self.rows, self.cols)
+    }
+
+    /// Write the matrix in the current format
+    pub fn encode(&self) -> Vec<u8> {
+        let ty = self.element_type();
+        let mut blob = Vec::with_capacity(MATRIX_HEADER_LEN + ty.data_len(self.rows * self.cols).unwrap_or(0));
+        blob.extend_from_slice(&MATRIX_MAGIC);
+        blob.push(VERSION);
+        blob.push(ty.code());
+        blob.extend_from_slice(&(self.rows as u32).to_le_bytes());
+        blob.extend_from_slice(&(self.cols as u32).to_le_bytes());
+        self.elements.encode_data(&mut blob);
+        blob
+    }
+
+    pub fn decode(blob: &[u8]) -> Result<Matrix, String> {
+        if blob.len() >= 2 && blob[..2] == MAGIC {
+            return Err("expected a matrix, not a vector".into());
+        }
+        if blob.len() < MATRIX_HEADER_LEN || blob[..2] != MATRIX_MAGIC {
+            return Err("not a matrix".into());
+        }
+        if blob[2] != VERSION {
+            return Err(format!("matrix format version {} isn't supported, only version {}",
+                blob[2], VERSION));
+        }
+        let ty = match ElementType::from_code(blob[3]) {
+            Some(ty) => ty,
+            None => return Err(format!("unknown matrix element type {}", blob[3]))
+        };
+        let rows = u32::from_le_bytes([blob[4], blob[5], blob[6], blob[7]]) as usize;
+        let cols = u32::from_le_bytes([blob[8], blob[9], blob[10], blob[11]]) as usize;
+        let len = match rows.checked_mul(cols) {
+            Some(len) => len,
+            None => return Err(format!("corrupt matrix: {}x{} elements are too many to fit in memory", rows, cols))
+        };
+        let elements = Vector::decode_data(ty, len, &blob[MATRIX_HEADER_LEN..])
+            .map_err(|e| format!("corrupt matrix: {}", e))?;
+        Ok(Matrix { rows, cols, elements })
+    }
+}
+
+/// The largest f32 below a finite one
+fn next_down(x: f32) -> f32 {
+    if x == 0.0 { -f32::from_bits(1) }
+    else if x > 0.0 { f32::from_bits(x.to_bits() - 1) }
+    else { f32::from_bits(x.to_bits() + 1) }
+}
+
+/// The smallest f32 above a finite one
+fn next_up(x: f32) -> f32 {
+    -next_down(-x)
+}
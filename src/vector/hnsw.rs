@@ -0,0 +1,276 @@
+//! Hierarchical navigable small world graphs
+//!
+//! An HNSW graph finds approximate nearest neighbours without comparing the
+//! query to every vector, following Malkov and Yashunin's ["Efficient and
+//! robust approximate nearest neighbor search using Hierarchical Navigable
+//! Small World graphs"](https://arxiv.org/abs/1603.09320). Every node is on
+//! layer 0, and each layer above holds a random fraction of the layer below.
+//! A search starts at the top and greedily walks towards the query, layer
+//! by layer, finishing with a wider search of layer 0.
+//!
+//! Nodes are identified by rowid. A node's level comes from a hash of its
+//! rowid, so rebuilding a graph from the same rows in the same order gives
+//! the same graph. This module only keeps the graph in memory;
+//! `virtual_table::vec_hnsw` stores it in tables.
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+use random::SplitMix64;
+use vector::distance::{Metric, Neighbour};
+
+/// Levels above this are so unlikely that they're never used
+const MAX_LEVEL: usize = 16;
+
+/// How a graph is built and searched
+#[derive(Clone, Debug)]
+pub struct Params {
+    pub dims: usize,
+    pub metric: Metric,
+    /// How many neighbours each node links to on each layer above 0, and
+    /// half as many as on layer 0
+    pub m: usize,
+    /// How many candidates to consider when linking a new node
+    pub ef_construction: usize,
+    /// How many candidates to consider when searching, at least k
+    pub ef_search: usize
+}
+
+pub struct Node {
+    pub vector: Vec<f64>,
+    /// The rowids of the node's neighbours on each layer, from 0 up to its
+    /// level
+    pub links: Vec<Vec<i64>>
+}
+impl Node {
+    pub fn level(&self) -> usize {
+        self.links.len() - 1
+    }
+}
+
+pub struct Hnsw {
+    pub params: Params,
+    nodes: HashMap<i64, Node>,
+    /// Every node by level, highest first, then by rowid, so the first is
+    /// where searches start and the next takes over if it's removed
+    entries: BTreeSet<(Reverse<usize>, i64)>
+}
+impl Hnsw {
+    pub fn new(params: Params) -> Hnsw {
+        Hnsw { params, nodes: HashMap::new(), entries: BTreeSet::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: i64) -> Option<&Node> {
+        self.nodes.get(&id)
+    }
+
+    /// Every rowid in the graph, in order
+    pub fn ids(&self) -> Vec<i64> {
+        let mut ids: Vec<i64> = self.nodes.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// The level a node with this rowid is on
+    pub fn level_for(&self, id: i64) -> usize {
+        // The chance of reaching each level is 1/m of the one below
+        let u = SplitMix64::for_row(0, id).next_f64();
+        let level = -(1.0 - u).ln() / (self.params.m as f64).ln();
+        (level as usize).min(MAX_LEVEL)
+    }
+
+    /// Check a vector can go in the graph or be searched for, which it
+    /// can't if the metric doesn't give it a distance, like the cosine
+    /// distance of a zero vector
+    pub fn check(&self, vector: &[f64]) -> Result<bool, String> {
+        if vector.len() != self.params.dims {
+            return Err(format!("the vector has {} dimensions but the index has {}",
+                vector.len(), self.params.dims));
+        }
+        if vector.iter().any(|x| !x.is_finite()) {
+            return Err("the vector has an element that isn't a finite number".into());
+        }
+        Ok(self.params.metric.distance(vector, vector)?.is_some())
+    }
+
+    /// Add a node with its links as they were stored
+    pub fn load(&mut self, id: i64, node: Node) {
+        self.entries.insert((Reverse(node.level()), id));
+        self.nodes.insert(id, node);
+    }
+
+    /// Link a new node into the graph, which must not have its rowid yet
+    ///
+    /// Returns the rowid and layer of every list of links that changed.
+    pub fn insert(&mut self, id: i64, vector: Vec<f64>) -> Vec<(i64, usize)> {
+        let level = self.level_for(id);
+        let mut links = vec![vec![]; level + 1];
+        if let Some(entry) = self.entry() {
+            let top = self.nodes[&entry].level();
+            let mut nearest = vec![self.neighbour(&vector, entry)];
+            for layer in (level + 1..=top).rev() {
+                nearest = self.search_layer(&vector, &nearest, 1, layer);
+            }
+            for layer in (0..=level.min(top)).rev() {
+                nearest = self.search_layer(&vector, &nearest, self.params.ef_construction, layer);
+                links[layer] = self.select(&nearest, self.params.m);
+            }
+        }
+        let mut changed: Vec<(i64, usize)> = (0..=level).map(|layer| (id, layer)).collect();
+        for (layer, neighbours) in links.iter().enumerate() {
+            for &n in neighbours {
+                self.nodes.get_mut(&n).unwrap().links[layer].push(id);
+                changed.push((n, layer));
+            }
+        }
+        self.nodes.insert(id, Node { vector, links: links.clone() });
+        for (layer, neighbours) in links.iter().enumerate() {
+            for &n in neighbours {
+                if self.nodes[&n].links[layer].len() > self.max_links(layer) {
+                    let kept = self.relink(n, layer, &[]);
+                    self.nodes.get_mut(&n).unwrap().links[layer] = kept;
+                }
+            }
+        }
+        self.entries.insert((Reverse(level), id));
+        changed
+    }
+
+    /// Unlink a node and remove it, if it's in the graph
+    ///
+    /// Its neighbours are relinked to each other so the graph stays
+    /// connected. Nodes which linked to it without it linking back keep
+    /// their links, which searches skip, until their links next change.
+    /// Returns the rowid and layer of every other list of links that
+    /// changed.
+    pub fn remove(&mut self, id: i64) -> Option<Vec<(i64, usize)>> {
+        let node = self.nodes.remove(&id)?;
+        self.entries.remove(&(Reverse(node.level()), id));
+        let mut changed = vec![];
+        for (layer, neighbours) in node.links.iter().enumerate() {
+            for &n in neighbours {
+                if self.has_layer(n, layer) {
+                    let kept = self.relink(n, layer, neighbours);
+                    self.nodes.get_mut(&n).unwrap().links[layer] = kept;
+                    changed.push((n, layer));
+                }
+            }
+        }
+        Some(changed)
+    }
+
+    /// The k nodes nearest the query, nearest first
+    pub fn search(&self, query: &[f64], k: usize) -> Vec<Neighbour> {
+        let entry = match self.entry() {
+            Some(entry) if k > 0 => entry,
+            _ => return vec![]
+        };
+        let mut nearest = vec![self.neighbour(query, entry)];
+        for layer in (1..=self.nodes[&entry].level()).rev() {
+            nearest = self.search_layer(query, &nearest, 1, layer);
+        }
+        let mut nearest = self.search_layer(query, &nearest, self.params.ef_search.max(k), 0);
+        nearest.truncate(k);
+        nearest
+    }
+
+    /// Where searches start: the node with the highest level, and the
+    /// smallest rowid of those
+    fn entry(&self) -> Option<i64> {
+        self.entries.iter().next().map(|&(_, id)| id)
+    }
+
+    /// Whether a node is in the graph and reaches a layer
+    fn has_layer(&self, id: i64, layer: usize) -> bool {
+        self.nodes.get(&id).is_some_and(|x| x.level() >= layer)
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 { 2 * self.params.m } else { self.params.m }
+    }
+
+    /// The distance from a vector to a node, where infinity means the
+    /// metric can't compare them
+    fn neighbour(&self, vector: &[f64], id: i64) -> Neighbour {
+        let distance = match self.params.metric.distance(vector, &self.nodes[&id].vector) {
+            Ok(Some(distance)) => distance,
+            _ => f64::INFINITY
+        };
+        Neighbour { distance, rowid: id }
+    }
+
+    /// The `ef` nodes on a layer nearest the query that can be found from
+    /// the nodes `start`, nearest first
+    fn search_layer(&self, query: &[f64], start: &[Neighbour], ef: usize, layer: usize) -> Vec<Neighbour> {
+        let mut visited: HashSet<i64> = start.iter().map(|n| n.rowid).collect();
+        let mut candidates: BinaryHeap<Reverse<Neighbour>> = start.iter().map(|&n| Reverse(n)).collect();
+        // The furthest of the nearest so far is on top, to be replaced first
+        let mut nearest: BinaryHeap<Neighbour> = start.iter().cloned().collect();
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            if nearest.len() >= ef && nearest.peek().is_some_and(|f| candidate.distance > f.distance) {
+                break;
+            }
+            for &n in &self.nodes[&candidate.rowid].links[layer] {
+                // Skip links to removed nodes, or to nodes that reused their rowid
+                if !visited.insert(n) || !self.has_layer(n, layer) {
+                    continue;
+                }
+                let next = self.neighbour(query, n);
+                if nearest.len() < ef || nearest.peek().is_some_and(|f| next < *f) {
+                    candidates.push(Reverse(next));
+                    nearest.push(next);
+                    if nearest.len() > ef {
+                        nearest.pop();
+                    }
+                }
+            }
+        }
+        nearest.into_sorted_vec()
+    }
+
+    /// Choose up to m of the candidates, which are sorted by distance from
+    /// some node, as that node's links
+    ///
+    /// This is the paper's heuristic: a candidate is skipped if it's nearer
+    /// one already chosen than the node, since the chosen one leads to it,
+    /// so the links spread out in every direction. Skipped candidates are
+    /// only used if there are too few others.
+    fn select(&self, candidates: &[Neighbour], m: usize) -> Vec<i64> {
+        let mut chosen: Vec<i64> = vec![];
+        let mut skipped = vec![];
+        for c in candidates {
+            if chosen.len() >= m {
+                break;
+            }
+            let vector = &self.nodes[&c.rowid].vector;
+            if chosen.iter().all(|&s| self.neighbour(vector, s).distance > c.distance) {
+                chosen.push(c.rowid);
+            } else {
+                skipped.push(c.rowid);
+            }
+        }
+        let room = m - chosen.len();
+        chosen.extend(skipped.into_iter().take(room));
+        chosen
+    }
+
+    /// Choose new links for a node on a layer from its current links and
+    /// `extra`, leaving out missing nodes
+    fn relink(&self, id: i64, layer: usize, extra: &[i64]) -> Vec<i64> {
+        let node = &self.nodes[&id];
+        let mut ids: Vec<i64> = node.links[layer].iter().chain(extra).cloned()
+            .filter(|&n| n != id && self.has_layer(n, layer))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        let mut candidates: Vec<Neighbour> = ids.into_iter().map(|n| self.neighbour(&node.vector, n)).collect();
+        candidates.sort();
+        self.select(&candidates, self.max_links(layer))
+    }
+}
@@ -0,0 +1,224 @@
+//! k-means clustering
+//!
+//! `vec_kmeans(v, k[, iterations, seed])` is an aggregate that clusters a
+//! group's vectors into k clusters, giving their centroids as the rows of
+//! a matrix (see [`matrix`](../matrix/index.html)).
+//! `vec_nearest_centroid(v, centroids)` is the row of the centroid nearest
+//! a vector, counting from 1, which makes a cluster id:
+//!
+//! ```sql
+//! -- Partition the documents into 256 lists, like an IVF index
+//! CREATE TABLE clusters AS SELECT vec_kmeans(embedding, 256, 20, 42) AS centroids FROM docs;
+//! UPDATE docs SET list = vec_nearest_centroid(embedding, (SELECT centroids FROM clusters));
+//! ```
+//!
+//! The first centroids are chosen by k-means++, which picks vectors far
+//! from those already chosen. Then Lloyd's algorithm moves each centroid
+//! to the mean of its cluster, until no vector changes cluster or it has
+//! run `iterations` times (20 unless given). A cluster left empty takes
+//! the vector farthest from its centroid. The seed (0 unless given) picks
+//! the first centroids, so the same rows in the same order with the same
+//! seed always give the same centroids.
+//!
+//! Distances are Euclidean. There are fewer than k centroids if the group
+//! has fewer than k distinct vectors. NULL rows are skipped, a group
+//! without any vectors gives NULL, and every vector must have the same
+//! number of dimensions. The centroids are f32 if every vector is, and
+//! f64 otherwise.
+use sqlite3_raw::*;
+use aggregate::Aggregate;
+use dynamics::*;
+use random::SplitMix64;
+use std::collections::VecDeque;
+use vector::{integer_arg, simd, vector_arg, ElementType, Matrix};
+use vector::matrix::matrix_arg;
+
+/// The arguments after the vector
+struct Settings {
+    k: usize,
+    iterations: usize,
+    seed: i64
+}
+impl Settings {
+    fn new(args: &[SQLiteValue]) -> Result<Settings, String> {
+        let k = integer_arg("vec_kmeans", args[1], "k")?;
+        let iterations = match args.get(2) {
+            Some(&arg) => integer_arg("vec_kmeans", arg, "iterations")?,
+            None => 20
+        };
+        let seed = match args.get(3) {
+            Some(&arg) => integer_arg("vec_kmeans", arg, "seed")?,
+            None => 0
+        };
+        if k < 1 {
+            return Err(format!("vec_kmeans(): k should be at least 1, not {}", k));
+        }
+        if iterations < 0 {
+            return Err("vec_kmeans(): iterations can't be negative".into());
+        }
+        Ok(Settings { k: k as usize, iterations: iterations as usize, seed })
+    }
+}
+
+/// The first centroid nearest a point, or None if no distance to one is
+/// a number
+fn nearest<C: AsRef<[f64]>>(centroids: &[C], point: &[f64]) -> Option<usize> {
+    let mut best: Option<(usize, f64)> = None;
+    for (i, centroid) in centroids.iter().enumerate() {
+        let distance = simd::l2_squared(centroid.as_ref(), point);
+        if best.map_or(!distance.is_nan(), |(_, shortest)| distance < shortest) {
+            best = Some((i, distance));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+/// Choose up to k distinct points as the first centroids with k-means++,
+/// where each point is chosen with probability proportional to its
+/// squared distance from the nearest centroid so far
+fn initial_centroids(points: &[&[f64]], k: usize, rng: &mut SplitMix64) -> Vec<Vec<f64>> {
+    let first = (rng.next_u64() % points.len() as u64) as usize;
+    let mut centroids = vec![points[first].to_vec()];
+    let mut distances: Vec<f64> = points.iter().map(|p| simd::l2_squared(p, points[first])).collect();
+    while centroids.len() < k {
+        let total: f64 = distances.iter().sum();
+        // Every point is already a centroid
+        if total == 0.0 {
+            break;
+        }
+        let mut target = rng.next_f64() * total;
+        let chosen = distances.iter().position(|&d| { target -= d; target < 0.0 })
+            // Rounding can leave a little of the total over
+            .or_else(|| distances.iter().rposition(|&d| d > 0.0))
+            .unwrap();
+        centroids.push(points[chosen].to_vec());
+        for (d, p) in distances.iter_mut().zip(points) {
+            *d = d.min(simd::l2_squared(p, points[chosen]));
+        }
+    }
+    centroids
+}
+
+/// The centroids of at most k clusters of points
+fn cluster(points: &[&[f64]], settings: &Settings) -> Vec<Vec<f64>> {
+    let mut rng = SplitMix64::new(settings.seed);
+    let mut centroids = initial_centroids(points, settings.k, &mut rng);
+    let dims = points[0].len();
+    let mut clusters = vec![usize::MAX; points.len()];
+    for _ in 0..settings.iterations {
+        let mut changed = false;
+        for (point, cluster) in points.iter().zip(clusters.iter_mut()) {
+            let nearest = nearest(&centroids, point).unwrap_or(0);
+            changed |= nearest != *cluster;
+            *cluster = nearest;
+        }
+        if !changed {
+            break;
+        }
+        let mut sums = vec![vec![0.0; dims]; centroids.len()];
+        let mut counts = vec![0usize; centroids.len()];
+        for (point, &c) in points.iter().zip(&clusters) {
+            for (sum, x) in sums[c].iter_mut().zip(point.iter()) {
+                *sum += x;
+            }
+            counts[c] += 1;
+        }
+        for ((centroid, sum), &count) in centroids.iter_mut().zip(sums).zip(&counts) {
+            if count > 0 {
+                *centroid = sum.iter().map(|x| x / count as f64).collect();
+            }
+        }
+        // An empty cluster takes the point farthest from its centroid, from
+        // a cluster that won't be left empty in turn
+        for c in 0..centroids.len() {
+            if counts[c] > 0 {
+                continue;
+            }
+            let farthest = (0..points.len())
+                .filter(|&i| counts[clusters[i]] > 1)
+                .map(|i| (i, simd::l2_squared(points[i], &centroids[clusters[i]])))
+                .fold(None, |best: Option<(usize, f64)>, (i, d)| match best {
+                    Some((_, farthest)) if farthest >= d => best,
+                    _ => Some((i, d))
+                });
+            if let Some((i, _)) = farthest {
+                counts[clusters[i]] -= 1;
+                clusters[i] = c;
+                counts[c] = 1;
+                centroids[c] = points[i].to_vec();
+            }
+        }
+    }
+    centroids
+}
+
+/// `vec_kmeans(v, k[, iterations, seed])`: The centroids of k clusters of
+/// the vectors, as the rows of a matrix
+#[derive(Default)]
+pub struct VecKmeans {
+    settings: Option<Settings>,
+    /// Each vector, and whether it was f32
+    vectors: VecDeque<(Vec<f64>, bool)>
+}
+impl Aggregate for VecKmeans {
+    fn step(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        if self.settings.is_none() {
+            self.settings = Some(Settings::new(args)?);
+        }
+        let v = match vector_arg("vec_kmeans", args[0])? {
+            Some(v) => v,
+            None => return Ok(())
+        };
+        if let Some(first) = self.vectors.front().map(|entry| &entry.0) {
+            if first.len() != v.dims() {
+                return Err(format!("vec_kmeans(): every vector must have {} dimensions like the first, not {}",
+                    first.len(), v.dims()));
+            }
+        }
+        let values = v.to_f64();
+        if let Some(i) = values.iter().position(|x| !x.is_finite()) {
+            return Err(format!("vec_kmeans(): element {} is {}, which can't be clustered", i + 1, values[i]));
+        }
+        self.vectors.push_back((values, v.element_type() == ElementType::F32));
+        Ok(())
+    }
+    fn inverse(&mut self, args: &[SQLiteValue]) -> Result<(), String> {
+        // NULL rows were never added
+        if !args[0].is_null() {
+            self.vectors.pop_front();
+        }
+        Ok(())
+    }
+    fn value(&self) -> Result<SQLiteReturn, String> {
+        let settings = match self.settings {
+            Some(ref settings) if !self.vectors.is_empty() => settings,
+            _ => return Ok(SQLiteReturn::SQLiteNull)
+        };
+        let points: Vec<&[f64]> = self.vectors.iter().map(|entry| &entry.0[..]).collect();
+        let centroids = cluster(&points, settings);
+        let ty = if self.vectors.iter().all(|&(_, is_f32)| is_f32) { ElementType::F32 } else { ElementType::F64 };
+        let values: Vec<f64> = centroids.concat();
+        let m = Matrix::from_f64(ty, centroids.len(), points[0].len(), &values)
+            .map_err(|e| format!("vec_kmeans(): {}", e))?;
+        Ok(m.encode().into())
+    }
+}
+
+/// `vec_nearest_centroid(v, centroids)`: The row of the nearest centroid,
+/// counting from 1
+pub fn vec_nearest_centroid(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let (v, m) = match (vector_arg("vec_nearest_centroid", args[0])?, matrix_arg("vec_nearest_centroid", args[1])?) {
+        (Some(v), Some(m)) => (v, m),
+        _ => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    if v.dims() != m.cols {
+        return Err(format!("vec_nearest_centroid(): the vector has {} dimensions but the centroids have {}",
+            v.dims(), m.cols));
+    }
+    let values = m.to_f64();
+    let centroids: Vec<&[f64]> = (0..m.rows).map(|r| &values[r * m.cols..(r + 1) * m.cols]).collect();
+    Ok(match nearest(&centroids, &v.to_f64()) {
+        Some(i) => (i as i64 + 1).into(),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
@@ -0,0 +1,324 @@
+//! Matrices and linear algebra
+//!
+//! Matrices are BLOBs like vectors, with a header giving the element type
+//! and the number of rows and columns, see [`format`](../format/index.html).
+//! They are written in JSON as an array of rows:
+//!
+//! ```sql
+//! -- Project every embedding with a learned 64x768 matrix
+//! UPDATE docs SET small = mat_vec_mul(:projection, embedding);
+//! SELECT mat_to_json(mat_mul(mat_from_json('[[1,2],[3,4]]'), mat_identity(2)));
+//! ```
+//!
+//! - `mat_from_json(json[, type])` and `mat_to_json(m)` convert matrices
+//!   to and from JSON arrays of rows, which must all be the same length
+//! - `mat_identity(n[, type])` is the n by n identity matrix
+//! - `mat_transpose(m)` swaps the rows and columns
+//! - `mat_mul(a, b)` is the matrix product, where a has as many columns as
+//!   b has rows
+//! - `mat_vec_mul(m, v)` is the product of a matrix and a column vector,
+//!   which has as many dimensions as the matrix has columns
+//! - `mat_inverse(m)` is the inverse of a square matrix, or NULL if it's
+//!   singular
+//! - `mat_det(m)` is the determinant of a square matrix
+//!
+//! Everything is computed in f64. Products and inverses are f32 if every
+//! argument is, and f64 otherwise, since they rarely fit in integers.
+use sqlite3_raw::*;
+use dynamics::*;
+use serde_json::{self, Value};
+use vector::{fits_in_blob, type_arg, type_name, vector_arg, ElementType, Matrix, Vector};
+use vector::format::{HEADER_LEN, MATRIX_HEADER_LEN};
+use vector::text::{format_elements, parse_elements};
+
+/// Read a matrix argument, or None if it's NULL
+pub fn matrix_arg(function: &str, arg: SQLiteValue) -> Result<Option<Matrix>, String> {
+    match arg.value_type() {
+        SQLITE_NULL => Ok(None),
+        SQLITE_BLOB => {
+            let blob: Option<Vec<u8>> = arg.into();
+            Matrix::decode(&blob.unwrap_or_default())
+                .map(Some)
+                .map_err(|e| format!("{}(): {}", function, e))
+        },
+        other => Err(format!("{}(): expected a matrix, not {}", function, type_name(other)))
+    }
+}
+
+/// The element type of a product of two matrices or vectors
+fn product_type(a: ElementType, b: ElementType) -> ElementType {
+    if a == ElementType::F32 && b == ElementType::F32 { ElementType::F32 } else { ElementType::F64 }
+}
+
+/// Check a matrix is square, giving its size
+fn square(function: &str, m: &Matrix) -> Result<usize, String> {
+    if m.rows != m.cols {
+        return Err(format!("{}(): the matrix should be square, not {}", function, m.shape()));
+    }
+    Ok(m.rows)
+}
+
+/// The product of an n×m and an m×p matrix, row by row
+fn multiply(a: &[f64], b: &[f64], n: usize, m: usize, p: usize) -> Vec<f64> {
+    let mut product = vec![0.0; n * p];
+    for i in 0..n {
+        let row = &mut product[i * p..(i + 1) * p];
+        // Going along b's rows rather than down its columns reads it in order
+        for k in 0..m {
+            let x = a[i * m + k];
+            for (y, &z) in row.iter_mut().zip(&b[k * p..(k + 1) * p]) {
+                *y += x * z;
+            }
+        }
+    }
+    product
+}
+
+fn identity(n: usize) -> Vec<f64> {
+    let mut m = vec![0.0; n * n];
+    for i in 0..n {
+        m[i * n + i] = 1.0;
+    }
+    m
+}
+
+/// Find the row from `col` down with the largest element in column `col`,
+/// the most stable choice of pivot
+fn pivot(a: &[f64], n: usize, col: usize) -> usize {
+    (col..n).max_by(|&i, &j| a[i * n + col].abs().total_cmp(&a[j * n + col].abs())).unwrap()
+}
+
+fn swap_rows(a: &mut [f64], n: usize, i: usize, j: usize) {
+    for k in 0..n {
+        a.swap(i * n + k, j * n + k);
+    }
+}
+
+/// The inverse of an n×n matrix by Gauss-Jordan elimination, or None if it
+/// is singular
+///
+/// A pivot that is tiny next to the largest element counts as zero, since
+/// rounding makes singular matrices look merely ill-conditioned.
+fn invert(mut a: Vec<f64>, n: usize) -> Option<Vec<f64>> {
+    let mut inverse = identity(n);
+    let largest = a.iter().fold(0.0, |m: f64, x| m.max(x.abs()));
+    let tiny = largest * n as f64 * f64::EPSILON;
+    for col in 0..n {
+        let p = pivot(&a, n, col);
+        if a[p * n + col].abs() <= tiny || a[p * n + col].is_nan() {
+            return None;
+        }
+        swap_rows(&mut a, n, p, col);
+        swap_rows(&mut inverse, n, p, col);
+        let scale = a[col * n + col];
+        for j in 0..n {
+            a[col * n + j] /= scale;
+            inverse[col * n + j] /= scale;
+        }
+        for i in 0..n {
+            let factor = a[i * n + col];
+            if i == col || factor == 0.0 {
+                continue;
+            }
+            for j in 0..n {
+                a[i * n + j] -= factor * a[col * n + j];
+                inverse[i * n + j] -= factor * inverse[col * n + j];
+            }
+        }
+    }
+    Some(inverse)
+}
+
+/// The determinant of an n×n matrix, by LU decomposition
+fn determinant(mut a: Vec<f64>, n: usize) -> f64 {
+    let mut det = 1.0;
+    for col in 0..n {
+        let p = pivot(&a, n, col);
+        if a[p * n + col] == 0.0 {
+            return 0.0;
+        }
+        if p != col {
+            swap_rows(&mut a, n, p, col);
+            det = -det;
+        }
+        let scale = a[col * n + col];
+        det *= scale;
+        for i in col + 1..n {
+            let factor = a[i * n + col] / scale;
+            for j in col..n {
+                a[i * n + j] -= factor * a[col * n + j];
+            }
+        }
+    }
+    det
+}
+
+/// `mat_from_json(json[, type])`: Read a JSON array of rows of numbers
+pub fn mat_from_json(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let ty = type_arg("mat_from_json", args, 1)?;
+    let text: String = {let x: Option<String> = args[0].into(); x}.unwrap_or_default();
+    let rows = match serde_json::from_str(&text) {
+        Ok(Value::Array(rows)) => rows,
+        Ok(_) => return Err("mat_from_json(): expected a JSON array of rows".into()),
+        Err(e) => return Err(format!("mat_from_json(): {}", e))
+    };
+    let mut values = vec![];
+    let mut cols = 0;
+    for (i, row) in rows.iter().enumerate() {
+        let elements = match *row {
+            Value::Array(ref elements) => elements,
+            ref other => return Err(format!("mat_from_json(): row {} is {}, which isn't an array", i + 1, other))
+        };
+        if i == 0 {
+            cols = elements.len();
+        } else if elements.len() != cols {
+            return Err(format!("mat_from_json(): row {} has {} elements but row 1 has {}",
+                i + 1, elements.len(), cols));
+        }
+        let mut numbers = Vec::with_capacity(cols);
+        for (j, element) in elements.iter().enumerate() {
+            match *element {
+                Value::Number(ref x) => numbers.push(x.to_string()),
+                ref other => return Err(format!("mat_from_json(): row {}, element {} is {}, which isn't a number",
+                    i + 1, j + 1, other))
+            }
+        }
+        // q8 elements are quantized once the whole matrix's range is known
+        let row_type = if ty == ElementType::Q8 { ElementType::F64 } else { ty };
+        let row = parse_elements(row_type, numbers.iter().map(|x| x.as_str()))
+            .map_err(|e| format!("mat_from_json(): row {}, {}", i + 1, e))?;
+        values.extend(row.to_f64());
+    }
+    let m = Matrix::from_f64(ty, rows.len(), cols, &values).map_err(|e| format!("mat_from_json(): {}", e))?;
+    Ok(m.encode().into())
+}
+
+/// `mat_to_json(m)`: Write a matrix as a JSON array of rows
+///
+/// JSON has no infinities or NaN, so matrices containing them are an error.
+pub fn mat_to_json(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let m = match matrix_arg("mat_to_json", args[0])? {
+        Some(m) => m,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    if let Some(i) = m.to_f64().iter().position(|x| !x.is_finite()) {
+        return Err(format!("mat_to_json(): row {}, element {} is {}, which JSON can't represent",
+            i / m.cols + 1, i % m.cols + 1, m.to_f64()[i]));
+    }
+    let elements = format_elements(&m.elements);
+    let rows: Vec<String> = (0..m.rows)
+        .map(|i| format!("[{}]", elements[i * m.cols..(i + 1) * m.cols].join(",")))
+        .collect();
+    Ok(format!("[{}]", rows.join(",")).into())
+}
+
+/// `mat_identity(n[, type])`: The n by n identity matrix
+pub fn mat_identity(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let ty = type_arg("mat_identity", args, 1)?;
+    let n = match args[0].value_type() {
+        SQLITE_INTEGER => i64::from(args[0]),
+        other => return Err(format!("mat_identity(): the size should be an integer, not {}", type_name(other)))
+    };
+    if n < 0 {
+        return Err("mat_identity(): the size can't be negative".into());
+    }
+    // Check the size before allocating, like vzero()
+    if n > 65535 || !fits_in_blob(ctx, MATRIX_HEADER_LEN, ty, (n * n) as usize) {
+        return Err(format!("mat_identity(): a {0}x{0} matrix is too big for a BLOB", n));
+    }
+    let n = n as usize;
+    let m = Matrix::from_f64(ty, n, n, &identity(n)).map_err(|e| format!("mat_identity(): {}", e))?;
+    Ok(m.encode().into())
+}
+
+/// `mat_transpose(m)`: The matrix with its rows as columns
+pub fn mat_transpose(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let m = match matrix_arg("mat_transpose", args[0])? {
+        Some(m) => m,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    let values = m.to_f64();
+    let transposed: Vec<f64> = (0..m.cols * m.rows)
+        .map(|i| values[(i % m.rows) * m.cols + i / m.rows])
+        .collect();
+    let t = Matrix::from_f64(m.element_type(), m.cols, m.rows, &transposed)
+        .map_err(|e| format!("mat_transpose(): {}", e))?;
+    Ok(t.encode().into())
+}
+
+/// `mat_mul(a, b)`: The matrix product
+pub fn mat_mul(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let (a, b) = match (matrix_arg("mat_mul", args[0])?, matrix_arg("mat_mul", args[1])?) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    if a.cols != b.rows {
+        return Err(format!("mat_mul(): can't multiply a {} matrix by a {} matrix, \
+            which needs {} rows", a.shape(), b.shape(), a.cols));
+    }
+    // A tall matrix times a wide one can be far bigger than either
+    let ty = product_type(a.element_type(), b.element_type());
+    let fits = match a.rows.checked_mul(b.cols) {
+        Some(n) => fits_in_blob(ctx, MATRIX_HEADER_LEN, ty, n),
+        None => false
+    };
+    if !fits {
+        return Err(format!("mat_mul(): a {}x{} matrix is too big for a BLOB", a.rows, b.cols));
+    }
+    let product = multiply(&a.to_f64(), &b.to_f64(), a.rows, a.cols, b.cols);
+    let m = Matrix::from_f64(ty, a.rows, b.cols, &product)
+        .map_err(|e| format!("mat_mul(): {}", e))?;
+    Ok(m.encode().into())
+}
+
+/// `mat_vec_mul(m, v)`: The product of a matrix and a column vector
+pub fn mat_vec_mul(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let (m, v) = match (matrix_arg("mat_vec_mul", args[0])?, vector_arg("mat_vec_mul", args[1])?) {
+        (Some(m), Some(v)) => (m, v),
+        _ => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    if m.cols != v.dims() {
+        return Err(format!("mat_vec_mul(): can't multiply a {} matrix by a vector with {} dimensions, \
+            which needs {}", m.shape(), v.dims(), m.cols));
+    }
+    let ty = product_type(m.element_type(), v.element_type());
+    if !fits_in_blob(ctx, HEADER_LEN, ty, m.rows) {
+        return Err(format!("mat_vec_mul(): a vector with {} dimensions is too big for a BLOB", m.rows));
+    }
+    let product = multiply(&m.to_f64(), &v.to_f64(), m.rows, m.cols, 1);
+    let result = Vector::from_f64(ty, &product)
+        .map_err(|e| format!("mat_vec_mul(): {}", e))?;
+    Ok(result.encode().into())
+}
+
+/// `mat_inverse(m)`: The inverse of a square matrix, or NULL if it has none
+pub fn mat_inverse(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let m = match matrix_arg("mat_inverse", args[0])? {
+        Some(m) => m,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    let n = square("mat_inverse", &m)?;
+    Ok(match invert(m.to_f64(), n) {
+        Some(inverse) => Matrix::from_f64(product_type(m.element_type(), m.element_type()), n, n, &inverse)
+            .map_err(|e| format!("mat_inverse(): {}", e))?
+            .encode()
+            .into(),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
+
+/// `mat_det(m)`: The determinant of a square matrix
+pub fn mat_det(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let m = match matrix_arg("mat_det", args[0])? {
+        Some(m) => m,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    let n = square("mat_det", &m)?;
+    Ok(determinant(m.to_f64(), n).into())
+}
@@ -0,0 +1,312 @@
+//! Vectors
+//!
+//! Vectors are stored in BLOBs with a small header giving the element type
+//! and the number of dimensions, see [`format`](format/index.html). They
+//! can hold 32 or 64 bit floats, 8 bit integers, single bits or 8 bit
+//! quantized values.
+//!
+//! - `vec_f32(x1, x2, ...)` and `vec_f64(x1, x2, ...)` make a vector from
+//!   numbers. Given one vector instead, they convert it.
+//! - `vec_type(v)` is the element type: `'f32'`, `'f64'`, `'i8'`, `'bit'`
+//!   or `'q8'`
+//! - `vec_dims(v)` is the number of dimensions
+//! - `vec_import(blob, type)` reads a vector made by `extras.cpp`, which
+//!   has no header, so the element type has to be given. That's `'f64'`,
+//!   or `'f32'` if it was built with `REDUCED_PRECISION`.
+//! - `vzero(n[, type])` and `vone(n[, type])` are n zeros or n ones
+//! - `vread(text[, type])`, `vshow(v)`, `vec_from_json(json[, type])` and
+//!   `vec_to_json(v)` convert vectors to and from text, see
+//!   [`text`](text/index.html)
+//!
+//! - `add(a, b)`, `subtract(a, b)`, `mult(a, b)`, `div(a, b)`, `pow(a, b)`,
+//!   `vmin(a, b)`, `vmax(a, b)`, `vabs(a)` and `vclip(a, lo, hi)` work
+//!   element-wise on any mix of vectors and numbers, see
+//!   [`arith`](arith/index.html)
+//!
+//! - `vec_dot(a, b)`, `vec_cosine_similarity(a, b)`,
+//!   `vec_cosine_distance(a, b)`, `vec_l2(a, b)`, `vec_l2_squared(a, b)`,
+//!   `vec_l1(a, b)`, `vec_linf(a, b)`, `vec_hamming(a, b)` and
+//!   `vec_jaccard(a, b)` compare two vectors, see
+//!   [`distance`](distance/index.html). The table-valued function
+//!   `vec_knn()` finds the rows of a table nearest a vector, and the
+//!   virtual table `vec_hnsw` indexes vectors to find them faster, see
+//!   [`hnsw`](hnsw/index.html).
+//!   The sums, and element-wise arithmetic on two vectors, use SIMD
+//!   instructions when the CPU has them, see [`simd`](simd/index.html).
+//!
+//! - `vec_slice(v, start, end)`, `vec_concat(a, b, ...)`, `vec_sort(v)`,
+//!   `vec_norm(v[, p])`, `vec_normalize(v)`, `vec_softmax(v)`,
+//!   `vec_argmax(v)`, `vec_argmin(v)` and `vec_topk_indices(v, k)`
+//!   rearrange or summarize the elements of a vector, see
+//!   [`elements`](elements/index.html)
+//!
+//! - `vec_quantize_i8(v[, min, max])`, `vec_quantize_binary(v)` and
+//!   `vec_dequantize(v)` convert vectors to and from compact quantized
+//!   forms, see [`quantize`](quantize/index.html)
+//!
+//! - `mat_from_json(json[, type])`, `mat_to_json(m)`, `mat_identity(n)`,
+//!   `mat_transpose(m)`, `mat_mul(a, b)`, `mat_vec_mul(m, v)`,
+//!   `mat_inverse(m)` and `mat_det(m)` work with matrices, which are
+//!   stored like vectors, see [`matrix`](matrix/index.html)
+//!
+//! - `vec_sum(v)`, `vec_avg(v)`, `vec_min(v)` and `vec_max(v)` are
+//!   element-wise aggregate and window functions, and `vec_collect(x[, type])`
+//!   makes a vector of a group of numbers, see
+//!   [`aggregate`](aggregate/index.html). The table-valued function
+//!   `vec_each(v)` has a row for each element.
+//!
+//! - `vec_kmeans(v, k[, iterations, seed])` is an aggregate clustering
+//!   vectors, giving a matrix of centroids, and
+//!   `vec_nearest_centroid(v, centroids)` is the cluster a vector belongs
+//!   to, see [`kmeans`](kmeans/index.html)
+//!
+//! Functions taking an element type make f64 vectors unless told otherwise.
+//! All of them return NULL if any argument is NULL.
+use sqlite3_raw::*;
+use dynamics::*;
+use std::os::raw::c_int;
+use std::ptr;
+
+pub mod aggregate;
+pub mod arith;
+pub mod distance;
+pub mod elements;
+pub mod format;
+pub mod hnsw;
+pub mod kmeans;
+pub mod matrix;
+pub mod quantize;
+pub mod simd;
+pub mod text;
+pub use self::format::{ElementType, Matrix, Vector};
+
+/// Register every vector function with a connection
+///
+/// # Safety
+/// `db` must be an open connection and the extension API must be loaded.
+pub unsafe fn register(db: *mut sqlite3) -> i32 {
+    or_die!(create_function!(db, "vec_f32", -1, vec_f32));
+    or_die!(create_function!(db, "vec_f64", -1, vec_f64));
+    or_die!(create_function!(db, "vec_type", 1, vec_type));
+    or_die!(create_function!(db, "vec_dims", 1, vec_dims));
+    or_die!(create_function!(db, "vec_import", 2, vec_import));
+    or_die!(create_function!(db, "vzero", 1, vzero));
+    or_die!(create_function!(db, "vzero", 2, vzero));
+    or_die!(create_function!(db, "vone", 1, vone));
+    or_die!(create_function!(db, "vone", 2, vone));
+    or_die!(create_function!(db, "vread", 1, text::vread));
+    or_die!(create_function!(db, "vread", 2, text::vread));
+    or_die!(create_function!(db, "vshow", 1, text::vshow));
+    or_die!(create_function!(db, "vec_from_json", 1, text::vec_from_json));
+    or_die!(create_function!(db, "vec_from_json", 2, text::vec_from_json));
+    or_die!(create_function!(db, "vec_to_json", 1, text::vec_to_json));
+    or_die!(create_function!(db, "add", 2, arith::add));
+    or_die!(create_function!(db, "subtract", 2, arith::subtract));
+    or_die!(create_function!(db, "mult", 2, arith::mult));
+    or_die!(create_function!(db, "div", 2, arith::div));
+    or_die!(create_function!(db, "pow", 2, arith::pow));
+    or_die!(create_function!(db, "vmin", 2, arith::vmin));
+    or_die!(create_function!(db, "vmax", 2, arith::vmax));
+    or_die!(create_function!(db, "vabs", 1, arith::vabs));
+    or_die!(create_function!(db, "vclip", 3, arith::vclip));
+    or_die!(create_function!(db, "vec_dot", 2, distance::vec_dot));
+    or_die!(create_function!(db, "vec_cosine_similarity", 2, distance::vec_cosine_similarity));
+    or_die!(create_function!(db, "vec_cosine_distance", 2, distance::vec_cosine_distance));
+    or_die!(create_function!(db, "vec_l2", 2, distance::vec_l2));
+    or_die!(create_function!(db, "vec_l2_squared", 2, distance::vec_l2_squared));
+    or_die!(create_function!(db, "vec_l1", 2, distance::vec_l1));
+    or_die!(create_function!(db, "vec_linf", 2, distance::vec_linf));
+    or_die!(create_function!(db, "vec_hamming", 2, distance::vec_hamming));
+    or_die!(create_function!(db, "vec_jaccard", 2, distance::vec_jaccard));
+    or_die!(create_function!(db, "vec_slice", 3, elements::vec_slice));
+    or_die!(create_function!(db, "vec_concat", -1, elements::vec_concat));
+    or_die!(create_function!(db, "vec_sort", 1, elements::vec_sort));
+    or_die!(create_function!(db, "vec_norm", 1, elements::vec_norm));
+    or_die!(create_function!(db, "vec_norm", 2, elements::vec_norm));
+    or_die!(create_function!(db, "vec_normalize", 1, elements::vec_normalize));
+    or_die!(create_function!(db, "vec_softmax", 1, elements::vec_softmax));
+    or_die!(create_function!(db, "vec_argmax", 1, elements::vec_argmax));
+    or_die!(create_function!(db, "vec_argmin", 1, elements::vec_argmin));
+    or_die!(create_function!(db, "vec_topk_indices", 2, elements::vec_topk_indices));
+    or_die!(create_function!(db, "vec_quantize_i8", 1, quantize::vec_quantize_i8));
+    or_die!(create_function!(db, "vec_quantize_i8", 3, quantize::vec_quantize_i8));
+    or_die!(create_function!(db, "vec_quantize_binary", 1, quantize::vec_quantize_binary));
+    or_die!(create_function!(db, "vec_dequantize", 1, quantize::vec_dequantize));
+    or_die!(create_function!(db, "mat_from_json", 1, matrix::mat_from_json));
+    or_die!(create_function!(db, "mat_from_json", 2, matrix::mat_from_json));
+    or_die!(create_function!(db, "mat_to_json", 1, matrix::mat_to_json));
+    or_die!(create_function!(db, "mat_identity", 1, matrix::mat_identity));
+    or_die!(create_function!(db, "mat_identity", 2, matrix::mat_identity));
+    or_die!(create_function!(db, "mat_transpose", 1, matrix::mat_transpose));
+    or_die!(create_function!(db, "mat_mul", 2, matrix::mat_mul));
+    or_die!(create_function!(db, "mat_vec_mul", 2, matrix::mat_vec_mul));
+    or_die!(create_function!(db, "mat_inverse", 1, matrix::mat_inverse));
+    or_die!(create_function!(db, "mat_det", 1, matrix::mat_det));
+    or_die!(create_function!(db, "vec_nearest_centroid", 2, kmeans::vec_nearest_centroid));
+    or_die!(create_aggregate!(db, "vec_sum", 1, aggregate::VecSum));
+    or_die!(create_aggregate!(db, "vec_avg", 1, aggregate::VecAvg));
+    or_die!(create_aggregate!(db, "vec_min", 1, aggregate::VecMin));
+    or_die!(create_aggregate!(db, "vec_max", 1, aggregate::VecMax));
+    or_die!(create_aggregate!(db, "vec_kmeans", 2, kmeans::VecKmeans));
+    or_die!(create_aggregate!(db, "vec_kmeans", 3, kmeans::VecKmeans));
+    or_die!(create_aggregate!(db, "vec_kmeans", 4, kmeans::VecKmeans));
+    or_die!(create_aggregate!(db, "vec_collect", 1, aggregate::VecCollect));
+    or_die!(create_aggregate!(db, "vec_collect", 2, aggregate::VecCollect));
+    SQLITE_OK
+}
+
+/// How to describe an argument's storage class in an error message
+pub fn type_name(value_type: i32) -> &'static str {
+    match value_type {
+        SQLITE_NULL => "NULL",
+        SQLITE_INTEGER => "an integer",
+        SQLITE_FLOAT => "a real",
+        SQLITE_TEXT => "text",
+        _ => "a BLOB"
+    }
+}
+
+/// Read a vector argument, or None if it's NULL
+pub fn vector_arg(function: &str, arg: SQLiteValue) -> Result<Option<Vector>, String> {
+    match arg.value_type() {
+        SQLITE_NULL => Ok(None),
+        SQLITE_BLOB => {
+            let blob: Option<Vec<u8>> = arg.into();
+            Vector::decode(&blob.unwrap_or_default())
+                .map(Some)
+                .map_err(|e| format!("{}(): {}", function, e))
+        },
+        other => Err(format!("{}(): expected a vector, not {}", function, type_name(other)))
+    }
+}
+
+/// The longest BLOB the function's connection allows
+fn max_blob_len(ctx: *mut sqlite3_context) -> usize {
+    unsafe { sql_call!(limit)(sql_call!(context_db_handle)(ctx), SQLITE_LIMIT_LENGTH, -1) as usize }
+}
+
+/// Whether a header and this many elements fit in a BLOB the function's
+/// connection allows
+fn fits_in_blob(ctx: *mut sqlite3_context, header_len: usize, ty: ElementType, elements: usize) -> bool {
+    match ty.data_len(elements).and_then(|len| len.checked_add(header_len)) {
+        Some(len) => len <= max_blob_len(ctx),
+        None => false
+    }
+}
+
+/// An integer argument, called `name` in errors
+fn integer_arg(function: &str, arg: SQLiteValue, name: &str) -> Result<i64, String> {
+    match arg.value_type() {
+        SQLITE_INTEGER => Ok(i64::from(arg)),
+        other => Err(format!("{}(): {} should be an integer, not {}", function, name, type_name(other)))
+    }
+}
+
+/// The element type argument at position `i`, which is f64 if it's missing
+fn type_arg(function: &str, args: &[SQLiteValue], i: usize) -> Result<ElementType, String> {
+    match args.get(i) {
+        None => Ok(ElementType::F64),
+        Some(&arg) => {
+            let name: String = {let x: Option<String> = arg.into(); x}.unwrap_or_default();
+            ElementType::from_name(&name).map_err(|e| format!("{}(): {}", function, e))
+        }
+    }
+}
+
+/// The elements for `vec_f32()` or `vec_f64()`: either the numbers given,
+/// or the elements of the one vector given
+fn elements(function: &str, args: &[SQLiteValue]) -> Result<Option<Vec<f64>>, String> {
+    if args.len() == 1 && args[0].value_type() == SQLITE_BLOB {
+        return Ok(vector_arg(function, args[0])?.map(|v| v.to_f64()));
+    }
+    let mut values = Vec::with_capacity(args.len());
+    for (i, &arg) in args.iter().enumerate() {
+        match arg.value_type() {
+            SQLITE_NULL => return Ok(None),
+            SQLITE_INTEGER | SQLITE_FLOAT => values.push(f64::from(arg)),
+            other => return Err(format!("{}(): argument {} should be a number, not {}",
+                function, i + 1, type_name(other)))
+        }
+    }
+    Ok(Some(values))
+}
+
+/// `vec_f32(x1, x2, ...)` or `vec_f32(v)`: A vector of 32 bit floats
+fn vec_f32(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(match elements("vec_f32", args)? {
+        Some(values) => Vector::F32(values.into_iter().map(|x| x as f32).collect()).encode().into(),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
+
+/// `vec_f64(x1, x2, ...)` or `vec_f64(v)`: A vector of 64 bit floats
+fn vec_f64(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(match elements("vec_f64", args)? {
+        Some(values) => Vector::F64(values).encode().into(),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
+
+/// `vec_type(v)`: The name of the vector's element type
+fn vec_type(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(match vector_arg("vec_type", args[0])? {
+        Some(v) => v.element_type().name().to_string().into(),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
+
+/// `vec_dims(v)`: The number of dimensions of the vector
+fn vec_dims(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(match vector_arg("vec_dims", args[0])? {
+        Some(v) => (v.dims() as i64).into(),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
+
+/// `vec_import(blob, type)`: Add a header to a vector from `extras.cpp`
+fn vec_import(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    if args[0].value_type() != SQLITE_BLOB {
+        return Err(format!("vec_import(): expected a BLOB, not {}", type_name(args[0].value_type())));
+    }
+    let blob: Vec<u8> = {let x: Option<Vec<u8>> = args[0].into(); x}.unwrap_or_default();
+    let name: String = {let x: Option<String> = args[1].into(); x}.unwrap_or_default();
+    let import = ElementType::from_name(&name)
+        .and_then(|ty| Vector::decode_legacy(&blob, ty))
+        .map_err(|e| format!("vec_import(): {}", e))?;
+    Ok(import.encode().into())
+}
+
+/// `vzero(n[, type])`: A vector of n zeros
+fn vzero(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    filled(ctx, "vzero", args, 0.0)
+}
+
+/// `vone(n[, type])`: A vector of n ones
+fn vone(ctx: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    filled(ctx, "vone", args, 1.0)
+}
+
+/// A vector with every element the same
+fn filled(ctx: *mut sqlite3_context, function: &str, args: &[SQLiteValue], value: f64) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let ty = type_arg(function, args, 1)?;
+    let dims = match args[0].value_type() {
+        SQLITE_INTEGER => i64::from(args[0]),
+        other => return Err(format!("{}(): the length should be an integer, not {}",
+            function, type_name(other)))
+    };
+    if dims < 0 {
+        return Err(format!("{}(): the length can't be negative", function));
+    }
+    // Check the size before allocating, since the length is often a typo
+    if dims > i64::from(u32::MAX) || !fits_in_blob(ctx, format::HEADER_LEN, ty, dims as usize) {
+        return Err(format!("{}(): {} dimensions is too big for a BLOB", function, dims));
+    }
+    let v = Vector::from_f64(ty, &vec![value; dims as usize]).map_err(|e| format!("{}(): {}", function, e))?;
+    Ok(v.encode().into())
+}
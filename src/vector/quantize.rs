@@ -0,0 +1,74 @@
+//! Quantizing vectors to take less space
+//!
+//! - `vec_quantize_i8(v[, min, max])` makes a q8 vector, storing each
+//!   element in one byte as the nearest of 256 evenly spaced values from
+//!   min to max. Elements outside the range are clamped to it. Without a
+//!   range, the vector's own smallest and largest elements are used.
+//!   Giving every vector the same range, like the range of the whole
+//!   collection, keeps their rounding consistent.
+//! - `vec_quantize_binary(v)` keeps only the signs, as a bit vector that
+//!   is 1 where the element is positive and 0 elsewhere.
+//! - `vec_dequantize(v)` gives back f32 elements: the values a q8 vector's
+//!   elements stand for, or 0 and 1 for the bits of a binary one, as bits
+//!   count everywhere else.
+//!
+//! Quantized vectors can be compared without dequantizing them first. The
+//! distance functions and `vec_knn()` use the values q8 elements stand for,
+//! so a q8 vector can be compared with a full precision query, and
+//! `vec_hamming()` of two binary vectors counts the signs that differ. A
+//! coarse search over quantized vectors can then be refined with the full
+//! precision ones:
+//!
+//! ```sql
+//! SELECT docs.id FROM vec_knn('docs', 'embedding_q8', :query, 100) AS knn
+//! JOIN docs ON docs.rowid = knn.rowid
+//! ORDER BY vec_l2(docs.embedding, :query) LIMIT 10;
+//! ```
+use sqlite3_raw::*;
+use dynamics::*;
+use vector::{type_name, vector_arg, ElementType, Vector};
+
+/// `vec_quantize_i8(v[, min, max])`: A q8 vector of 8 bit codes for the
+/// elements, clamped to the range
+pub fn vec_quantize_i8(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let values = vector_arg("vec_quantize_i8", args[0])?.unwrap().to_f64();
+    let mut range = [0.0f32; 2];
+    for (i, (bound, &arg)) in range.iter_mut().zip(&args[1..]).enumerate() {
+        *bound = match arg.value_type() {
+            SQLITE_INTEGER | SQLITE_FLOAT => f64::from(arg) as f32,
+            other => return Err(format!("vec_quantize_i8(): {} should be a number, not {}",
+                ["min", "max"][i], type_name(other)))
+        };
+    }
+    let v = if args.len() == 1 {
+        Vector::from_f64(ElementType::Q8, &values)
+    } else if range[0] < range[1] {
+        Vector::quantize(&values, range[0], range[1])
+    } else {
+        Err("min should be less than max".into())
+    };
+    Ok(v.map_err(|e| format!("vec_quantize_i8(): {}", e))?.encode().into())
+}
+
+/// `vec_quantize_binary(v)`: A bit vector of which elements are positive
+pub fn vec_quantize_binary(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(match vector_arg("vec_quantize_binary", args[0])? {
+        Some(v) => Vector::Bit(v.to_f64().iter().map(|&x| x > 0.0).collect()).encode().into(),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
+
+/// `vec_dequantize(v)`: An f32 vector of the values a quantized vector
+/// stands for
+///
+/// Bits become 0 or 1, so distances to the result are the distances to the
+/// bit vector. Other vectors are just converted to f32.
+pub fn vec_dequantize(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(match vector_arg("vec_dequantize", args[0])? {
+        Some(v) => Vector::F32(v.to_f64().iter().map(|&x| x as f32).collect()).encode().into(),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
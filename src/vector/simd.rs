@@ -0,0 +1,358 @@
+//! SIMD kernels for the loops over vector elements
+//!
+//! Distances and element-wise arithmetic spend nearly all their time in a
+//! few loops over f64 slices. These run them with the widest instructions
+//! the CPU has, chosen once at run time: AVX-512, AVX2 or SSE2 on x86, NEON
+//! on ARM64, or plain Rust anywhere else.
+//!
+//! Every instruction set gives exactly the same results, so a query can't
+//! change its answer by moving to another machine. Sums are split into
+//! eight interleaved partial sums (element i goes to sum i mod 8), which
+//! are added together in a fixed order, and nothing uses fused
+//! multiply-add, which rounds differently. That order isn't the one by one
+//! order of `extras.cpp`, so sums can differ from its loops by rounding,
+//! at most 2nε Σ|xᵢ| for n terms xᵢ, where ε = 2⁻⁵³. Element-wise
+//! arithmetic rounds each element once either way, so it's identical.
+#[cfg(target_arch = "x86")]
+use std::arch::x86 as arch;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64 as arch;
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64 as arch;
+
+/// How many partial sums reductions keep, whatever the register width
+const LANES: usize = 8;
+
+/// An instruction set the kernels can use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Isa {
+    Scalar,
+    Sse2,
+    Avx2,
+    Avx512,
+    Neon
+}
+
+/// Call the kernel for an instruction set, or `$scalar` if the CPU
+/// doesn't support it
+macro_rules! dispatch {
+    ($isa: expr, $kernel: ident($($arg: expr),*), $scalar: expr) => {
+        match $isa {
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Isa::Avx512 if Isa::Avx512.is_supported() => unsafe { avx512::$kernel($($arg),*) },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Isa::Avx2 if Isa::Avx2.is_supported() => unsafe { avx2::$kernel($($arg),*) },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Isa::Sse2 if Isa::Sse2.is_supported() => unsafe { sse2::$kernel($($arg),*) },
+            #[cfg(target_arch = "aarch64")]
+            Isa::Neon => unsafe { neon::$kernel($($arg),*) },
+            _ => $scalar
+        }
+    }
+}
+
+lazy_static! {
+    static ref BEST: Isa = Isa::detect();
+}
+
+impl Isa {
+    /// Every instruction set, fastest first
+    pub const ALL: [Isa; 5] = [Isa::Avx512, Isa::Avx2, Isa::Sse2, Isa::Neon, Isa::Scalar];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Isa::Scalar => "scalar",
+            Isa::Sse2 => "sse2",
+            Isa::Avx2 => "avx2",
+            Isa::Avx512 => "avx512",
+            Isa::Neon => "neon"
+        }
+    }
+
+    /// The fastest instruction set this CPU supports
+    pub fn best() -> Isa {
+        *BEST
+    }
+
+    fn detect() -> Isa {
+        Isa::ALL.iter().cloned().find(|isa| isa.is_supported()).unwrap_or(Isa::Scalar)
+    }
+
+    /// Whether this CPU supports the instruction set
+    pub fn is_supported(self) -> bool {
+        match self {
+            Isa::Scalar => true,
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Isa::Sse2 => is_x86_feature_detected!("sse2"),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Isa::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Isa::Avx512 => is_x86_feature_detected!("avx512f"),
+            #[cfg(target_arch = "aarch64")]
+            Isa::Neon => ::std::arch::is_aarch64_feature_detected!("neon"),
+            #[allow(unreachable_patterns)]
+            _ => false
+        }
+    }
+
+    /// The dot product, Σ aᵢbᵢ
+    ///
+    /// Like every kernel, this runs the scalar code if the CPU doesn't
+    /// support the instruction set, which gives the same result anyway.
+    pub fn dot(self, a: &[f64], b: &[f64]) -> f64 {
+        assert_eq!(a.len(), b.len());
+        dispatch!(self, dot(a, b), sum(a, b, dot_term))
+    }
+
+    /// The squared Euclidean distance, Σ (aᵢ - bᵢ)²
+    pub fn l2_squared(self, a: &[f64], b: &[f64]) -> f64 {
+        assert_eq!(a.len(), b.len());
+        dispatch!(self, l2_squared(a, b), sum(a, b, l2_term))
+    }
+
+    /// The Manhattan distance, Σ |aᵢ - bᵢ|
+    pub fn l1(self, a: &[f64], b: &[f64]) -> f64 {
+        assert_eq!(a.len(), b.len());
+        dispatch!(self, l1(a, b), sum(a, b, l1_term))
+    }
+
+    /// aᵢ + bᵢ for each element
+    pub fn add(self, a: &[f64], b: &[f64]) -> Vec<f64> {
+        assert_eq!(a.len(), b.len());
+        dispatch!(self, add(a, b), a.iter().zip(b).map(|(x, y)| x + y).collect())
+    }
+
+    /// aᵢ - bᵢ for each element
+    pub fn subtract(self, a: &[f64], b: &[f64]) -> Vec<f64> {
+        assert_eq!(a.len(), b.len());
+        dispatch!(self, subtract(a, b), a.iter().zip(b).map(|(x, y)| x - y).collect())
+    }
+
+    /// aᵢ × bᵢ for each element
+    pub fn mult(self, a: &[f64], b: &[f64]) -> Vec<f64> {
+        assert_eq!(a.len(), b.len());
+        dispatch!(self, mult(a, b), a.iter().zip(b).map(|(x, y)| x * y).collect())
+    }
+
+    /// aᵢ / bᵢ for each element
+    pub fn div(self, a: &[f64], b: &[f64]) -> Vec<f64> {
+        assert_eq!(a.len(), b.len());
+        dispatch!(self, div(a, b), a.iter().zip(b).map(|(x, y)| x / y).collect())
+    }
+}
+
+/// `Isa::dot()` with the best instruction set
+pub fn dot(a: &[f64], b: &[f64]) -> f64 {
+    Isa::best().dot(a, b)
+}
+
+/// `Isa::l2_squared()` with the best instruction set
+pub fn l2_squared(a: &[f64], b: &[f64]) -> f64 {
+    Isa::best().l2_squared(a, b)
+}
+
+/// `Isa::l1()` with the best instruction set
+pub fn l1(a: &[f64], b: &[f64]) -> f64 {
+    Isa::best().l1(a, b)
+}
+
+fn dot_term(x: f64, y: f64) -> f64 {
+    x * y
+}
+
+fn l2_term(x: f64, y: f64) -> f64 {
+    (x - y) * (x - y)
+}
+
+fn l1_term(x: f64, y: f64) -> f64 {
+    (x - y).abs()
+}
+
+/// Sum `term` of each pair of elements in the scalar code
+fn sum(a: &[f64], b: &[f64], term: fn(f64, f64) -> f64) -> f64 {
+    let whole = a.len() - a.len() % LANES;
+    let mut sums = [0.0; LANES];
+    for (x, y) in a[..whole].chunks(LANES).zip(b[..whole].chunks(LANES)) {
+        for (lane, sum) in sums.iter_mut().enumerate() {
+            *sum += term(x[lane], y[lane]);
+        }
+    }
+    finish(sums, &a[whole..], &b[whole..], term)
+}
+
+/// Add the terms of the elements left over after the whole groups of
+/// LANES to the first sums, then add up the sums pairwise
+fn finish(mut sums: [f64; LANES], a: &[f64], b: &[f64], term: fn(f64, f64) -> f64) -> f64 {
+    for (sum, (&x, &y)) in sums.iter_mut().zip(a.iter().zip(b)) {
+        *sum += term(x, y);
+    }
+    let quads = [sums[0] + sums[4], sums[1] + sums[5], sums[2] + sums[6], sums[3] + sums[7]];
+    (quads[0] + quads[2]) + (quads[1] + quads[3])
+}
+
+/// Sum `$vector` of each pair of registers into LANES partial sums, using
+/// the `vzero`, `vload`, `vstore` and `vadd` of the module it's expanded in
+macro_rules! simd_sum {
+    ($a: ident, $b: ident, |$x: ident, $y: ident| $vector: expr, $scalar: expr) => { {
+        const REGISTERS: usize = super::LANES / WIDTH;
+        let whole = $a.len() - $a.len() % super::LANES;
+        let mut sums = [vzero(); REGISTERS];
+        let mut i = 0;
+        while i < whole {
+            for (r, sum) in sums.iter_mut().enumerate() {
+                let $x = vload($a.as_ptr().add(i + r * WIDTH));
+                let $y = vload($b.as_ptr().add(i + r * WIDTH));
+                *sum = vadd(*sum, $vector);
+            }
+            i += super::LANES;
+        }
+        let mut lanes = [0.0; super::LANES];
+        for (r, sum) in sums.iter().enumerate() {
+            vstore(lanes.as_mut_ptr().add(r * WIDTH), *sum);
+        }
+        super::finish(lanes, &$a[whole..], &$b[whole..], $scalar)
+    } }
+}
+
+/// Apply `$vector` to each pair of registers, and `$scalar` to the
+/// elements left over
+macro_rules! simd_map {
+    ($a: ident, $b: ident, |$x: ident, $y: ident| $vector: expr, $scalar: expr) => { {
+        let whole = $a.len() - $a.len() % WIDTH;
+        let mut out = vec![0.0; $a.len()];
+        let mut i = 0;
+        while i < whole {
+            let $x = vload($a.as_ptr().add(i));
+            let $y = vload($b.as_ptr().add(i));
+            vstore(out.as_mut_ptr().add(i), $vector);
+            i += WIDTH;
+        }
+        for ((z, &x), &y) in out[whole..].iter_mut().zip(&$a[whole..]).zip(&$b[whole..]) {
+            *z = $scalar(x, y);
+        }
+        out
+    } }
+}
+
+/// Every kernel, for a module defining `WIDTH` and the register operations
+/// for a target feature
+macro_rules! kernels {
+    ($feature: tt) => {
+        #[target_feature(enable = $feature)]
+        pub unsafe fn dot(a: &[f64], b: &[f64]) -> f64 {
+            simd_sum!(a, b, |x, y| vmul(x, y), super::dot_term)
+        }
+
+        #[target_feature(enable = $feature)]
+        pub unsafe fn l2_squared(a: &[f64], b: &[f64]) -> f64 {
+            simd_sum!(a, b, |x, y| { let d = vsub(x, y); vmul(d, d) }, super::l2_term)
+        }
+
+        #[target_feature(enable = $feature)]
+        pub unsafe fn l1(a: &[f64], b: &[f64]) -> f64 {
+            simd_sum!(a, b, |x, y| vabs(vsub(x, y)), super::l1_term)
+        }
+
+        #[target_feature(enable = $feature)]
+        pub unsafe fn add(a: &[f64], b: &[f64]) -> Vec<f64> {
+            simd_map!(a, b, |x, y| vadd(x, y), |x: f64, y: f64| x + y)
+        }
+
+        #[target_feature(enable = $feature)]
+        pub unsafe fn subtract(a: &[f64], b: &[f64]) -> Vec<f64> {
+            simd_map!(a, b, |x, y| vsub(x, y), |x: f64, y: f64| x - y)
+        }
+
+        #[target_feature(enable = $feature)]
+        pub unsafe fn mult(a: &[f64], b: &[f64]) -> Vec<f64> {
+            simd_map!(a, b, |x, y| vmul(x, y), |x: f64, y: f64| x * y)
+        }
+
+        #[target_feature(enable = $feature)]
+        pub unsafe fn div(a: &[f64], b: &[f64]) -> Vec<f64> {
+            simd_map!(a, b, |x, y| vdiv(x, y), |x: f64, y: f64| x / y)
+        }
+    }
+}
+
+/// Define the register operations a kernel module needs, each a single
+/// intrinsic
+macro_rules! registers {
+    ($feature: tt, $V: ty, $width: expr,
+     zero: $zero: path, load: $load: path, store: $store: path,
+     add: $add: path, sub: $sub: path, mul: $mul: path, div: $div: path, abs: |$v: ident| $abs: expr) => {
+        type V = $V;
+        const WIDTH: usize = $width;
+
+        #[inline]
+        #[target_feature(enable = $feature)]
+        unsafe fn vzero() -> V { $zero() }
+        #[inline]
+        #[target_feature(enable = $feature)]
+        unsafe fn vload(p: *const f64) -> V { $load(p) }
+        #[inline]
+        #[target_feature(enable = $feature)]
+        unsafe fn vstore(p: *mut f64, v: V) { $store(p, v) }
+        #[inline]
+        #[target_feature(enable = $feature)]
+        unsafe fn vadd(a: V, b: V) -> V { $add(a, b) }
+        #[inline]
+        #[target_feature(enable = $feature)]
+        unsafe fn vsub(a: V, b: V) -> V { $sub(a, b) }
+        #[inline]
+        #[target_feature(enable = $feature)]
+        unsafe fn vmul(a: V, b: V) -> V { $mul(a, b) }
+        #[inline]
+        #[target_feature(enable = $feature)]
+        unsafe fn vdiv(a: V, b: V) -> V { $div(a, b) }
+        #[inline]
+        #[target_feature(enable = $feature)]
+        unsafe fn vabs($v: V) -> V { $abs }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod sse2 {
+    use super::arch::*;
+    registers!("sse2", __m128d, 2,
+        zero: _mm_setzero_pd, load: _mm_loadu_pd, store: _mm_storeu_pd,
+        add: _mm_add_pd, sub: _mm_sub_pd, mul: _mm_mul_pd, div: _mm_div_pd,
+        abs: |v| _mm_andnot_pd(_mm_set1_pd(-0.0), v));
+    kernels!("sse2");
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod avx2 {
+    use super::arch::*;
+    registers!("avx2", __m256d, 4,
+        zero: _mm256_setzero_pd, load: _mm256_loadu_pd, store: _mm256_storeu_pd,
+        add: _mm256_add_pd, sub: _mm256_sub_pd, mul: _mm256_mul_pd, div: _mm256_div_pd,
+        abs: |v| _mm256_andnot_pd(_mm256_set1_pd(-0.0), v));
+    kernels!("avx2");
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod avx512 {
+    use super::arch::*;
+    registers!("avx512f", __m512d, 8,
+        zero: _mm512_setzero_pd, load: _mm512_loadu_pd, store: _mm512_storeu_pd,
+        add: _mm512_add_pd, sub: _mm512_sub_pd, mul: _mm512_mul_pd, div: _mm512_div_pd,
+        abs: |v| _mm512_abs_pd(v));
+    kernels!("avx512f");
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use super::arch::*;
+    registers!("neon", float64x2_t, 2,
+        zero: zero_f64x2, load: vld1q_f64, store: vst1q_f64,
+        add: vaddq_f64, sub: vsubq_f64, mul: vmulq_f64, div: vdivq_f64,
+        abs: |v| vabsq_f64(v));
+    kernels!("neon");
+
+    #[inline]
+    #[target_feature(enable = "neon")]
+    unsafe fn zero_f64x2() -> float64x2_t {
+        vdupq_n_f64(0.0)
+    }
+}
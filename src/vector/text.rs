@@ -0,0 +1,109 @@
+//! Reading and writing vectors as text and JSON
+//!
+//! Elements are written in the shortest form that reads back as exactly
+//! the same number, so `vread(vshow(v))` and
+//! `vec_from_json(vec_to_json(v))` always give back `v` (as long as the
+//! element type is given again). Anything that isn't a number is an error,
+//! rather than the end of the vector as it was in `extras.cpp`.
+//!
+//! q8 vectors are written as the values their elements stand for, and
+//! reading those back quantizes them again, which may not give exactly the
+//! same range.
+use sqlite3_raw::*;
+use dynamics::*;
+use serde_json::{self, Value};
+use vector::{type_arg, vector_arg, ElementType, Vector};
+
+/// Parse one element, written in decimal or scientific notation
+///
+/// f32 elements are parsed as f32, so they are rounded only once.
+fn parse_element(ty: ElementType, text: &str) -> Option<f64> {
+    match ty {
+        ElementType::F32 => text.parse::<f32>().ok().map(f64::from),
+        _ => text.parse::<f64>().ok()
+    }
+}
+
+/// Parse the elements of a vector, naming the first one that isn't valid
+pub fn parse_elements<'t, I>(ty: ElementType, elements: I) -> Result<Vector, String>
+    where I: Iterator<Item = &'t str>
+{
+    let mut values = vec![];
+    for (i, text) in elements.enumerate() {
+        match parse_element(ty, text) {
+            Some(x) => values.push(x),
+            None => return Err(format!("element {} is '{}', which isn't a number", i + 1, text))
+        }
+    }
+    Vector::from_f64(ty, &values)
+}
+
+/// The shortest text for each element that reads back exactly
+pub fn format_elements(v: &Vector) -> Vec<String> {
+    match *v {
+        Vector::F32(ref v) => v.iter().map(|x| format!("{:?}", x)).collect(),
+        Vector::F64(ref v) => v.iter().map(|x| format!("{:?}", x)).collect(),
+        Vector::I8(ref v) => v.iter().map(|x| x.to_string()).collect(),
+        Vector::Bit(ref v) => v.iter().map(|&x| (x as u8).to_string()).collect(),
+        Vector::Q8 { .. } => v.to_f64().iter().map(|&x| format!("{:?}", x as f32)).collect()
+    }
+}
+
+/// `vread(text[, type])`: Read whitespace separated numbers into a vector
+pub fn vread(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let ty = type_arg("vread", args, 1)?;
+    let text: String = {let x: Option<String> = args[0].into(); x}.unwrap_or_default();
+    let v = parse_elements(ty, text.split_whitespace()).map_err(|e| format!("vread(): {}", e))?;
+    Ok(v.encode().into())
+}
+
+/// `vshow(v)`: Write a vector as space separated numbers, for `vread()`
+pub fn vshow(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    Ok(match vector_arg("vshow", args[0])? {
+        Some(v) => format_elements(&v).join(" ").into(),
+        None => SQLiteReturn::SQLiteNull
+    })
+}
+
+/// `vec_from_json(json[, type])`: Read a JSON array of numbers into a vector
+pub fn vec_from_json(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    if args.iter().any(SQLiteValue::is_null) {
+        return Ok(SQLiteReturn::SQLiteNull);
+    }
+    let ty = type_arg("vec_from_json", args, 1)?;
+    let text: String = {let x: Option<String> = args[0].into(); x}.unwrap_or_default();
+    let elements = match serde_json::from_str(&text) {
+        Ok(Value::Array(elements)) => elements,
+        Ok(_) => return Err("vec_from_json(): expected a JSON array".into()),
+        Err(e) => return Err(format!("vec_from_json(): {}", e))
+    };
+    let mut numbers = Vec::with_capacity(elements.len());
+    for (i, element) in elements.iter().enumerate() {
+        match *element {
+            Value::Number(ref x) => numbers.push(x.to_string()),
+            ref other => return Err(format!("vec_from_json(): element {} is {}, which isn't a number",
+                i + 1, other))
+        }
+    }
+    let v = parse_elements(ty, numbers.iter().map(|x| x.as_str()))
+        .map_err(|e| format!("vec_from_json(): {}", e))?;
+    Ok(v.encode().into())
+}
+
+/// `vec_to_json(v)`: Write a vector as a JSON array
+///
+/// JSON has no infinities or NaN, so vectors containing them are an error.
+pub fn vec_to_json(_: *mut sqlite3_context, args: &[SQLiteValue]) -> Result<SQLiteReturn, String> {
+    let v = match vector_arg("vec_to_json", args[0])? {
+        Some(v) => v,
+        None => return Ok(SQLiteReturn::SQLiteNull)
+    };
+    if let Some(i) = v.to_f64().iter().position(|x| !x.is_finite()) {
+        return Err(format!("vec_to_json(): element {} is {}, which JSON can't represent",
+            i + 1, v.to_f64()[i]));
+    }
+    Ok(format!("[{}]", format_elements(&v).join(",")).into())
+}
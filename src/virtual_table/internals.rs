@@ -9,7 +9,7 @@
 //! between VirtualCursor and sqlite_vtab_cursor.
 
 use sqlite3_raw::*;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::slice;
 use std::os::raw::c_void;
 use std::ops::{Deref, DerefMut};
@@ -58,47 +58,148 @@ impl<T> DerefMut for CursorWrapper<T> {
 }
 
 
+/// Construct a VirtualTable for CREATE VIRTUAL TABLE.
+/// See [`sqlite3_module.xCreate`](https://sqlite.org/vtab.html)
+pub unsafe extern "C" fn vtab_create<Tab: VirtualTable>(
+    db: *mut sqlite3,
+    _state: *mut c_void,
+    argc: i32,
+    argv: *const *const i8,
+    pp_vtab: *mut *mut sqlite3_vtab,
+    pz_err: *mut *mut i8
+) -> i32 {
+    vtab_init(db, argc, argv, pp_vtab, pz_err, Tab::create)
+}
+
 /// Construct a VirtualTable.
 /// See [`sqlite3_module.xConnect`](https://sqlite.org/vtab.html)
 pub unsafe extern "C" fn vtab_connect<Tab: VirtualTable>(
     db: *mut sqlite3,
     _state: *mut c_void,
-    _argc: i32,
-    _argv: *const *const i8,
+    argc: i32,
+    argv: *const *const i8,
+    pp_vtab: *mut *mut sqlite3_vtab,
+    pz_err: *mut *mut i8
+) -> i32 {
+    vtab_init(db, argc, argv, pp_vtab, pz_err, Tab::connect)
+}
+
+/// Declare the table's columns and construct it with `init`
+unsafe fn vtab_init<Tab: VirtualTable>(
+    db: *mut sqlite3,
+    argc: i32,
+    argv: *const *const i8,
     pp_vtab: *mut *mut sqlite3_vtab,
-    _pz_err: *mut *mut i8
+    pz_err: *mut *mut i8,
+    init: fn(*mut sqlite3, &[String]) -> Result<Tab, String>
 ) -> i32 {
-    println!("connecting");
     or_die!(sql_call!(declare_vtab)(db, Tab::vtable_definition().as_ptr()));
-    
-    let vtab : VTabWrapper<Tab> = VTabWrapper{
-        base: Default::default(),
-        inner: Tab::create()
-    };
-    *pp_vtab = Box::into_raw(Box::new(vtab)) as *mut sqlite3_vtab;
-    SQLITE_OK
+    let args : Vec<String> = slice::from_raw_parts(argv, argc as usize).iter()
+        .map(|&arg| CStr::from_ptr(arg).to_string_lossy().into_owned())
+        .collect();
+    match init(db, &args) {
+        Ok(inner) => {
+            let vtab : VTabWrapper<Tab> = VTabWrapper{
+                base: Default::default(),
+                inner
+            };
+            *pp_vtab = Box::into_raw(Box::new(vtab)) as *mut sqlite3_vtab;
+            SQLITE_OK
+        },
+        Err(message) => {
+            *pz_err = mprintf(&message);
+            SQLITE_ERROR
+        }
+    }
 }
 
 /// Destroy a VirtualTable.
 /// See [`sqlite3_module.xDisconnect`](https://sqlite.org/vtab.html)
 pub unsafe extern "C" fn vtab_disconnect<Tab: VirtualTable>(vtab: *mut sqlite3_vtab) -> i32 {
     Box::from_raw(vtab as *mut VTabWrapper<Tab>);
-    println!("disconnecting");
     // It will be dropped when it goes out of scope here.
     SQLITE_OK
 }
 
+/// Destroy a VirtualTable and whatever it stored, for DROP TABLE.
+/// See [`sqlite3_module.xDestroy`](https://sqlite.org/vtab.html)
+pub unsafe extern "C" fn vtab_destroy<Tab: VirtualTable>(vtab: *mut sqlite3_vtab) -> i32 {
+    let pvtab = (vtab as *mut VTabWrapper<Tab>).as_mut().unwrap();
+    if let Err(message) = pvtab.destroy() {
+        set_vtab_error(vtab, &message);
+        return SQLITE_ERROR;
+    }
+    vtab_disconnect::<Tab>(vtab)
+}
+
+/// Insert, delete or update a row.
+/// See [`sqlite3_module.xUpdate`](https://sqlite.org/vtab.html)
+pub unsafe extern "C" fn vtab_update<Tab: VirtualTable>(
+    vtab: *mut sqlite3_vtab,
+    argc: i32,
+    argv: *mut *mut sqlite3_value,
+    p_rowid: *mut sqlite_int64
+) -> i32 {
+    let pvtab = (vtab as *mut VTabWrapper<Tab>).as_mut().unwrap();
+    match pvtab.update(slice::from_raw_parts(argv, argc as usize)) {
+        Ok(rowid) => {
+            *p_rowid = rowid;
+            SQLITE_OK
+        },
+        Err(message) => {
+            set_vtab_error(vtab, &message);
+            SQLITE_ERROR
+        }
+    }
+}
+
+/// Rename a VirtualTable.
+/// See [`sqlite3_module.xRename`](https://sqlite.org/vtab.html)
+pub unsafe extern "C" fn vtab_rename<Tab: VirtualTable>(
+    vtab: *mut sqlite3_vtab,
+    new_name: *const i8
+) -> i32 {
+    let pvtab = (vtab as *mut VTabWrapper<Tab>).as_mut().unwrap();
+    match pvtab.rename(&CStr::from_ptr(new_name).to_string_lossy()) {
+        Ok(()) => SQLITE_OK,
+        Err(message) => {
+            set_vtab_error(vtab, &message);
+            SQLITE_ERROR
+        }
+    }
+}
+
+/// Start a transaction, which needs nothing, but without this SQLite
+/// wouldn't report rollbacks.
+/// See [`sqlite3_module.xBegin`](https://sqlite.org/vtab.html)
+pub unsafe extern "C" fn vtab_begin<Tab: VirtualTable>(_vtab: *mut sqlite3_vtab) -> i32 {
+    SQLITE_OK
+}
+
+/// Roll back a transaction.
+/// See [`sqlite3_module.xRollback`](https://sqlite.org/vtab.html)
+pub unsafe extern "C" fn vtab_rollback<Tab: VirtualTable>(vtab: *mut sqlite3_vtab) -> i32 {
+    (vtab as *mut VTabWrapper<Tab>).as_mut().unwrap().rollback();
+    SQLITE_OK
+}
+
+/// Roll back to a savepoint.
+/// See [`sqlite3_module.xRollbackTo`](https://sqlite.org/vtab.html)
+pub unsafe extern "C" fn vtab_rollback_to<Tab: VirtualTable>(vtab: *mut sqlite3_vtab, _savepoint: i32) -> i32 {
+    vtab_rollback::<Tab>(vtab)
+}
+
 /// Construct a VirtualCursor.
 /// See [`sqlite3_module.xOpen`](https://sqlite.org/vtab.html)
 pub unsafe extern "C" fn vtab_open<Tab: VirtualTable>(
-    _p: *mut sqlite3_vtab,
+    p: *mut sqlite3_vtab,
     pp_cursor: *mut *mut sqlite3_vtab_cursor
-) -> i32 where
-    Tab: VirtualTable,
-    Tab::Cursor: Default
-{
-    println!("opening");
-    let cursor : CursorWrapper<Tab::Cursor> = Default::default();
+) -> i32 {
+    let vtab = (p as *mut VTabWrapper<Tab>).as_mut().unwrap();
+    let cursor : CursorWrapper<Tab::Cursor> = CursorWrapper{
+        base: Default::default(),
+        inner: vtab.open_cursor()
+    };
     *pp_cursor = Box::into_raw(Box::new(cursor)) as *mut sqlite3_vtab_cursor;
     SQLITE_OK
 }
@@ -108,7 +209,6 @@ pub unsafe extern "C" fn vtab_open<Tab: VirtualTable>(
 pub unsafe extern "C" fn cursor_close<Tab: VirtualTable>(
     cur: *mut sqlite3_vtab_cursor
 ) -> i32 {
-    println!("closing");
     Box::from_raw(cur as *mut CursorWrapper<Tab::Cursor>);
     SQLITE_OK
 }
@@ -200,12 +300,30 @@ pub unsafe extern "C" fn cursor_filter<Tab: VirtualTable>(
     } else {
         Some(CStr::from_ptr(idx_c_str))
     };
-    pcur.filter(
-        idx_num,
-        idx_str,
-        argv
-    );
-    SQLITE_OK
+    match pcur.filter(idx_num, idx_str, argv) {
+        Ok(()) => SQLITE_OK,
+        Err(message) => {
+            set_vtab_error((*cur).pVtab, &message);
+            SQLITE_ERROR
+        }
+    }
+}
+
+/// Report an error message through a virtual table.
+///
+/// SQLite takes ownership of `zErrMsg`, so it has to be allocated with
+/// `sqlite3_mprintf()` and any previous message has to be freed first.
+pub unsafe fn set_vtab_error(vtab: *mut sqlite3_vtab, message: &str) {
+    if !(*vtab).zErrMsg.is_null() {
+        sql_call!(free)((*vtab).zErrMsg as *mut c_void);
+    }
+    (*vtab).zErrMsg = mprintf(message);
+}
+
+/// Copy a message into memory from `sqlite3_mprintf()`, for SQLite to free
+unsafe fn mprintf(message: &str) -> *mut i8 {
+    let message = CString::new(message.replace('\0', "")).unwrap();
+    sql_call!(mprintf)(const_cstr!("%s").as_ptr(), message.as_ptr())
 }
 
 
@@ -1,5 +1,11 @@
 //! Extensions using Virtual Tables
 pub mod range;
+pub mod random_series;
+pub mod regex_matches;
+pub mod regex_split;
+pub mod vec_each;
+pub mod vec_hnsw;
+pub mod vec_knn;
 pub mod internals;
 
 use sqlite3_raw::*;
@@ -15,19 +21,42 @@ pub enum VirtualEponymity {
     Eponymous,
     EponymousOnly
 }
-pub trait VirtualTable {
+pub trait VirtualTable: Sized {
     type Cursor : VirtualCursor;
     /// Whether this virtual table can be used via `CREATE TABLE`, as a function, or both
     fn vtable_eponymity() -> VirtualEponymity;
     fn vtable_definition() -> ConstCStr;
     /// Create a virtual table with CREATE TABLE.
-    fn create() -> Self;
-    // fn destroy(Self);
-    
-    /// Create a virtual table using function
-    fn connect() -> Self;
-    //fn disconnect(Self);
-    
+    ///
+    /// `db` is the connection the table belongs to, for tables that need to
+    /// read other tables. `args` are the module name, the schema name, the
+    /// table name and then the arguments inside `USING module(...)`.
+    fn create(db: *mut sqlite3, args: &[String]) -> Result<Self, String>;
+
+    /// Create a virtual table using function, or reconnect to one made by
+    /// `create()`, with the same arguments
+    fn connect(db: *mut sqlite3, args: &[String]) -> Result<Self, String>;
+
+    /// Delete anything `create()` stored, for DROP TABLE
+    fn destroy(&mut self) -> Result<(), String> { Ok(()) }
+
+    /// Insert, delete or update a row, for tables whose module sets
+    /// `xUpdate`, returning the rowid of an inserted row
+    ///
+    /// See [`xUpdate`](https://sqlite.org/vtab.html#xupdate) for the
+    /// meaning of the arguments.
+    fn update(&mut self, _args: &[*mut sqlite3_value]) -> Result<i64, String> {
+        Err("this table is read-only".into())
+    }
+
+    /// Rename anything `create()` stored, for ALTER TABLE RENAME
+    fn rename(&mut self, _name: &str) -> Result<(), String> { Ok(()) }
+
+    /// Forget any state read from the database, because the transaction (or
+    /// a savepoint in it) was rolled back
+    fn rollback(&mut self) {}
+
+    /// Start a new scan of the table
     fn open_cursor(&mut self) -> Self::Cursor;
     // fn close_cursor(&mut self, cursor: Self::Cursor);
     
@@ -44,8 +73,10 @@ pub trait VirtualCursor {
     fn column(&self, i32) -> SQLiteReturn;
     fn rowid(&self) -> i64;
     fn eof(&self) -> bool;
+    /// Rewind the cursor to the first row, using the arguments chosen by
+    /// `best_index`. Errors are reported to SQLite as the query's error.
     fn filter(&mut self,
         idx_num: i32,
         idx_str: Option<&CStr>,
-        args: &[*mut sqlite3_value]);
+        args: &[*mut sqlite3_value]) -> Result<(), String>;
 }
\ No newline at end of file
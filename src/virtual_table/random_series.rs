@@ -0,0 +1,203 @@
+//! `random_series(count, distribution, params..., seed)`
+//!
+//! Generates `count` reproducible pseudo-random values. Each row's value
+//! depends only on the seed, the distribution and the rowid, so re-running a
+//! query (or reading the rows in a different order) gives identical output.
+//! The seed is optional and defaults to 0.
+//!
+//! | distribution  | parameters      | values   |
+//! |---------------|-----------------|----------|
+//! | `uniform`     | `low, high`     | reals    |
+//! | `normal`      | `mean, std_dev` | reals    |
+//! | `exponential` | `rate`          | reals    |
+//! | `poisson`     | `lambda`        | integers |
+//! | `binomial`    | `n, p`          | integers |
+//! | `zipf`        | `n, s`          | integers |
+//!
+//! ```sql
+//! SELECT value FROM random_series(1000, 'normal', 0, 1, 42);
+//! SELECT value FROM random_series(10, 'poisson', 3.5);
+//! ```
+use sqlite3_raw::*;
+use std::ffi::CStr;
+use const_cstr::ConstCStr;
+use virtual_table::*;
+use virtual_table::internals::*;
+use random::{Distribution, SplitMix64};
+use vector::type_name;
+
+impl VirtualTable for RandomSeriesVTab {
+    type Cursor = RandomSeriesCursor;
+    fn vtable_eponymity() -> VirtualEponymity {
+        VirtualEponymity::EponymousOnly
+    }
+    fn vtable_definition() -> ConstCStr {
+        const_cstr!("CREATE TABLE random_series(value, count HIDDEN, distribution HIDDEN, \
+            arg1 HIDDEN, arg2 HIDDEN, arg3 HIDDEN);")
+    }
+    fn create(_db: *mut sqlite3, _args: &[String])  -> Result<Self, String> { Ok(Default::default()) }
+    fn connect(_db: *mut sqlite3, _args: &[String]) -> Result<Self, String> { Ok(Default::default()) }
+    fn open_cursor(&mut self) -> Self::Cursor {
+        Default::default()
+    }
+    fn best_index(&self,
+        idx_info: &mut sqlite3_index_info,
+        constraints: &[sqlite3_index_info_sqlite3_index_constraint],
+        _order_bys: &[sqlite3_index_info_sqlite3_index_orderby],
+        constraint_usages: &mut [sqlite3_index_info_sqlite3_index_constraint_usage]
+    ){
+        // Hidden column i (counting from count) sets bit i of idx_num, and
+        // the arguments are passed to filter() in column order.
+        let mut column_constraints = [None; N_HIDDEN_COLUMNS];
+        for (i, constraint) in constraints.iter().enumerate() {
+            let column = constraint.iColumn - RANDOM_COLUMN_COUNT;
+            if constraint.usable != 0
+                && constraint.op == SQLITE_INDEX_CONSTRAINT_EQ
+                && column >= 0 && (column as usize) < N_HIDDEN_COLUMNS {
+                column_constraints[column as usize] = Some(i);
+            }
+        }
+        let mut idx_num = 0i32;
+        let mut n_arg = 0i32;
+        for (column, constraint) in column_constraints.iter().enumerate() {
+            if let Some(i) = *constraint {
+                n_arg += 1;
+                idx_num |= 1 << column;
+                constraint_usages[i].argvIndex = n_arg;
+                constraint_usages[i].omit = 1;
+            }
+        }
+        if idx_num & 3 == 3 {
+            idx_info.estimatedCost = 1.0;
+            idx_info.estimatedRows = 1000;
+        } else {
+            /* Without a count and a distribution filter() will fail, so
+            ** the planner should avoid this plan if at all possible */
+            idx_info.estimatedCost = 2147483647.0f64;
+            idx_info.estimatedRows = 2147483647;
+        }
+        idx_info.idxNum = idx_num;
+    }
+}
+impl VirtualCursor for RandomSeriesCursor {
+    fn next(&mut self) {
+        self.rowid += 1;
+    }
+    fn column(&self, index: i32) -> SQLiteReturn {
+        match index {
+            RANDOM_COLUMN_VALUE => match self.distribution {
+                Some(ref distribution) => {
+                    let mut rng = SplitMix64::for_row(self.seed, self.rowid);
+                    let x = distribution.sample(&mut rng);
+                    if distribution.is_discrete() { (x as i64).into() } else { x.into() }
+                },
+                None => SQLiteReturn::SQLiteNull
+            },
+            RANDOM_COLUMN_COUNT => self.count.into(),
+            RANDOM_COLUMN_DISTRIBUTION => self.distribution_name.clone().into(),
+            _ => self.args.get((index - RANDOM_COLUMN_ARG1) as usize)
+                .map(|&x| x.into())
+                .unwrap_or(SQLiteReturn::SQLiteNull)
+        }
+    }
+    fn rowid(&self) -> i64 { self.rowid }
+    fn eof(&self) -> bool {
+        self.rowid > self.count
+    }
+    fn filter(&mut self,
+        idx_num: i32,
+        _idx_str: Option<&CStr>,
+        args: &[*mut sqlite3_value]
+    ) -> Result<(), String> {
+        if idx_num & 3 != 3 {
+            return Err("random_series() requires a count and a distribution".into());
+        }
+        let values : Vec<SQLiteValue> = args.iter()
+            .map(|&arg| unsafe { SQLiteValue::from_raw_unchecked(arg) })
+            .collect();
+        self.count = values[0].into();
+        self.distribution_name = {let x: Option<String> = values[1].into(); x}
+            .ok_or("random_series() requires a distribution name")?;
+        self.args = values[2..].iter().enumerate().map(|(i, &x)| match x.value_type() {
+            SQLITE_INTEGER | SQLITE_FLOAT => Ok(f64::from(x)),
+            other => Err(format!("random_series() requires numbers after the distribution, \
+                but argument {} is {}", i + 3, type_name(other)))
+        }).collect::<Result<_, _>>()?;
+
+        let arity = Distribution::arity(&self.distribution_name).unwrap_or(self.args.len());
+        let (params, seed) = if self.args.len() > arity {
+            // The seed comes last, after the distribution's parameters
+            (&self.args[..arity], Some(self.args[arity]))
+        } else {
+            (&self.args[..], None)
+        };
+        if self.args.len() > arity + 1 {
+            return Err(format!("random_series() got {} arguments after the distribution \
+                but {} takes {} parameters and a seed",
+                self.args.len(), self.distribution_name, arity));
+        }
+        self.distribution = Some(Distribution::new(&self.distribution_name, params)?);
+        self.seed = match seed {
+            Some(x) if x.fract() == 0.0 => x as i64,
+            Some(_) => return Err("random_series() requires an integer seed".into()),
+            None => 0
+        };
+        self.rowid = 1;
+        Ok(())
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct RandomSeriesVTab {
+}
+
+
+#[derive(Default)]
+pub struct RandomSeriesCursor {
+    rowid: i64,
+    count: i64,
+    seed: i64,
+    distribution_name: String,
+    distribution: Option<Distribution>,
+    /// Everything after the distribution name: parameters, then maybe a seed
+    args: Vec<f64>
+}
+
+
+/*
+** This following structure defines all the methods for the
+** random_series virtual table.
+*/
+pub static RANDOM_SERIES_MODULE : sqlite3_module = sqlite3_module {
+    iVersion:       0,
+    xCreate:        None,
+    xConnect:       Some(vtab_connect::<RandomSeriesVTab>),
+    xBestIndex:     Some(vtab_best_index::<RandomSeriesVTab>),
+    xDisconnect:    Some(vtab_disconnect::<RandomSeriesVTab>),
+    xDestroy:       None,
+    xOpen:          Some(vtab_open::<RandomSeriesVTab>),   // open a cursor
+    xClose:         Some(cursor_close::<RandomSeriesVTab>),  // close a cursor
+    xFilter:        Some(cursor_filter::<RandomSeriesVTab>), // configure scan constraints
+    xNext:          Some(cursor_next::<RandomSeriesVTab>),   // advance a cursor
+    xEof:           Some(cursor_eof::<RandomSeriesVTab>),    // check for end of scan
+    xColumn:        Some(cursor_column::<RandomSeriesVTab>), // read data
+    xRowid:         Some(cursor_rowid::<RandomSeriesVTab>),  // read data
+    xUpdate:        None,
+    xBegin:         None,
+    xSync:          None,
+    xCommit:        None,
+    xRollback:      None,
+    xFindFunction:  None,
+    xRename:        None,
+    // The following are for version 2 and above
+    xSavepoint:     None,
+    xRelease:       None,
+    xRollbackTo:    None
+};
+
+const RANDOM_COLUMN_VALUE        : i32 = 0;
+const RANDOM_COLUMN_COUNT        : i32 = 1;
+const RANDOM_COLUMN_DISTRIBUTION : i32 = 2;
+const RANDOM_COLUMN_ARG1         : i32 = 3;
+const N_HIDDEN_COLUMNS : usize = 5;
@@ -13,8 +13,8 @@ impl VirtualTable for RangeVTab {
     fn vtable_definition() -> ConstCStr {
         const_cstr!("CREATE TABLE range(value, start HIDDEN, stop HIDDEN, step HIDDEN);")
     }
-    fn create()  -> Self { Default::default() }
-    fn connect() -> Self { Default::default() }
+    fn create(_db: *mut sqlite3, _args: &[String])  -> Result<Self, String> { Ok(Default::default()) }
+    fn connect(_db: *mut sqlite3, _args: &[String]) -> Result<Self, String> { Ok(Default::default()) }
     fn open_cursor(&mut self) -> Self::Cursor {
         Default::default()
     }
@@ -112,7 +112,7 @@ impl VirtualCursor for RangeCursor {
         idx_num: i32,
         _idx_str: Option<&CStr>,
         args: &[*mut sqlite3_value]
-    ) {
+    ) -> Result<(), String> {
         let mut i = 0usize;
         if idx_num & 1 != 0 {
             self.start = unsafe{sql_call!(value_int64)(args[i])};
@@ -146,6 +146,7 @@ impl VirtualCursor for RangeCursor {
             self.value = self.start;
         }
         self.rowid = 1;
+        Ok(())
     }
 }
 
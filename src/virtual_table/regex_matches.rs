@@ -0,0 +1,246 @@
+//! `regex_matches(pattern, subject[, flags])`
+//!
+//! One row for every match of the pattern in the subject, so it can be used
+//! as a lateral join. An optional third argument gives the flags, as for the
+//! scalar functions in `regexp`:
+//!
+//! ```sql
+//! SELECT logs.id, m.text FROM logs, regex_matches('(\d+)ms', logs.line) AS m;
+//! ```
+//!
+//! | column   | meaning                                                       |
+//! |----------|---------------------------------------------------------------|
+//! | `idx`    | The number of the match, counting from 1                      |
+//! | `start`  | Where the match starts, counting characters from 1            |
+//! | `end`    | Where the match ends (exclusive), so `end - start` is its length |
+//! | `text`   | The text of the match                                         |
+//! | `groups` | A JSON object of the groups, keyed by name or else by number  |
+//!
+//! Positions count characters like `substr()` and `instr()` do, so
+//! `substr(subject, start, end - start) = text`.
+use sqlite3_raw::*;
+use std::ffi::CStr;
+use const_cstr::ConstCStr;
+use serde_json::Value;
+use virtual_table::*;
+use virtual_table::internals::*;
+use regexp;
+use regexp::engine::{Captures, Pattern};
+
+impl VirtualTable for RegexMatchesVTab {
+    type Cursor = RegexMatchesCursor;
+    fn vtable_eponymity() -> VirtualEponymity {
+        VirtualEponymity::EponymousOnly
+    }
+    fn vtable_definition() -> ConstCStr {
+        const_cstr!("CREATE TABLE regex_matches(idx, start, end, text, groups, \
+            pattern HIDDEN, subject HIDDEN, flags HIDDEN);")
+    }
+    fn create(_db: *mut sqlite3, _args: &[String])  -> Result<Self, String> { Ok(Default::default()) }
+    fn connect(_db: *mut sqlite3, _args: &[String]) -> Result<Self, String> { Ok(Default::default()) }
+    fn open_cursor(&mut self) -> Self::Cursor {
+        Default::default()
+    }
+    fn best_index(&self,
+        idx_info: &mut sqlite3_index_info,
+        constraints: &[sqlite3_index_info_sqlite3_index_constraint],
+        _order_bys: &[sqlite3_index_info_sqlite3_index_orderby],
+        constraint_usages: &mut [sqlite3_index_info_sqlite3_index_constraint_usage]
+    ){
+        best_index_pattern_subject(idx_info, constraints, constraint_usages,
+            MATCHES_COLUMN_PATTERN, MATCHES_COLUMN_SUBJECT, MATCHES_COLUMN_FLAGS);
+    }
+}
+
+/// Plan a scan of a table function taking a pattern, a subject and flags
+///
+/// The pattern and subject are required, and are passed to `filter()` in
+/// that order with bit 1 of `idx_num` set. The flags are optional, and if
+/// given come third with bit 2 set. Any other plan (such as running before
+/// the table that supplies the subject) is made too expensive to choose.
+pub fn best_index_pattern_subject(
+    idx_info: &mut sqlite3_index_info,
+    constraints: &[sqlite3_index_info_sqlite3_index_constraint],
+    constraint_usages: &mut [sqlite3_index_info_sqlite3_index_constraint_usage],
+    pattern_column: i32,
+    subject_column: i32,
+    flags_column: i32
+){
+    let mut pattern_idx = None;
+    let mut subject_idx = None;
+    let mut flags_idx = None;
+    for (i, constraint) in constraints.iter().enumerate() {
+        if constraint.usable != 0 && constraint.op == SQLITE_INDEX_CONSTRAINT_EQ {
+            if constraint.iColumn == pattern_column {
+                pattern_idx = Some(i);
+            } else if constraint.iColumn == subject_column {
+                subject_idx = Some(i);
+            } else if constraint.iColumn == flags_column {
+                flags_idx = Some(i);
+            }
+        }
+    }
+    match (pattern_idx, subject_idx) {
+        (Some(pattern), Some(subject)) => {
+            constraint_usages[pattern].argvIndex = 1;
+            constraint_usages[pattern].omit = 1;
+            constraint_usages[subject].argvIndex = 2;
+            constraint_usages[subject].omit = 1;
+            idx_info.idxNum = 1;
+            if let Some(flags) = flags_idx {
+                constraint_usages[flags].argvIndex = 3;
+                constraint_usages[flags].omit = 1;
+                idx_info.idxNum |= 2;
+            }
+            idx_info.estimatedCost = 10.0;
+            idx_info.estimatedRows = 10;
+        },
+        _ => {
+            idx_info.idxNum = 0;
+            idx_info.estimatedCost = 2147483647.0f64;
+            idx_info.estimatedRows = 2147483647;
+        }
+    }
+}
+
+impl VirtualCursor for RegexMatchesCursor {
+    fn next(&mut self) {
+        self.rowid += 1;
+    }
+    fn column(&self, index: i32) -> SQLiteReturn {
+        let row = &self.matches[self.rowid as usize - 1];
+        match index {
+            MATCHES_COLUMN_IDX => self.rowid.into(),
+            MATCHES_COLUMN_START => row.start.into(),
+            MATCHES_COLUMN_END => row.end.into(),
+            MATCHES_COLUMN_TEXT => row.text.clone().into(),
+            MATCHES_COLUMN_GROUPS => row.groups.clone().into(),
+            MATCHES_COLUMN_PATTERN => self.pattern.clone().into(),
+            MATCHES_COLUMN_SUBJECT => self.subject.clone().into(),
+            _ => self.flags.clone().into()
+        }
+    }
+    fn rowid(&self) -> i64 { self.rowid }
+    fn eof(&self) -> bool {
+        self.rowid as usize > self.matches.len()
+    }
+    fn filter(&mut self,
+        idx_num: i32,
+        _idx_str: Option<&CStr>,
+        args: &[*mut sqlite3_value]
+    ) -> Result<(), String> {
+        if idx_num & 1 == 0 {
+            return Err("regex_matches() requires a pattern and a subject".into());
+        }
+        let args : Vec<Option<String>> = args.iter()
+            .map(|&arg| unsafe { SQLiteValue::from_raw_unchecked(arg) }.into())
+            .collect();
+        self.rowid = 1;
+        self.matches.clear();
+        // A NULL argument matches nothing
+        if let Some(args) = args.into_iter().collect::<Option<Vec<String>>>() {
+            let flags = args.get(2).cloned().unwrap_or_default();
+            let regex = regexp::compile("regex_matches", &args[0], &flags)?;
+            self.matches = find_matches(&regex, &args[1])
+                .map_err(|e| format!("regex_matches(): {}", e))?;
+            self.subject = args[1].clone();
+            self.pattern = args[0].clone();
+            self.flags = flags;
+        }
+        Ok(())
+    }
+}
+
+/// Find every match, converting byte offsets into character positions
+fn find_matches(regex: &Pattern, subject: &str) -> Result<Vec<Match>, String> {
+    let mut matches = vec![];
+    let mut byte = 0;
+    let mut position = 1;
+    for caps in regex.captures_all(subject)? {
+        let (start_byte, end_byte) = caps.span(0).unwrap();
+        let text = &subject[start_byte..end_byte];
+        position += subject[byte..start_byte].chars().count() as i64;
+        let start = position;
+        position += text.chars().count() as i64;
+        byte = end_byte;
+        matches.push(Match {
+            start,
+            end: position,
+            text: text.to_string(),
+            groups: groups_json(regex, &caps)
+        });
+    }
+    Ok(matches)
+}
+
+/// Format every group but the whole match as a JSON object, in pattern order
+fn groups_json(regex: &Pattern, caps: &Captures) -> String {
+    let fields : Vec<String> = regex.capture_names().iter().enumerate().skip(1)
+        .map(|(i, name)| format!("{}:{}",
+            Value::from(name.map_or_else(|| i.to_string(), |name| name.to_string())),
+            caps.get(i).map_or(Value::Null, Value::from)))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct RegexMatchesVTab {
+}
+
+struct Match {
+    start: i64,
+    end: i64,
+    text: String,
+    groups: String
+}
+
+#[derive(Default)]
+pub struct RegexMatchesCursor {
+    rowid: i64,
+    pattern: String,
+    subject: String,
+    flags: String,
+    matches: Vec<Match>
+}
+
+
+/*
+** This following structure defines all the methods for the
+** regex_matches virtual table.
+*/
+pub static REGEX_MATCHES_MODULE : sqlite3_module = sqlite3_module {
+    iVersion:       0,
+    xCreate:        None,
+    xConnect:       Some(vtab_connect::<RegexMatchesVTab>),
+    xBestIndex:     Some(vtab_best_index::<RegexMatchesVTab>),
+    xDisconnect:    Some(vtab_disconnect::<RegexMatchesVTab>),
+    xDestroy:       None,
+    xOpen:          Some(vtab_open::<RegexMatchesVTab>),   // open a cursor
+    xClose:         Some(cursor_close::<RegexMatchesVTab>),  // close a cursor
+    xFilter:        Some(cursor_filter::<RegexMatchesVTab>), // configure scan constraints
+    xNext:          Some(cursor_next::<RegexMatchesVTab>),   // advance a cursor
+    xEof:           Some(cursor_eof::<RegexMatchesVTab>),    // check for end of scan
+    xColumn:        Some(cursor_column::<RegexMatchesVTab>), // read data
+    xRowid:         Some(cursor_rowid::<RegexMatchesVTab>),  // read data
+    xUpdate:        None,
+    xBegin:         None,
+    xSync:          None,
+    xCommit:        None,
+    xRollback:      None,
+    xFindFunction:  None,
+    xRename:        None,
+    // The following are for version 2 and above
+    xSavepoint:     None,
+    xRelease:       None,
+    xRollbackTo:    None
+};
+
+const MATCHES_COLUMN_IDX     : i32 = 0;
+const MATCHES_COLUMN_START   : i32 = 1;
+const MATCHES_COLUMN_END     : i32 = 2;
+const MATCHES_COLUMN_TEXT    : i32 = 3;
+const MATCHES_COLUMN_GROUPS  : i32 = 4;
+const MATCHES_COLUMN_PATTERN : i32 = 5;
+const MATCHES_COLUMN_SUBJECT : i32 = 6;
+const MATCHES_COLUMN_FLAGS   : i32 = 7;
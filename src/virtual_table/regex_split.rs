@@ -0,0 +1,143 @@
+//! `regex_split(pattern, subject[, flags])`
+//!
+//! One row for each piece of the subject between matches of the pattern,
+//! with its ordinal in `idx` (counting from 1) and its text in `value`. An
+//! optional third argument gives the flags, as for the scalar functions in
+//! `regexp`:
+//!
+//! ```sql
+//! SELECT idx, value FROM regex_split('\s*,\s*', 'a ,b,  c');
+//! ```
+//!
+//! Like `str::split`, a match at the very start or end of the subject gives
+//! an empty piece there, so splitting `' a b'` on `'\s+'` gives
+//! `''`, `'a'` and `'b'`.
+use sqlite3_raw::*;
+use std::ffi::CStr;
+use const_cstr::ConstCStr;
+use virtual_table::*;
+use virtual_table::internals::*;
+use virtual_table::regex_matches::best_index_pattern_subject;
+use regexp;
+
+impl VirtualTable for RegexSplitVTab {
+    type Cursor = RegexSplitCursor;
+    fn vtable_eponymity() -> VirtualEponymity {
+        VirtualEponymity::EponymousOnly
+    }
+    fn vtable_definition() -> ConstCStr {
+        const_cstr!("CREATE TABLE regex_split(idx, value, pattern HIDDEN, subject HIDDEN, \
+            flags HIDDEN);")
+    }
+    fn create(_db: *mut sqlite3, _args: &[String])  -> Result<Self, String> { Ok(Default::default()) }
+    fn connect(_db: *mut sqlite3, _args: &[String]) -> Result<Self, String> { Ok(Default::default()) }
+    fn open_cursor(&mut self) -> Self::Cursor {
+        Default::default()
+    }
+    fn best_index(&self,
+        idx_info: &mut sqlite3_index_info,
+        constraints: &[sqlite3_index_info_sqlite3_index_constraint],
+        _order_bys: &[sqlite3_index_info_sqlite3_index_orderby],
+        constraint_usages: &mut [sqlite3_index_info_sqlite3_index_constraint_usage]
+    ){
+        best_index_pattern_subject(idx_info, constraints, constraint_usages,
+            SPLIT_COLUMN_PATTERN, SPLIT_COLUMN_SUBJECT, SPLIT_COLUMN_FLAGS);
+    }
+}
+
+impl VirtualCursor for RegexSplitCursor {
+    fn next(&mut self) {
+        self.rowid += 1;
+    }
+    fn column(&self, index: i32) -> SQLiteReturn {
+        match index {
+            SPLIT_COLUMN_IDX => self.rowid.into(),
+            SPLIT_COLUMN_VALUE => self.pieces[self.rowid as usize - 1].clone().into(),
+            SPLIT_COLUMN_PATTERN => self.pattern.clone().into(),
+            SPLIT_COLUMN_SUBJECT => self.subject.clone().into(),
+            _ => self.flags.clone().into()
+        }
+    }
+    fn rowid(&self) -> i64 { self.rowid }
+    fn eof(&self) -> bool {
+        self.rowid as usize > self.pieces.len()
+    }
+    fn filter(&mut self,
+        idx_num: i32,
+        _idx_str: Option<&CStr>,
+        args: &[*mut sqlite3_value]
+    ) -> Result<(), String> {
+        if idx_num & 1 == 0 {
+            return Err("regex_split() requires a pattern and a subject".into());
+        }
+        let args : Vec<Option<String>> = args.iter()
+            .map(|&arg| unsafe { SQLiteValue::from_raw_unchecked(arg) }.into())
+            .collect();
+        self.rowid = 1;
+        self.pieces.clear();
+        // A NULL argument has no pieces
+        if let Some(args) = args.into_iter().collect::<Option<Vec<String>>>() {
+            let flags = args.get(2).cloned().unwrap_or_default();
+            let regex = regexp::compile("regex_split", &args[0], &flags)?;
+            self.pieces = regex.split(&args[1])
+                .map_err(|e| format!("regex_split(): {}", e))?
+                .into_iter().map(str::to_string).collect();
+            self.subject = args[1].clone();
+            self.pattern = args[0].clone();
+            self.flags = flags;
+        }
+        Ok(())
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct RegexSplitVTab {
+}
+
+#[derive(Default)]
+pub struct RegexSplitCursor {
+    rowid: i64,
+    pattern: String,
+    subject: String,
+    flags: String,
+    pieces: Vec<String>
+}
+
+
+/*
+** This following structure defines all the methods for the
+** regex_split virtual table.
+*/
+pub static REGEX_SPLIT_MODULE : sqlite3_module = sqlite3_module {
+    iVersion:       0,
+    xCreate:        None,
+    xConnect:       Some(vtab_connect::<RegexSplitVTab>),
+    xBestIndex:     Some(vtab_best_index::<RegexSplitVTab>),
+    xDisconnect:    Some(vtab_disconnect::<RegexSplitVTab>),
+    xDestroy:       None,
+    xOpen:          Some(vtab_open::<RegexSplitVTab>),   // open a cursor
+    xClose:         Some(cursor_close::<RegexSplitVTab>),  // close a cursor
+    xFilter:        Some(cursor_filter::<RegexSplitVTab>), // configure scan constraints
+    xNext:          Some(cursor_next::<RegexSplitVTab>),   // advance a cursor
+    xEof:           Some(cursor_eof::<RegexSplitVTab>),    // check for end of scan
+    xColumn:        Some(cursor_column::<RegexSplitVTab>), // read data
+    xRowid:         Some(cursor_rowid::<RegexSplitVTab>),  // read data
+    xUpdate:        None,
+    xBegin:         None,
+    xSync:          None,
+    xCommit:        None,
+    xRollback:      None,
+    xFindFunction:  None,
+    xRename:        None,
+    // The following are for version 2 and above
+    xSavepoint:     None,
+    xRelease:       None,
+    xRollbackTo:    None
+};
+
+const SPLIT_COLUMN_IDX     : i32 = 0;
+const SPLIT_COLUMN_VALUE   : i32 = 1;
+const SPLIT_COLUMN_PATTERN : i32 = 2;
+const SPLIT_COLUMN_SUBJECT : i32 = 3;
+const SPLIT_COLUMN_FLAGS   : i32 = 4;
@@ -0,0 +1,154 @@
+//! `vec_each(v)`
+//!
+//! One row for each element of a vector, so elements can be filtered and
+//! aggregated in SQL. `vec_collect()` puts them back together:
+//!
+//! ```sql
+//! -- Zero the negative elements of every vector
+//! SELECT id, (SELECT vec_collect(max(value, 0), vec_type(embedding) ORDER BY idx)
+//!             FROM vec_each(embedding))
+//! FROM docs;
+//! ```
+//!
+//! | column  | meaning                                  |
+//! |---------|------------------------------------------|
+//! | `idx`   | The position of the element, from 1      |
+//! | `value` | The element                              |
+//!
+//! Elements of i8 and bit vectors are integers, and the others are reals;
+//! q8 elements are the values they stand for. A NULL vector has no rows.
+use sqlite3_raw::*;
+use std::ffi::CStr;
+use const_cstr::ConstCStr;
+use virtual_table::*;
+use virtual_table::internals::*;
+use vector::{vector_arg, Vector};
+
+impl VirtualTable for VecEachVTab {
+    type Cursor = VecEachCursor;
+    fn vtable_eponymity() -> VirtualEponymity {
+        VirtualEponymity::EponymousOnly
+    }
+    fn vtable_definition() -> ConstCStr {
+        const_cstr!("CREATE TABLE vec_each(idx, value, vector HIDDEN);")
+    }
+    fn create(_db: *mut sqlite3, _args: &[String])  -> Result<Self, String> { Ok(Default::default()) }
+    fn connect(_db: *mut sqlite3, _args: &[String]) -> Result<Self, String> { Ok(Default::default()) }
+    fn open_cursor(&mut self) -> Self::Cursor {
+        Default::default()
+    }
+    fn best_index(&self,
+        idx_info: &mut sqlite3_index_info,
+        constraints: &[sqlite3_index_info_sqlite3_index_constraint],
+        order_bys: &[sqlite3_index_info_sqlite3_index_orderby],
+        constraint_usages: &mut [sqlite3_index_info_sqlite3_index_constraint_usage]
+    ){
+        let vector = constraints.iter().position(|constraint| constraint.usable != 0
+            && constraint.op == SQLITE_INDEX_CONSTRAINT_EQ
+            && constraint.iColumn == EACH_COLUMN_VECTOR);
+        match vector {
+            Some(i) => {
+                constraint_usages[i].argvIndex = 1;
+                constraint_usages[i].omit = 1;
+                idx_info.idxNum = 1;
+                idx_info.estimatedCost = 100.0;
+                idx_info.estimatedRows = 100;
+            },
+            None => {
+                idx_info.idxNum = 0;
+                idx_info.estimatedCost = 2147483647.0f64;
+                idx_info.estimatedRows = 2147483647;
+            }
+        }
+        // The rows already come out in order
+        if let [ref order_by] = *order_bys {
+            if order_by.iColumn == EACH_COLUMN_IDX && order_by.desc == 0 {
+                idx_info.orderByConsumed = 1;
+            }
+        }
+    }
+}
+
+impl VirtualCursor for VecEachCursor {
+    fn next(&mut self) {
+        self.rowid += 1;
+    }
+    fn column(&self, index: i32) -> SQLiteReturn {
+        let i = self.rowid as usize - 1;
+        match index {
+            EACH_COLUMN_IDX => self.rowid.into(),
+            EACH_COLUMN_VALUE => match self.vector {
+                Some(Vector::I8(ref v)) => i64::from(v[i]).into(),
+                Some(Vector::Bit(ref v)) => i64::from(v[i]).into(),
+                _ => self.values[i].into()
+            },
+            _ => self.vector.as_ref().map_or(SQLiteReturn::SQLiteNull, |v| v.encode().into())
+        }
+    }
+    fn rowid(&self) -> i64 { self.rowid }
+    fn eof(&self) -> bool {
+        self.rowid as usize > self.values.len()
+    }
+    fn filter(&mut self,
+        idx_num: i32,
+        _idx_str: Option<&CStr>,
+        args: &[*mut sqlite3_value]
+    ) -> Result<(), String> {
+        if idx_num & 1 == 0 {
+            return Err("vec_each() requires a vector".into());
+        }
+        self.rowid = 1;
+        self.vector = vector_arg("vec_each", unsafe { SQLiteValue::from_raw_unchecked(args[0]) })?;
+        self.values = self.vector.as_ref().map_or(vec![], Vector::to_f64);
+        Ok(())
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct VecEachVTab {
+}
+
+#[derive(Default)]
+pub struct VecEachCursor {
+    rowid: i64,
+    vector: Option<Vector>,
+    /// Every element, converted once
+    values: Vec<f64>
+}
+
+
+/*
+** This following structure defines all the methods for the
+** vec_each virtual table.
+*/
+pub static VEC_EACH_MODULE : sqlite3_module = sqlite3_module {
+    iVersion:       0,
+    xCreate:        None,
+    xConnect:       Some(vtab_connect::<VecEachVTab>),
+    xBestIndex:     Some(vtab_best_index::<VecEachVTab>),
+    xDisconnect:    Some(vtab_disconnect::<VecEachVTab>),
+    xDestroy:       None,
+    xOpen:          Some(vtab_open::<VecEachVTab>),   // open a cursor
+    xClose:         Some(cursor_close::<VecEachVTab>),  // close a cursor
+    xFilter:        Some(cursor_filter::<VecEachVTab>), // configure scan constraints
+    xNext:          Some(cursor_next::<VecEachVTab>),   // advance a cursor
+    xEof:           Some(cursor_eof::<VecEachVTab>),    // check for end of scan
+    xColumn:        Some(cursor_column::<VecEachVTab>), // read data
+    xRowid:         Some(cursor_rowid::<VecEachVTab>),  // read data
+    xUpdate:        None,
+    xBegin:         None,
+    xSync:          None,
+    xCommit:        None,
+    xRollback:      None,
+    xFindFunction:  None,
+    xRename:        None,
+    // The following are for version 2 and above
+    xSavepoint:     None,
+    xRelease:       None,
+    xRollbackTo:    None
+};
+
+const EACH_COLUMN_IDX    : i32 = 0;
+const EACH_COLUMN_VALUE  : i32 = 1;
+const EACH_COLUMN_VECTOR : i32 = 2;
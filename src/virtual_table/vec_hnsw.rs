@@ -0,0 +1,512 @@
+//! `CREATE VIRTUAL TABLE name USING vec_hnsw(dim=N, ...)`
+//!
+//! An approximate nearest neighbour index over vectors, using an HNSW graph
+//! (see `vector::hnsw`). Insert vectors into its `embedding` column, with
+//! the rowid of the row they describe, and search with MATCH and k:
+//!
+//! ```sql
+//! CREATE VIRTUAL TABLE doc_index USING vec_hnsw(dim=768, metric=cosine);
+//! INSERT INTO doc_index(rowid, embedding) SELECT id, embedding FROM docs;
+//!
+//! SELECT docs.title, doc_index.distance
+//! FROM doc_index JOIN docs ON docs.id = doc_index.rowid
+//! WHERE doc_index.embedding MATCH vec_from_json(:query) AND k = 10;
+//! ```
+//!
+//! | option            | meaning                                              |
+//! |-------------------|------------------------------------------------------|
+//! | `dim`             | The number of dimensions, which is required          |
+//! | `metric`          | `l2` (the default) or another metric of `vec_knn`    |
+//! | `m`               | Links per node on each layer, 16 by default          |
+//! | `ef_construction` | Candidates to link a new node to, 200 by default     |
+//! | `ef_search`       | Candidates to search, 40 by default, and at least k  |
+//!
+//! Larger `m`, `ef_construction` and `ef_search` find the true nearest
+//! neighbours more often (the recall), but take longer and, for `m`, more
+//! space. A search returns the k rows it found, nearest first, with their
+//! `distance`; scanning the table without MATCH returns every row.
+//!
+//! The graph is kept in `name_nodes` and `name_edges`, with a count of the
+//! changes to them in `name_info`, and none of them should be changed
+//! directly. It's read into memory when the table is first used, and again
+//! when the count isn't what this connection last saw, because another
+//! connection changed it or a transaction was rolled back.
+use sqlite3_raw::*;
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::rc::Rc;
+use const_cstr::ConstCStr;
+use virtual_table::*;
+use virtual_table::internals::*;
+use vector::{type_name, Vector};
+use vector::distance::{Metric, Neighbour};
+use vector::hnsw::{Hnsw, Node, Params};
+
+impl VirtualTable for VecHnswVTab {
+    type Cursor = VecHnswCursor;
+    fn vtable_eponymity() -> VirtualEponymity {
+        VirtualEponymity::NonEponymous
+    }
+    fn vtable_definition() -> ConstCStr {
+        const_cstr!("CREATE TABLE x(embedding, distance HIDDEN, k HIDDEN);")
+    }
+    fn create(db: *mut sqlite3, args: &[String]) -> Result<Self, String> {
+        let index = Index::new(db, args)?;
+        index.create_tables()?;
+        Ok(VecHnswVTab { index: Rc::new(RefCell::new(index)) })
+    }
+    fn connect(db: *mut sqlite3, args: &[String]) -> Result<Self, String> {
+        Ok(VecHnswVTab { index: Rc::new(RefCell::new(Index::new(db, args)?)) })
+    }
+    fn destroy(&mut self) -> Result<(), String> {
+        let index = self.index.borrow();
+        unsafe {
+            execute(index.db, &format!("DROP TABLE {}", index.shadow("nodes")), &[])?;
+            execute(index.db, &format!("DROP TABLE {}", index.shadow("edges")), &[])?;
+            execute(index.db, &format!("DROP TABLE {}", index.shadow("info")), &[])
+        }
+    }
+    fn update(&mut self, args: &[*mut sqlite3_value]) -> Result<i64, String> {
+        let values : Vec<SQLiteValue> = args.iter()
+            .map(|&arg| unsafe { SQLiteValue::from_raw_unchecked(arg) })
+            .collect();
+        let mut index = self.index.borrow_mut();
+        if values.len() == 1 {
+            let result = index.delete(values[0].into());
+            return index.forget_on_error(result).map(|_| 0);
+        }
+        // Read the new row before changing anything
+        let rowid = match values[1].value_type() {
+            SQLITE_NULL => None,
+            SQLITE_INTEGER => Some(i64::from(values[1])),
+            other => return Err(format!("vec_hnsw: the rowid should be an integer, not {}", type_name(other)))
+        };
+        let blob = match values[2].value_type() {
+            SQLITE_BLOB => {let x: Option<Vec<u8>> = values[2].into(); x}.unwrap_or_default(),
+            other => return Err(format!("vec_hnsw: the embedding should be a vector, not {}", type_name(other)))
+        };
+        let vector = Vector::decode(&blob).map_err(|e| format!("vec_hnsw: {}", e))?;
+        let old = if values[0].is_null() { None } else { Some(i64::from(values[0])) };
+        index.check_insert(rowid, old, &vector)?;
+        let result = old.map_or(Ok(()), |old| index.delete(old))
+            .and_then(|_| index.insert(rowid, &vector, blob));
+        index.forget_on_error(result)
+    }
+    fn rename(&mut self, name: &str) -> Result<(), String> {
+        let mut index = self.index.borrow_mut();
+        for suffix in ["nodes", "edges", "info"].iter() {
+            let sql = format!("ALTER TABLE {} RENAME TO \"{}_{}\"",
+                index.shadow(suffix), name.replace('"', "\"\""), suffix);
+            unsafe { execute(index.db, &sql, &[])? };
+        }
+        index.name = name.to_string();
+        Ok(())
+    }
+    fn rollback(&mut self) {
+        self.index.borrow_mut().graph = None;
+    }
+    fn open_cursor(&mut self) -> Self::Cursor {
+        VecHnswCursor {
+            index: self.index.clone(),
+            position: 0,
+            rows: vec![],
+            k: None
+        }
+    }
+    fn best_index(&self,
+        idx_info: &mut sqlite3_index_info,
+        constraints: &[sqlite3_index_info_sqlite3_index_constraint],
+        order_bys: &[sqlite3_index_info_sqlite3_index_orderby],
+        constraint_usages: &mut [sqlite3_index_info_sqlite3_index_constraint_usage]
+    ){
+        let mut query_idx = None;
+        let mut k_idx = None;
+        let mut unusable_match = false;
+        for (i, constraint) in constraints.iter().enumerate() {
+            if constraint.iColumn == HNSW_COLUMN_EMBEDDING && constraint.op == SQLITE_INDEX_CONSTRAINT_MATCH {
+                if constraint.usable != 0 {
+                    query_idx = Some(i);
+                } else {
+                    unusable_match = true;
+                }
+            } else if constraint.iColumn == HNSW_COLUMN_K && constraint.op == SQLITE_INDEX_CONSTRAINT_EQ
+                && constraint.usable != 0 {
+                k_idx = Some(i);
+            }
+        }
+        idx_info.idxNum = 0;
+        if let Some(query) = query_idx {
+            constraint_usages[query].argvIndex = 1;
+            constraint_usages[query].omit = 1;
+            idx_info.idxNum = 1;
+            if let Some(k) = k_idx {
+                constraint_usages[k].argvIndex = 2;
+                constraint_usages[k].omit = 1;
+                idx_info.idxNum |= 2;
+            }
+            idx_info.estimatedCost = 1000.0;
+            idx_info.estimatedRows = 10;
+            // The rows already come out nearest first
+            if let [ref order_by] = *order_bys {
+                if order_by.iColumn == HNSW_COLUMN_DISTANCE && order_by.desc == 0 {
+                    idx_info.orderByConsumed = 1;
+                }
+            }
+        } else if unusable_match {
+            /* SQLite can't evaluate MATCH itself, so the planner should
+            ** avoid this plan if at all possible */
+            idx_info.estimatedCost = 2147483647.0f64;
+            idx_info.estimatedRows = 2147483647;
+        } else {
+            idx_info.estimatedCost = 1000000.0;
+            idx_info.estimatedRows = 1000000;
+        }
+    }
+}
+
+impl VirtualCursor for VecHnswCursor {
+    fn next(&mut self) {
+        self.position += 1;
+    }
+    fn column(&self, index: i32) -> SQLiteReturn {
+        let row = self.rows[self.position];
+        match index {
+            HNSW_COLUMN_EMBEDDING => {
+                // The stored vector, which is exactly as it was inserted
+                let index = self.index.borrow();
+                let mut embedding = SQLiteReturn::SQLiteNull;
+                let sql = format!("SELECT vector FROM {} WHERE id = {}", index.shadow("nodes"), row.rowid);
+                let found = unsafe { query_each(index.db, &sql, |values| {
+                    let blob: Option<Vec<u8>> = values[0].into();
+                    embedding = blob.map_or(SQLiteReturn::SQLiteNull, SQLiteReturn::from);
+                    Ok(())
+                }) };
+                found.map_or(SQLiteReturn::SQLiteNull, |_| embedding)
+            },
+            HNSW_COLUMN_DISTANCE if self.k.is_some() => row.distance.into(),
+            HNSW_COLUMN_K => self.k.map_or(SQLiteReturn::SQLiteNull, SQLiteReturn::from),
+            _ => SQLiteReturn::SQLiteNull
+        }
+    }
+    fn rowid(&self) -> i64 {
+        self.rows[self.position].rowid
+    }
+    fn eof(&self) -> bool {
+        self.position >= self.rows.len()
+    }
+    fn filter(&mut self,
+        idx_num: i32,
+        _idx_str: Option<&CStr>,
+        args: &[*mut sqlite3_value]
+    ) -> Result<(), String> {
+        let values : Vec<SQLiteValue> = args.iter()
+            .map(|&arg| unsafe { SQLiteValue::from_raw_unchecked(arg) })
+            .collect();
+        self.position = 0;
+        self.rows.clear();
+        self.k = None;
+        let mut index = self.index.borrow_mut();
+        let graph = index.graph()?;
+        if idx_num & 1 == 0 {
+            self.rows = graph.ids().into_iter().map(|rowid| Neighbour { distance: 0.0, rowid }).collect();
+            return Ok(());
+        }
+        if idx_num & 2 == 0 {
+            return Err("vec_hnsw: a MATCH needs k too, like `WHERE embedding MATCH ? AND k = 10`".into());
+        }
+        let k = match values[1].value_type() {
+            SQLITE_INTEGER => i64::from(values[1]),
+            other => return Err(format!("vec_hnsw: k should be an integer, not {}", type_name(other)))
+        };
+        if k < 0 {
+            return Err("vec_hnsw: k can't be negative".into());
+        }
+        self.k = Some(k);
+        // A NULL query is near nothing
+        let query = match values[0].value_type() {
+            SQLITE_NULL => return Ok(()),
+            SQLITE_BLOB => {
+                let blob: Vec<u8> = {let x: Option<Vec<u8>> = values[0].into(); x}.unwrap_or_default();
+                Vector::decode(&blob).map_err(|e| format!("vec_hnsw: {}", e))?.to_f64()
+            },
+            other => return Err(format!("vec_hnsw: MATCH needs a vector, not {}", type_name(other)))
+        };
+        if graph.check(&query).map_err(|e| format!("vec_hnsw: {}", e))? {
+            self.rows = graph.search(&query, k as usize);
+        }
+        Ok(())
+    }
+}
+
+/// The options of an index, and its graph once it has been read
+struct Index {
+    db: *mut sqlite3,
+    schema: String,
+    name: String,
+    params: Params,
+    graph: Option<Hnsw>,
+    /// The count in the info table as of the graph in memory
+    changes: i64
+}
+impl Index {
+    /// Parse the options from `CREATE VIRTUAL TABLE`
+    fn new(db: *mut sqlite3, args: &[String]) -> Result<Index, String> {
+        let mut dims = None;
+        let mut params = Params { dims: 0, metric: Metric::L2, m: 16, ef_construction: 200, ef_search: 40 };
+        for arg in &args[3..] {
+            let (key, value) = match arg.find('=') {
+                Some(i) => (arg[..i].trim().to_lowercase(), arg[i + 1..].trim()),
+                None => return Err(format!("vec_hnsw: expected an option like key=value, not '{}'", arg))
+            };
+            let number = || value.parse::<usize>().ok().filter(|&n| n > 0)
+                .ok_or_else(|| format!("vec_hnsw: {} should be a positive integer, not '{}'", key, value));
+            match &key[..] {
+                "dim" => dims = Some(number()?),
+                "metric" => params.metric = Metric::from_name(value).map_err(|e| format!("vec_hnsw: {}", e))?,
+                "m" => params.m = number()?,
+                "ef_construction" => params.ef_construction = number()?,
+                "ef_search" => params.ef_search = number()?,
+                _ => return Err(format!("vec_hnsw: unknown option '{}', expected dim, metric, m, \
+                    ef_construction or ef_search", key))
+            }
+        }
+        params.dims = dims.ok_or("vec_hnsw: the number of dimensions is required, like vec_hnsw(dim=768)")?;
+        if params.m < 2 {
+            return Err("vec_hnsw: m should be at least 2".into());
+        }
+        Ok(Index {
+            db,
+            schema: args[1].clone(),
+            name: args[2].clone(),
+            params,
+            graph: None,
+            changes: 0
+        })
+    }
+
+    fn create_tables(&self) -> Result<(), String> {
+        unsafe {
+            execute(self.db, &format!("CREATE TABLE {}(id INTEGER PRIMARY KEY, level INTEGER NOT NULL, \
+                vector BLOB NOT NULL)", self.shadow("nodes")), &[])?;
+            execute(self.db, &format!("CREATE TABLE {}(id INTEGER NOT NULL, level INTEGER NOT NULL, \
+                neighbours BLOB NOT NULL, PRIMARY KEY (id, level)) WITHOUT ROWID", self.shadow("edges")), &[])?;
+            execute(self.db, &format!("CREATE TABLE {}(changes INTEGER NOT NULL)", self.shadow("info")), &[])?;
+            execute(self.db, &format!("INSERT INTO {}(changes) VALUES (0)", self.shadow("info")), &[])
+        }
+    }
+
+    /// The quoted name of one of the index's tables
+    fn shadow(&self, suffix: &str) -> String {
+        format!("\"{}\".\"{}_{}\"", self.schema.replace('"', "\"\""), self.name.replace('"', "\"\""), suffix)
+    }
+
+    /// The graph, read again if it's missing or out of date
+    fn graph(&mut self) -> Result<&mut Hnsw, String> {
+        let mut changes = 0;
+        unsafe {
+            query_each(self.db, &format!("SELECT changes FROM {}", self.shadow("info")), |row| {
+                changes = i64::from(row[0]);
+                Ok(())
+            })?;
+        }
+        if self.graph.is_none() || changes != self.changes {
+            self.graph = Some(self.read()?);
+            self.changes = changes;
+        }
+        Ok(self.graph.as_mut().unwrap())
+    }
+
+    /// Read the graph from its tables
+    fn read(&self) -> Result<Hnsw, String> {
+        let mut graph = Hnsw::new(self.params.clone());
+        let mut nodes = vec![];
+        unsafe {
+            query_each(self.db, &format!("SELECT id, level, vector FROM {}", self.shadow("nodes")), |row| {
+                let blob: Vec<u8> = {let x: Option<Vec<u8>> = row[2].into(); x}.unwrap_or_default();
+                let v = Vector::decode(&blob).map_err(|e| format!("vec_hnsw: node {}: {}", i64::from(row[0]), e))?;
+                nodes.push((i64::from(row[0]), i64::from(row[1]) as usize, v));
+                Ok(())
+            })?;
+        }
+        let mut links: Vec<(i64, usize, Vec<i64>)> = vec![];
+        unsafe {
+            query_each(self.db, &format!("SELECT id, level, neighbours FROM {}", self.shadow("edges")), |row| {
+                let blob: Vec<u8> = {let x: Option<Vec<u8>> = row[2].into(); x}.unwrap_or_default();
+                let chunks = blob.chunks_exact(8);
+                if !chunks.remainder().is_empty() {
+                    return Err(format!("vec_hnsw: node {}: the links on layer {} are {} bytes, \
+                        which isn't a whole number of rowids", i64::from(row[0]), i64::from(row[1]), blob.len()));
+                }
+                let neighbours = chunks.map(|b| {
+                    let mut bytes = [0; 8];
+                    bytes.copy_from_slice(b);
+                    i64::from_le_bytes(bytes)
+                }).collect();
+                links.push((i64::from(row[0]), i64::from(row[1]) as usize, neighbours));
+                Ok(())
+            })?;
+        }
+        let mut nodes: Vec<(i64, Node)> = nodes.into_iter().map(|(id, level, v)| (id, Node {
+            vector: v.to_f64(),
+            links: vec![vec![]; level + 1]
+        })).collect();
+        nodes.sort_by_key(|&(id, _)| id);
+        for (id, level, neighbours) in links {
+            if let Ok(i) = nodes.binary_search_by_key(&id, |&(id, _)| id) {
+                if let Some(list) = nodes[i].1.links.get_mut(level) {
+                    *list = neighbours;
+                }
+            }
+        }
+        for (id, node) in nodes {
+            graph.load(id, node);
+        }
+        Ok(graph)
+    }
+
+    /// Check a vector can be inserted with a rowid, replacing the row
+    /// `old` if it's an update
+    fn check_insert(&mut self, rowid: Option<i64>, old: Option<i64>, vector: &Vector) -> Result<(), String> {
+        let graph = self.graph()?;
+        if !graph.check(&vector.to_f64()).map_err(|e| format!("vec_hnsw: {}", e))? {
+            return Err(format!("vec_hnsw: the {} distance isn't defined for this vector",
+                graph.params.metric.name()));
+        }
+        match rowid {
+            Some(rowid) if old != Some(rowid) && graph.get(rowid).is_some() =>
+                Err(format!("vec_hnsw: there is already a row with rowid {}", rowid)),
+            _ => Ok(())
+        }
+    }
+
+    /// Add a vector that passed `check_insert()` to the graph and store it,
+    /// returning its rowid
+    fn insert(&mut self, rowid: Option<i64>, vector: &Vector, blob: Vec<u8>) -> Result<i64, String> {
+        let db = self.db;
+        let (nodes, edges) = (self.shadow("nodes"), self.shadow("edges"));
+        let graph = self.graph()?;
+        let values = vector.to_f64();
+        let rowid = match rowid {
+            Some(rowid) => rowid,
+            // One more than the largest, like a table's rowid
+            None => {
+                let mut largest = 0;
+                unsafe {
+                    query_each(db, &format!("SELECT max(id) FROM {}", nodes), |row| {
+                        largest = i64::from(row[0]);
+                        Ok(())
+                    })?;
+                }
+                largest.checked_add(1).ok_or("vec_hnsw: the largest rowid is taken, so one has to be given")?
+            }
+        };
+        let level = graph.level_for(rowid);
+        let changed = graph.insert(rowid, values);
+        unsafe {
+            execute(db, &format!("INSERT INTO {}(id, level, vector) VALUES (?, ?, ?)", nodes),
+                &[rowid.into(), (level as i64).into(), blob.into()])?;
+            save_links(db, &edges, graph, &changed)?;
+        }
+        self.count_change()?;
+        Ok(rowid)
+    }
+
+    /// Remove a vector from the graph and its tables
+    fn delete(&mut self, rowid: i64) -> Result<(), String> {
+        let db = self.db;
+        let (nodes, edges) = (self.shadow("nodes"), self.shadow("edges"));
+        let graph = self.graph()?;
+        if let Some(changed) = graph.remove(rowid) {
+            unsafe {
+                execute(db, &format!("DELETE FROM {} WHERE id = ?", nodes), &[rowid.into()])?;
+                execute(db, &format!("DELETE FROM {} WHERE id = ?", edges), &[rowid.into()])?;
+                save_links(db, &edges, graph, &changed)?;
+            }
+            self.count_change()?;
+        }
+        Ok(())
+    }
+
+    /// Forget the graph if a change failed part way through, since it may
+    /// no longer match its tables
+    fn forget_on_error<T>(&mut self, result: Result<T, String>) -> Result<T, String> {
+        if result.is_err() {
+            self.graph = None;
+        }
+        result
+    }
+
+    /// Add one to the count of changes, which the graph in memory already
+    /// has, so other connections read it again
+    fn count_change(&mut self) -> Result<(), String> {
+        unsafe { execute(self.db, &format!("UPDATE {} SET changes = changes + 1", self.shadow("info")), &[])? };
+        self.changes += 1;
+        Ok(())
+    }
+}
+
+/// Store the links of each node and layer that changed, as little-endian
+/// rowids
+unsafe fn save_links(db: *mut sqlite3, edges: &str, graph: &Hnsw, changed: &[(i64, usize)]) -> Result<(), String> {
+    let mut changed = changed.to_vec();
+    changed.sort();
+    changed.dedup();
+    let sql = format!("INSERT OR REPLACE INTO {}(id, level, neighbours) VALUES (?, ?, ?)", edges);
+    for (id, level) in changed {
+        let blob: Vec<u8> = graph.get(id).unwrap().links[level].iter()
+            .flat_map(|n| n.to_le_bytes().to_vec())
+            .collect();
+        execute(db, &sql, &[id.into(), (level as i64).into(), blob.into()])?;
+    }
+    Ok(())
+}
+
+#[repr(C)]
+pub struct VecHnswVTab {
+    index: Rc<RefCell<Index>>
+}
+
+pub struct VecHnswCursor {
+    index: Rc<RefCell<Index>>,
+    position: usize,
+    /// The rows found, with their distances if there was a query
+    rows: Vec<Neighbour>,
+    k: Option<i64>
+}
+
+
+/*
+** This following structure defines all the methods for the
+** vec_hnsw virtual table.
+*/
+pub static VEC_HNSW_MODULE : sqlite3_module = sqlite3_module {
+    // Version 2 for xRollbackTo
+    iVersion:       2,
+    xCreate:        Some(vtab_create::<VecHnswVTab>),
+    xConnect:       Some(vtab_connect::<VecHnswVTab>),
+    xBestIndex:     Some(vtab_best_index::<VecHnswVTab>),
+    xDisconnect:    Some(vtab_disconnect::<VecHnswVTab>),
+    xDestroy:       Some(vtab_destroy::<VecHnswVTab>),
+    xOpen:          Some(vtab_open::<VecHnswVTab>),   // open a cursor
+    xClose:         Some(cursor_close::<VecHnswVTab>),  // close a cursor
+    xFilter:        Some(cursor_filter::<VecHnswVTab>), // configure scan constraints
+    xNext:          Some(cursor_next::<VecHnswVTab>),   // advance a cursor
+    xEof:           Some(cursor_eof::<VecHnswVTab>),    // check for end of scan
+    xColumn:        Some(cursor_column::<VecHnswVTab>), // read data
+    xRowid:         Some(cursor_rowid::<VecHnswVTab>),  // read data
+    xUpdate:        Some(vtab_update::<VecHnswVTab>),
+    xBegin:         Some(vtab_begin::<VecHnswVTab>),
+    xSync:          None,
+    xCommit:        None,
+    xRollback:      Some(vtab_rollback::<VecHnswVTab>),
+    xFindFunction:  None,
+    xRename:        Some(vtab_rename::<VecHnswVTab>),
+    // The following are for version 2 and above
+    xSavepoint:     None,
+    xRelease:       None,
+    xRollbackTo:    Some(vtab_rollback_to::<VecHnswVTab>)
+};
+
+const HNSW_COLUMN_EMBEDDING : i32 = 0;
+const HNSW_COLUMN_DISTANCE  : i32 = 1;
+const HNSW_COLUMN_K         : i32 = 2;
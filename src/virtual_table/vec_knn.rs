@@ -0,0 +1,262 @@
+//! `vec_knn(table, column, query, k[, metric])`
+//!
+//! The k rows of a table whose vectors are nearest the query vector, nearest
+//! first, for joining back to the table:
+//!
+//! ```sql
+//! SELECT docs.title, knn.distance
+//! FROM vec_knn('docs', 'embedding', vec_from_json(:query), 10) AS knn
+//! JOIN docs ON docs.rowid = knn.rowid;
+//! ```
+//!
+//! | column     | meaning                                 |
+//! |------------|-----------------------------------------|
+//! | `rowid`    | The rowid of the row in the table       |
+//! | `distance` | How far its vector is from the query    |
+//!
+//! The metric is one of `l2` (the default), `l2_squared`, `l1`, `linf`,
+//! `cosine`, `dot`, `hamming` or `jaccard`, as for the functions in
+//! `vector::distance`. `dot` ranks by the largest dot product first, so its
+//! distance is the product negated.
+//!
+//! Unlike `ORDER BY vec_l2(embedding, :query) LIMIT 10`, which sorts every
+//! row, this scans the table once keeping only the best k so far. It still
+//! reads every row; there is no index. Rows with a NULL vector are skipped,
+//! as are rows the metric can't compare, like zero vectors for `cosine`.
+//! Ties are broken by rowid.
+use sqlite3_raw::*;
+use std::collections::BinaryHeap;
+use std::ffi::CStr;
+use const_cstr::ConstCStr;
+use virtual_table::*;
+use virtual_table::internals::*;
+use vector::{type_name, vector_arg, Vector};
+use vector::distance::{Metric, Neighbour};
+
+impl VirtualTable for VecKnnVTab {
+    type Cursor = VecKnnCursor;
+    fn vtable_eponymity() -> VirtualEponymity {
+        VirtualEponymity::EponymousOnly
+    }
+    fn vtable_definition() -> ConstCStr {
+        const_cstr!("CREATE TABLE vec_knn(rowid, distance, \"table\" HIDDEN, \"column\" HIDDEN, \
+            query HIDDEN, k HIDDEN, metric HIDDEN);")
+    }
+    fn create(db: *mut sqlite3, _args: &[String])  -> Result<Self, String> { Ok(VecKnnVTab { db }) }
+    fn connect(db: *mut sqlite3, _args: &[String]) -> Result<Self, String> { Ok(VecKnnVTab { db }) }
+    fn open_cursor(&mut self) -> Self::Cursor {
+        VecKnnCursor {
+            db: self.db,
+            index: 0,
+            neighbours: vec![],
+            table: String::new(),
+            column: String::new(),
+            query: vec![],
+            k: 0,
+            metric: Metric::L2
+        }
+    }
+    fn best_index(&self,
+        idx_info: &mut sqlite3_index_info,
+        constraints: &[sqlite3_index_info_sqlite3_index_constraint],
+        order_bys: &[sqlite3_index_info_sqlite3_index_orderby],
+        constraint_usages: &mut [sqlite3_index_info_sqlite3_index_constraint_usage]
+    ){
+        // Hidden column i (counting from table) sets bit i of idx_num, and
+        // the arguments are passed to filter() in column order.
+        let mut column_constraints = [None; N_HIDDEN_COLUMNS];
+        for (i, constraint) in constraints.iter().enumerate() {
+            let column = constraint.iColumn - KNN_COLUMN_TABLE;
+            if constraint.usable != 0
+                && constraint.op == SQLITE_INDEX_CONSTRAINT_EQ
+                && column >= 0 && (column as usize) < N_HIDDEN_COLUMNS {
+                column_constraints[column as usize] = Some(i);
+            }
+        }
+        let mut idx_num = 0i32;
+        let mut n_arg = 0i32;
+        for (column, constraint) in column_constraints.iter().enumerate() {
+            if let Some(i) = *constraint {
+                n_arg += 1;
+                idx_num |= 1 << column;
+                constraint_usages[i].argvIndex = n_arg;
+                constraint_usages[i].omit = 1;
+            }
+        }
+        if idx_num & 15 == 15 {
+            // Every row has to be read, but only k come out
+            idx_info.estimatedCost = 1000000.0;
+            idx_info.estimatedRows = 10;
+        } else {
+            /* Without a table, column, query and k filter() will fail, so
+            ** the planner should avoid this plan if at all possible */
+            idx_info.estimatedCost = 2147483647.0f64;
+            idx_info.estimatedRows = 2147483647;
+        }
+        // The rows already come out nearest first
+        if let [ref order_by] = *order_bys {
+            if order_by.iColumn == KNN_COLUMN_DISTANCE && order_by.desc == 0 {
+                idx_info.orderByConsumed = 1;
+            }
+        }
+        idx_info.idxNum = idx_num;
+    }
+}
+
+impl VirtualCursor for VecKnnCursor {
+    fn next(&mut self) {
+        self.index += 1;
+    }
+    fn column(&self, index: i32) -> SQLiteReturn {
+        match index {
+            KNN_COLUMN_ROWID => self.neighbours[self.index].rowid.into(),
+            KNN_COLUMN_DISTANCE => self.neighbours[self.index].distance.into(),
+            KNN_COLUMN_TABLE => self.table.clone().into(),
+            KNN_COLUMN_COLUMN => self.column.clone().into(),
+            KNN_COLUMN_QUERY => self.query.clone().into(),
+            KNN_COLUMN_K => self.k.into(),
+            _ => self.metric.name().to_string().into()
+        }
+    }
+    fn rowid(&self) -> i64 {
+        self.neighbours[self.index].rowid
+    }
+    fn eof(&self) -> bool {
+        self.index >= self.neighbours.len()
+    }
+    fn filter(&mut self,
+        idx_num: i32,
+        _idx_str: Option<&CStr>,
+        args: &[*mut sqlite3_value]
+    ) -> Result<(), String> {
+        if idx_num & 15 != 15 {
+            return Err("vec_knn() requires a table, a column, a query vector and k".into());
+        }
+        let values : Vec<SQLiteValue> = args.iter()
+            .map(|&arg| unsafe { SQLiteValue::from_raw_unchecked(arg) })
+            .collect();
+        self.index = 0;
+        self.neighbours.clear();
+        self.table = {let x: Option<String> = values[0].into(); x}
+            .ok_or("vec_knn() requires a table name")?;
+        self.column = {let x: Option<String> = values[1].into(); x}
+            .ok_or("vec_knn() requires a column name")?;
+        self.k = match values[3].value_type() {
+            SQLITE_INTEGER => values[3].into(),
+            other => return Err(format!("vec_knn(): k should be an integer, not {}", type_name(other)))
+        };
+        if self.k < 0 {
+            return Err("vec_knn(): k can't be negative".into());
+        }
+        self.metric = match values.get(4).and_then(|&x| {let x: Option<String> = x.into(); x}) {
+            Some(name) => Metric::from_name(&name).map_err(|e| format!("vec_knn(): {}", e))?,
+            None => Metric::L2
+        };
+        // A NULL query is near nothing
+        let query = match vector_arg("vec_knn", values[2])? {
+            Some(query) => query,
+            None => return Ok(())
+        };
+        self.query = query.encode();
+        self.neighbours = unsafe {
+            nearest(self.db, &self.table, &self.column, &query.to_f64(), self.k as usize, self.metric)?
+        };
+        Ok(())
+    }
+}
+
+/// Scan a column for the k vectors nearest the query, nearest first
+unsafe fn nearest(db: *mut sqlite3, table: &str, column: &str, query: &[f64], k: usize, metric: Metric)
+    -> Result<Vec<Neighbour>, String>
+{
+    if k == 0 {
+        return Ok(vec![]);
+    }
+    let sql = format!("SELECT rowid, \"{}\" FROM \"{}\"",
+        column.replace('"', "\"\""), table.replace('"', "\"\""));
+    // The furthest of the nearest k so far is on top, to be replaced first
+    let mut heap = BinaryHeap::with_capacity(k.min(1024) + 1);
+    query_each(db, &sql, |row| {
+        let rowid = i64::from(row[0]);
+        let v = match row[1].value_type() {
+            SQLITE_NULL => return Ok(()),
+            SQLITE_BLOB => {
+                let blob: Vec<u8> = {let x: Option<Vec<u8>> = row[1].into(); x}.unwrap_or_default();
+                Vector::decode(&blob).map_err(|e| format!("row {}: {}", rowid, e))?
+            },
+            other => return Err(format!("row {} has {}, not a vector", rowid, type_name(other)))
+        };
+        if v.dims() != query.len() {
+            return Err(format!("row {} has {} dimensions but the query has {}",
+                rowid, v.dims(), query.len()));
+        }
+        let distance = match metric.distance(&v.to_f64(), query).map_err(|e| format!("row {}: {}", rowid, e))? {
+            Some(distance) if !distance.is_nan() => distance,
+            _ => return Ok(())
+        };
+        heap.push(Neighbour { distance, rowid });
+        if heap.len() > k {
+            heap.pop();
+        }
+        Ok(())
+    }).map_err(|e| format!("vec_knn(): {}", e))?;
+    Ok(heap.into_sorted_vec())
+}
+
+#[repr(C)]
+pub struct VecKnnVTab {
+    db: *mut sqlite3
+}
+
+pub struct VecKnnCursor {
+    /// The connection, to read the table from
+    db: *mut sqlite3,
+    index: usize,
+    neighbours: Vec<Neighbour>,
+    table: String,
+    column: String,
+    /// The query vector, encoded again for the hidden column
+    query: Vec<u8>,
+    k: i64,
+    metric: Metric
+}
+
+
+/*
+** This following structure defines all the methods for the
+** vec_knn virtual table.
+*/
+pub static VEC_KNN_MODULE : sqlite3_module = sqlite3_module {
+    iVersion:       0,
+    xCreate:        None,
+    xConnect:       Some(vtab_connect::<VecKnnVTab>),
+    xBestIndex:     Some(vtab_best_index::<VecKnnVTab>),
+    xDisconnect:    Some(vtab_disconnect::<VecKnnVTab>),
+    xDestroy:       None,
+    xOpen:          Some(vtab_open::<VecKnnVTab>),   // open a cursor
+    xClose:         Some(cursor_close::<VecKnnVTab>),  // close a cursor
+    xFilter:        Some(cursor_filter::<VecKnnVTab>), // configure scan constraints
+    xNext:          Some(cursor_next::<VecKnnVTab>),   // advance a cursor
+    xEof:           Some(cursor_eof::<VecKnnVTab>),    // check for end of scan
+    xColumn:        Some(cursor_column::<VecKnnVTab>), // read data
+    xRowid:         Some(cursor_rowid::<VecKnnVTab>),  // read data
+    xUpdate:        None,
+    xBegin:         None,
+    xSync:          None,
+    xCommit:        None,
+    xRollback:      None,
+    xFindFunction:  None,
+    xRename:        None,
+    // The following are for version 2 and above
+    xSavepoint:     None,
+    xRelease:       None,
+    xRollbackTo:    None
+};
+
+const KNN_COLUMN_ROWID    : i32 = 0;
+const KNN_COLUMN_DISTANCE : i32 = 1;
+const KNN_COLUMN_TABLE    : i32 = 2;
+const KNN_COLUMN_COLUMN   : i32 = 3;
+const KNN_COLUMN_QUERY    : i32 = 4;
+const KNN_COLUMN_K        : i32 = 5;
+const N_HIDDEN_COLUMNS : usize = 5;
@@ -0,0 +1,52 @@
+//! Helpers shared by the integration tests
+use rusqlite as sql;
+use glob;
+use std::path::Path;
+
+/// Open an in-memory database with this extension loaded
+pub fn get_connection() -> sql::Connection {
+    load_extension(sql::Connection::open_in_memory().unwrap())
+}
+
+/// Open a database file with this extension loaded
+#[allow(dead_code)]
+pub fn get_file_connection(path: &Path) -> sql::Connection {
+    load_extension(sql::Connection::open(path).unwrap())
+}
+
+fn load_extension(conn: sql::Connection) -> sql::Connection {
+    conn.load_extension_enable().unwrap();
+    let path = [".", "target/debug", "target/release", "./**", "../**"]
+        .iter()
+        .flat_map(|folder| ["dylib", "so", "dll"].iter().map(move |ext| (folder, ext)))
+        .flat_map(|(folder, ext)| glob::glob(&format!("{}/{}.{}", folder, "libsqlite3_extras", ext)).unwrap())
+        .map(|x| x.unwrap())
+        .next()
+        .expect("Couldn't find the dynamic library for SQLite to load. \
+            Looked in target/debug/libsqlite3_extras.{dll,so,dylib}");
+    conn.load_extension(path, None).unwrap();
+    conn
+}
+
+/// Run a query that should fail, returning the error message
+#[allow(dead_code)]
+pub fn fetch_error(conn: &sql::Connection, sql_string: &str) -> String {
+    match conn.query_row(sql_string, &[], |_| ()) {
+        Ok(_) => panic!("Expected an error from {}", sql_string),
+        Err(e) => e.to_string()
+    }
+}
+
+/// The version of the SQLite library running the tests, like 3025000 for
+/// 3.25.0, for skipping features it doesn't have
+#[allow(dead_code)]
+pub fn sqlite_version(conn: &sql::Connection) -> i64 {
+    let version: String = conn.query_row("SELECT sqlite_version();", &[], |r| r.get(0)).unwrap();
+    version.split('.').take(3).fold(0, |n, part| n * 1000 + part.parse::<i64>().unwrap())
+}
+
+macro_rules! fetch_one_cell {
+    ($conn: expr, $sql_string: expr) => {
+        $conn.query_row($sql_string, &[], |r| r.get(0)).unwrap()
+    }
+}
@@ -0,0 +1,61 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, sqlite_version};
+
+#[test]
+fn regex_tokenizer_indexes_identifiers() {
+    let conn = get_connection();
+    // The tokenizer is only registered from SQLite 3.20
+    if sqlite_version(&conn) < 3020000 {
+        return;
+    }
+    conn.execute_batch(r#"
+        CREATE VIRTUAL TABLE docs USING fts5(body, tokenize="extras_regex '[A-Za-z0-9_]+'");
+        INSERT INTO docs VALUES ('fn parse_args(argv: &[String]) -> Config');
+        INSERT INTO docs VALUES ('let parse = Parser::new();');
+    "#).unwrap();
+    let found: i64 = fetch_one_cell!(conn, "SELECT rowid FROM docs WHERE docs MATCH 'parse_args';");
+    assert_eq!(found, 1);
+    // Underscores don't split identifiers, so only the second row has "parse"
+    let found: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM docs WHERE docs MATCH 'parse';");
+    assert_eq!(found, 1);
+    // Tokens are lowercased by default
+    let found: i64 = fetch_one_cell!(conn, "SELECT rowid FROM docs WHERE docs MATCH 'PARSER';");
+    assert_eq!(found, 2);
+    // Offsets point into the original text
+    let highlighted: String = fetch_one_cell!(conn,
+        "SELECT highlight(docs, 0, '[', ']') FROM docs WHERE docs MATCH 'config';");
+    assert_eq!(highlighted, "fn parse_args(argv: &[String]) -> [Config]");
+}
+
+#[test]
+fn regex_tokenizer_options() {
+    let conn = get_connection();
+    if sqlite_version(&conn) < 3020000 {
+        return;
+    }
+    conn.execute_batch(r#"
+        CREATE VIRTUAL TABLE folded USING fts5(body, tokenize="extras_regex '\w+' fold 1");
+        INSERT INTO folded VALUES ('Un café très ﬁn');
+        CREATE VIRTUAL TABLE exact USING fts5(body, tokenize="extras_regex '\w+' lowercase 0");
+        INSERT INTO exact VALUES ('Parser parser');
+    "#).unwrap();
+    let found: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM folded WHERE folded MATCH 'cafe AND tres AND fin';");
+    assert_eq!(found, 1);
+    let found: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM folded WHERE folded MATCH 'CAFÉ';");
+    assert_eq!(found, 1);
+    let found: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM exact WHERE exact MATCH 'Parser';");
+    assert_eq!(found, 1);
+    let found: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM exact WHERE exact MATCH 'PARSER';");
+    assert_eq!(found, 0);
+
+    for bad in &[
+        r#"CREATE VIRTUAL TABLE bad USING fts5(body, tokenize="extras_regex '('");"#,
+        r#"CREATE VIRTUAL TABLE bad USING fts5(body, tokenize="extras_regex");"#,
+        r#"CREATE VIRTUAL TABLE bad USING fts5(body, tokenize="extras_regex '\w+' fold yes");"#,
+        r#"CREATE VIRTUAL TABLE bad USING fts5(body, tokenize="extras_regex '\w+' stem 1");"#
+    ] {
+        assert!(conn.execute_batch(bad).is_err(), "{} should fail", bad);
+    }
+}
@@ -0,0 +1,93 @@
+extern crate rusqlite;
+extern crate glob;
+extern crate sqlite3_extras;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+use sqlite3_extras::vector::{ElementType, Matrix};
+
+#[test]
+fn matrices_have_a_header_and_round_trip_through_json() {
+    let conn = get_connection();
+    let hex: String = fetch_one_cell!(conn, "SELECT hex(mat_from_json('[[1],[2]]', 'f32'));");
+    assert_eq!(hex, "584D010102000000010000000000803F00000040");
+    let json: String = fetch_one_cell!(conn, "SELECT mat_to_json(mat_from_json('[[1, 2.5, 3], [4, 5, -6]]'));");
+    assert_eq!(json, "[[1.0,2.5,3.0],[4.0,5.0,-6.0]]");
+    let json: String = fetch_one_cell!(conn, "SELECT mat_to_json(mat_from_json('[[1, 0], [0, 1]]', 'i8'));");
+    assert_eq!(json, "[[1,0],[0,1]]");
+    let json: String = fetch_one_cell!(conn, "SELECT mat_to_json(mat_from_json('[]'));");
+    assert_eq!(json, "[]");
+    let json: String = fetch_one_cell!(conn, "SELECT mat_to_json(mat_transpose(mat_from_json('[[1, 2, 3], [4, 5, 6]]')));");
+    assert_eq!(json, "[[1.0,4.0],[2.0,5.0],[3.0,6.0]]");
+    let json: String = fetch_one_cell!(conn, "SELECT mat_to_json(mat_identity(3, 'i8'));");
+    assert_eq!(json, "[[1,0,0],[0,1,0],[0,0,1]]");
+    let nothing: Option<String> = fetch_one_cell!(conn, "SELECT mat_to_json(mat_transpose(NULL));");
+    assert_eq!(nothing, None);
+
+    assert!(fetch_error(&conn, "SELECT mat_from_json('[[1, 2], [3]]');").contains("mat_from_json(): row 2 has 1 elements but row 1 has 2"));
+    assert!(fetch_error(&conn, "SELECT mat_from_json('[[1, \"a\"]]');").contains("row 1, element 2 is \"a\", which isn't a number"));
+    assert!(fetch_error(&conn, "SELECT mat_from_json('[[1], [300]]', 'i8');").contains("row 2, element 1 is 300, which isn't an i8"));
+    assert!(fetch_error(&conn, "SELECT mat_to_json(vread('1 2'));").contains("mat_to_json(): expected a matrix, not a vector"));
+    assert!(fetch_error(&conn, "SELECT vec_dims(mat_identity(2));").contains("vec_dims(): expected a vector, not a matrix"));
+    assert!(fetch_error(&conn, "SELECT mat_identity(-1);").contains("the size can't be negative"));
+    // A header claiming more elements than can be counted
+    assert!(fetch_error(&conn, "SELECT mat_to_json(X'584D0101FFFFFFFFFFFFFFFF');").contains("corrupt matrix"));
+}
+
+#[test]
+fn matrix_products_inverses_and_determinants() {
+    let conn = get_connection();
+    let json: String = fetch_one_cell!(conn, "SELECT mat_to_json(mat_mul(mat_from_json('[[1, 2], [3, 4], [5, 6]]'), mat_from_json('[[1, 0, 2], [0, 1, 3]]')));");
+    assert_eq!(json, "[[1.0,2.0,8.0],[3.0,4.0,18.0],[5.0,6.0,28.0]]");
+    // A projection from 3 dimensions to 2, applied to a column of embeddings
+    conn.execute_batch("
+        CREATE TABLE docs(id INTEGER PRIMARY KEY, embedding BLOB);
+        INSERT INTO docs(embedding) VALUES (vec_f32(1, 2, 3)), (vec_f32(0, -1, 1));
+    ").unwrap();
+    let projected: String = fetch_one_cell!(conn, "
+        SELECT group_concat(vshow(mat_vec_mul(mat_from_json('[[1, 0, 1], [0, 2, 0]]', 'f32'), embedding)), ' | ')
+        FROM docs;");
+    assert_eq!(projected, "4.0 4.0 | 1.0 -2.0");
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(mat_vec_mul(mat_identity(3, 'f32'), embedding)) FROM docs;");
+    assert_eq!(ty, "f32");
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(mat_vec_mul(mat_identity(3), embedding)) FROM docs;");
+    assert_eq!(ty, "f64");
+
+    let json: String = fetch_one_cell!(conn, "SELECT mat_to_json(mat_inverse(mat_from_json('[[4, 7], [2, 6]]')));");
+    assert_eq!(json, "[[0.6000000000000001,-0.7000000000000001],[-0.2,0.4]]");
+    let identity: bool = fetch_one_cell!(conn, "
+        SELECT mat_to_json(mat_mul(m, mat_inverse(m))) = '[[1.0,0.0,0.0],[0.0,1.0,0.0],[0.0,0.0,1.0]]'
+        FROM (SELECT mat_from_json('[[0, 2, 0], [1, 0, 0], [0, 0, 4]]') AS m);");
+    assert!(identity);
+    let singular: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT mat_inverse(mat_from_json('[[1, 2], [2, 4]]'));");
+    assert_eq!(singular, None);
+    let singular: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT mat_inverse(mat_from_json('[[0.1, 0.2, 0.3], [0.4, 0.5, 0.6], [0.7, 0.8, 0.9]]'));");
+    assert_eq!(singular, None);
+
+    let det: f64 = fetch_one_cell!(conn, "SELECT mat_det(mat_from_json('[[4, 7], [2, 6]]'));");
+    assert!((det - 10.0).abs() < 1e-12);
+    let det: f64 = fetch_one_cell!(conn, "SELECT mat_det(mat_from_json('[[0, 1], [1, 0]]'));");
+    assert_eq!(det, -1.0);
+    let det: f64 = fetch_one_cell!(conn, "SELECT mat_det(mat_identity(0));");
+    assert_eq!(det, 1.0);
+
+    assert!(fetch_error(&conn, "SELECT mat_mul(mat_identity(2), mat_from_json('[[1, 2, 3]]'));")
+        .contains("mat_mul(): can't multiply a 2x2 matrix by a 1x3 matrix, which needs 2 rows"));
+    assert!(fetch_error(&conn, "SELECT mat_vec_mul(mat_identity(2), vread('1 2 3'));")
+        .contains("can't multiply a 2x2 matrix by a vector with 3 dimensions"));
+    // A 40000x1 column times its transpose is too big, though neither is
+    assert!(fetch_error(&conn, "
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 40000)
+        SELECT mat_mul(m, mat_transpose(m)) FROM (SELECT mat_from_json(json_group_array(json_array(1)), 'f32') AS m FROM n);")
+        .contains("mat_mul(): a 40000x40000 matrix is too big for a BLOB"));
+    assert!(fetch_error(&conn, "SELECT mat_det(mat_from_json('[[1, 2]]'));").contains("mat_det(): the matrix should be square, not 1x2"));
+    assert!(fetch_error(&conn, "SELECT mat_inverse(mat_from_json('[[1, 2]]'));").contains("should be square"));
+}
+
+#[test]
+fn matrices_have_rows_times_columns_elements() {
+    let m = Matrix::from_f64(ElementType::F32, 2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+    assert_eq!(m.shape(), "2x3");
+    let err = Matrix::from_f64(ElementType::F32, 2, 2, &[1.0, 2.0, 3.0]).err().unwrap();
+    assert_eq!(err, "a 2x2 matrix can't have 3 elements");
+    assert!(Matrix::from_f64(ElementType::F64, 1 << 31, 1 << 31, &[]).is_err());
+}
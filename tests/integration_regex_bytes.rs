@@ -0,0 +1,44 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn regex_bytes_functions_search_raw_bytes() {
+    let conn = get_connection();
+    // Neither the subject nor the match is valid UTF-8
+    let found: Vec<u8> = fetch_one_cell!(conn, r"SELECT regex_find_bytes('\x7e[\x80-\xff]+\x7e', X'00017EFF80FE7E02');");
+    assert_eq!(found, vec![0x7e, 0xff, 0x80, 0xfe, 0x7e]);
+    let found: Vec<u8> = fetch_one_cell!(conn, r"SELECT regex_find_bytes('..', X'C3A9C3A9');");
+    assert_eq!(found, vec![0xc3, 0xa9]);
+    // The u flag makes . match whole UTF-8 characters
+    let found: Vec<u8> = fetch_one_cell!(conn, r"SELECT regex_find_bytes('..', X'C3A9C3A9', 'u');");
+    assert_eq!(found, vec![0xc3, 0xa9, 0xc3, 0xa9]);
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, r"SELECT regex_find_bytes('\xAA', X'0102');");
+    assert_eq!(nothing, None);
+
+    let matched: i64 = fetch_one_cell!(conn, r"SELECT regex_match_bytes('\x01.*\x04', X'01FF0304');");
+    assert_eq!(matched, 1);
+    let matched: i64 = fetch_one_cell!(conn, r"SELECT regex_match_bytes('\x01.*', X'00010203');");
+    assert_eq!(matched, 0);
+    let typ: String = fetch_one_cell!(conn, r"SELECT typeof(regex_find_bytes('b', 'abc'));");
+    assert_eq!(typ, "blob");
+}
+
+#[test]
+fn regex_replace_bytes_expands_groups_and_escapes() {
+    let conn = get_connection();
+    let replaced: Vec<u8> = fetch_one_cell!(conn,
+        r"SELECT regex_replace_bytes('\x7e([\x00-\xff])', '\xAB$1\x00', X'7E017E02FF');");
+    assert_eq!(replaced, vec![0xab, 0x01, 0x00, 0xab, 0x02, 0x00, 0xff]);
+    // The format can itself be a BLOB
+    let replaced: Vec<u8> = fetch_one_cell!(conn, r"SELECT regex_replace_bytes('(?P<b>\xFF)', X'247B627D2424', X'00FF');");
+    assert_eq!(replaced, b"\x00\xff$".to_vec());
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, r"SELECT regex_replace_bytes('a', 'b', NULL);");
+    assert_eq!(nothing, None);
+    assert!(fetch_error(&conn, r"SELECT regex_find_bytes('(\x01)\1', X'0101', 'b');").contains("regex_find_bytes()"));
+    // Raw bytes that aren't UTF-8 can't be part of a pattern
+    assert!(fetch_error(&conn, "SELECT regex_find_bytes(X'41FF', X'41FF');")
+        .contains("regex_find_bytes(): the pattern isn't UTF-8; write the bytes to match as escapes like \\xFF"));
+    assert!(fetch_error(&conn, "SELECT regex_match_bytes(X'FF', X'FF');").contains("isn't UTF-8"));
+}
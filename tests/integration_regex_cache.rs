@@ -0,0 +1,62 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error, sqlite_version};
+
+// The cache is shared by the whole process, so this is all one test to keep
+// other tests from changing it underneath.
+#[test]
+fn regex_cache_is_shared_and_bounded() {
+    let conn = get_connection();
+    let other = get_connection();
+    let stats = || -> String { fetch_one_cell!(conn, "SELECT regex_cache_stats();") };
+    assert_eq!(stats(), r#"{"entries":0,"capacity":256,"hits":0,"misses":0}"#);
+
+    // A pattern compiled for one function and connection is reused by others
+    let found: i64 = fetch_one_cell!(conn, "SELECT search('ab+c', 'xabbbc');");
+    assert_eq!(found, 1);
+    let found: String = fetch_one_cell!(other, "SELECT regex_find('ab+c', 'xabbbc');");
+    assert_eq!(found, "abbbc");
+    assert_eq!(stats(), r#"{"entries":1,"capacity":256,"hits":1,"misses":1}"#);
+
+    // Shrinking the capacity evicts the least recently used patterns
+    let capacity: i64 = fetch_one_cell!(conn, "SELECT regex_cache_config('capacity', 2);");
+    assert_eq!(capacity, 2);
+    conn.execute_batch("
+        SELECT search('x', 'x');
+        SELECT search('ab+c', 'abc');
+        SELECT search('y', 'y');
+        SELECT search('ab+c', 'abc');
+    ").unwrap();
+    // 'x' was evicted rather than 'ab+c', since 'ab+c' was used more recently
+    assert_eq!(stats(), r#"{"entries":2,"capacity":2,"hits":3,"misses":3}"#);
+    let capacity: i64 = fetch_one_cell!(conn, "SELECT regex_cache_config('capacity');");
+    assert_eq!(capacity, 2);
+
+    // Patterns that compile to too large a program are rejected
+    let found: i64 = fetch_one_cell!(conn, r"SELECT search('\w{10}', 'x');");
+    assert_eq!(found, 0);
+    let limit: i64 = fetch_one_cell!(conn, "SELECT regex_cache_config('size_limit', 10000);");
+    assert_eq!(limit, 10000);
+    assert!(fetch_error(&conn, r"SELECT search('\w{10}', 'x');").contains("size limit"));
+    let found: i64 = fetch_one_cell!(conn, "SELECT search('a+', 'aaa');");
+    assert_eq!(found, 1);
+
+    // So are backtracking searches that take too many steps
+    let limit: i64 = fetch_one_cell!(conn, "SELECT regex_cache_config('backtrack_limit');");
+    assert_eq!(limit, 1_000_000);
+    let found: i64 = fetch_one_cell!(conn, r"SELECT search('(a+)+\1b', 'aaaaaaaaaaaac', 'b');");
+    assert_eq!(found, 0);
+    let limit: i64 = fetch_one_cell!(conn, "SELECT regex_cache_config('backtrack_limit', 100);");
+    assert_eq!(limit, 100);
+    assert!(fetch_error(&conn, r"SELECT search('(a+)+\1b', 'aaaaaaaaaaaac', 'b');").contains("backtracking"));
+
+    assert!(fetch_error(&conn, "SELECT regex_cache_config('colour', 1);").contains("unknown setting"));
+    assert!(fetch_error(&conn, "SELECT regex_cache_config('capacity', -1);").contains("at least 0"));
+
+    // A database's views can't raise the limits, from SQLite 3.30
+    if sqlite_version(&conn) >= 3030000 {
+        conn.execute_batch("CREATE VIEW raise AS SELECT regex_cache_config('size_limit', 1000000000) AS x;").unwrap();
+        assert!(fetch_error(&conn, "SELECT x FROM raise;").contains("unsafe use of regex_cache_config()"));
+    }
+}
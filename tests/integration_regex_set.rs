@@ -0,0 +1,52 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn regex_any_and_which_check_every_pattern() {
+    let conn = get_connection();
+    let any: i64 = fetch_one_cell!(conn, r"SELECT regex_any('disk full on /dev/sda', 'timeout', '(?i)DISK', '\d+');");
+    assert_eq!(any, 1);
+    let any: i64 = fetch_one_cell!(conn, r"SELECT regex_any('all good', 'timeout', 'disk');");
+    assert_eq!(any, 0);
+    let which: String = fetch_one_cell!(conn, r"SELECT regex_which('ab', 'x', 'a', 'b', 'ab$');");
+    assert_eq!(which, "[2,3,4]");
+    let which: String = fetch_one_cell!(conn, r"SELECT regex_which('ab');");
+    assert_eq!(which, "[]");
+    let nothing: Option<String> = fetch_one_cell!(conn, r"SELECT regex_which(NULL, 'a');");
+    assert_eq!(nothing, None);
+    assert!(fetch_error(&conn, r"SELECT regex_any('a', 'b', '(');").contains("regex_any()"));
+    assert!(fetch_error(&conn, r"SELECT regex_which();").contains("requires a subject"));
+}
+
+#[test]
+fn regex_classify_reads_patterns_from_a_table() {
+    let conn = get_connection();
+    conn.execute_batch(r"
+        CREATE TABLE classes(label TEXT, pattern TEXT);
+        INSERT INTO classes VALUES ('timeout', 'timed? ?out'), ('disk', 'disk|space'),
+            ('unused', NULL), ('number', '\d+');
+        CREATE TABLE logs(id INTEGER PRIMARY KEY, line TEXT);
+        INSERT INTO logs(line) VALUES ('Request TIMED OUT after 30s'), ('no space left'), ('ok');
+    ").unwrap();
+    let hits: String = fetch_one_cell!(conn, "SELECT regex_classify('disk 2 timeout', 'classes');");
+    assert_eq!(hits, "[1,2,4]");
+    let labels: String = fetch_one_cell!(conn, "
+        SELECT group_concat(logs.id || ':' || classes.label, ' ')
+        FROM logs, json_each(regex_classify(logs.line, 'classes', 'i')) AS hit
+        JOIN classes ON classes.rowid = hit.value;");
+    assert_eq!(labels, "1:timeout 1:number 2:disk");
+    // Tables can be named with their schema
+    conn.execute_batch("
+        ATTACH ':memory:' AS aux;
+        CREATE TABLE aux.classes(pattern TEXT);
+        INSERT INTO aux.classes VALUES ('ok'), ('disk');
+    ").unwrap();
+    let hits: String = fetch_one_cell!(conn, "SELECT regex_classify('disk ok', 'aux.classes');");
+    assert_eq!(hits, "[1,2]");
+    let hits: String = fetch_one_cell!(conn, "SELECT regex_classify('disk 2 timeout', 'main.classes');");
+    assert_eq!(hits, "[1,2,4]");
+    assert!(fetch_error(&conn, "SELECT regex_classify('a', 'missing');").contains("no such table"));
+    assert!(fetch_error(&conn, "SELECT regex_classify('a', 'classes', 'b');").contains("isn't supported"));
+}
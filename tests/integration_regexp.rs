@@ -0,0 +1,167 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn match_needs_the_whole_subject() {
+    let conn = get_connection();
+    let matched: i64 = fetch_one_cell!(conn, "SELECT match('<tag [^>]+>', '<tag class=x>');");
+    assert_eq!(matched, 1);
+    let matched: i64 = fetch_one_cell!(conn, "SELECT match('<tag [^>]+>', 'a <tag class=x>');");
+    assert_eq!(matched, 0);
+    // Alternations are anchored as a whole, not just their ends
+    let matched: i64 = fetch_one_cell!(conn, "SELECT match('a|b', 'ab');");
+    assert_eq!(matched, 0);
+    // SQLite rewrites the MATCH operator into match(pattern, subject)
+    let matched: i64 = fetch_one_cell!(conn, "SELECT 'tag' MATCH 't.g';");
+    assert_eq!(matched, 1);
+}
+
+#[test]
+fn search_finds_matches_anywhere() {
+    let conn = get_connection();
+    let found: i64 = fetch_one_cell!(conn,
+        "SELECT search('is the (thir|four)teenth of May', 'Today is the fourteenth of May.');");
+    assert_eq!(found, 1);
+    let found: i64 = fetch_one_cell!(conn,
+        "SELECT search('is the (thir|four)teenth of May', 'Today is the fifteenth of May.');");
+    assert_eq!(found, 0);
+}
+
+#[test]
+fn sub_replaces_with_groups() {
+    let conn = get_connection();
+    let replaced: String = fetch_one_cell!(conn,
+        r"SELECT sub('(\w+) lives by lake (\w+)', '$1 thinks $2 is cool.', 'Sean lives by lake Erie');");
+    assert_eq!(replaced, "Sean thinks Erie is cool.");
+    let replaced: String = fetch_one_cell!(conn,
+        r"SELECT sub('(?P<first>\w+) (?P<last>\w+)', '${last}, ${first} ($&)', 'Ada Lovelace');");
+    assert_eq!(replaced, "Lovelace, Ada (Ada Lovelace)");
+    // extras.cpp style backreferences and escapes
+    let replaced: String = fetch_one_cell!(conn, r"SELECT sub('(\d+)', '\1$$\\', 'a1b22');");
+    assert_eq!(replaced, r"a1$\b22$\");
+    // Groups that don't exist or didn't match are empty
+    let replaced: String = fetch_one_cell!(conn, r"SELECT sub('(a)|(b)', '[$2$9]', 'ab');");
+    assert_eq!(replaced, "[][b]");
+}
+
+#[test]
+fn regex_functions_handle_null_and_errors() {
+    let conn = get_connection();
+    let nothing: Option<i64> = fetch_one_cell!(conn, "SELECT search(NULL, 'abc');");
+    assert_eq!(nothing, None);
+    let nothing: Option<String> = fetch_one_cell!(conn, "SELECT sub('a', 'b', NULL);");
+    assert_eq!(nothing, None);
+    assert!(fetch_error(&conn, "SELECT search('(unclosed', 'abc');").contains("search()"));
+    assert!(fetch_error(&conn, "SELECT sub('[z-a]', '', 'abc');").contains("sub()"));
+}
+
+#[test]
+fn regexp_operator_works() {
+    let conn = get_connection();
+    let matched: i64 = fetch_one_cell!(conn, "SELECT 'The year 1999' REGEXP '\\d{4}';");
+    assert_eq!(matched, 1);
+    let matched: i64 = fetch_one_cell!(conn, "SELECT 'The year' REGEXP '\\d{4}';");
+    assert_eq!(matched, 0);
+    // The pattern changes from row to row, so nothing may be cached wrongly
+    let count: i64 = fetch_one_cell!(conn,
+        "SELECT count(*) FROM (VALUES ('abc', 'b'), ('abc', 'x'), ('xyz', 'x'), ('xyz', '^y'))
+        WHERE column1 REGEXP column2;");
+    assert_eq!(count, 2);
+    let count: i64 = fetch_one_cell!(conn,
+        "SELECT count(*) FROM range(0, 1000) WHERE value REGEXP '^1[0-9]*5$';");
+    assert_eq!(count, 11);
+    assert!(fetch_error(&conn, "SELECT 'abc' REGEXP '(';").contains("regexp()"));
+}
+
+#[test]
+fn regex_capture_extracts_groups() {
+    let conn = get_connection();
+    let line = "'2018-03-04 GET /index.html 200 35ms'";
+    let status: String = fetch_one_cell!(conn, &format!(
+        r"SELECT regex_capture('(\w+) (\S+) (\d+)', {}, 3);", line));
+    assert_eq!(status, "200");
+    let path: String = fetch_one_cell!(conn, &format!(
+        r"SELECT regex_capture('(?P<verb>GET|POST) (?P<path>\S+)', {}, 'path');", line));
+    assert_eq!(path, "/index.html");
+    let whole: String = fetch_one_cell!(conn, &format!(
+        r"SELECT regex_capture('\d+ms', {}, 0);", line));
+    assert_eq!(whole, "35ms");
+    let nothing: Option<String> = fetch_one_cell!(conn, &format!(
+        r"SELECT regex_capture('(x)|(GET)', {}, 1);", line));
+    assert_eq!(nothing, None);
+    assert!(fetch_error(&conn, r"SELECT regex_capture('(a)', 'a', 2);").contains("no group 2"));
+    assert!(fetch_error(&conn, r"SELECT regex_capture('(a)', 'a', 'b');").contains("no group 'b'"));
+}
+
+#[test]
+fn regex_find_returns_the_first_match() {
+    let conn = get_connection();
+    let found: String = fetch_one_cell!(conn, r"SELECT regex_find('\d+', 'abc 123 456');");
+    assert_eq!(found, "123");
+    let found: Option<String> = fetch_one_cell!(conn, r"SELECT regex_find('\d+', 'abc');");
+    assert_eq!(found, None);
+    // Positions agree with instr() and substr(), which count characters
+    let (pos, instr): (i64, i64) = conn.query_row(
+        r"SELECT regex_find_pos('\d+', 'héllo 42'), instr('héllo 42', '42');",
+        &[], |r| (r.get(0), r.get(1))).unwrap();
+    assert_eq!(pos, 7);
+    assert_eq!(pos, instr);
+    let pos: i64 = fetch_one_cell!(conn, r"SELECT regex_find_pos('\d+', 'héllo 42', 'byte');");
+    assert_eq!(pos, 8);
+    let pos: i64 = fetch_one_cell!(conn, r"SELECT regex_find_pos('\d+', 'hello');");
+    assert_eq!(pos, 0);
+}
+
+#[test]
+fn regex_captures_json_names_every_group() {
+    let conn = get_connection();
+    let json: String = fetch_one_cell!(conn,
+        r#"SELECT regex_captures_json('(?P<key>\w+)="(?P<value>[^"]*)"(?P<rest>;)?', 'name="a \"b"');"#);
+    assert_eq!(json, r#"{"key":"name","value":"a \\","rest":null}"#);
+    let nothing: Option<String> = fetch_one_cell!(conn, r"SELECT regex_captures_json('(?P<x>\d)', 'abc');");
+    assert_eq!(nothing, None);
+}
+
+#[test]
+fn flags_change_how_patterns_match() {
+    let conn = get_connection();
+    let found: i64 = fetch_one_cell!(conn, "SELECT search('hello', 'HeLLo world', 'i');");
+    assert_eq!(found, 1);
+    let found: i64 = fetch_one_cell!(conn, "SELECT match('hello', 'HELLO', 'i');");
+    assert_eq!(found, 1);
+    let lines: String = fetch_one_cell!(conn, r"SELECT sub('^(\w+)', '<$1>', 'one' || char(10) || 'two', 'm');");
+    assert_eq!(lines, "<one>\n<two>");
+    let dotall: String = fetch_one_cell!(conn, r"SELECT regex_find('a.b', 'a' || char(10) || 'b', 's');");
+    assert_eq!(dotall, "a\nb");
+    // A trailing comment doesn't stop match() anchoring the pattern
+    let verbose: i64 = fetch_one_cell!(conn, r"SELECT match('\d+  # digits', '123', 'x');");
+    assert_eq!(verbose, 1);
+    let piece: String = fetch_one_cell!(conn, "SELECT regex_split_part('and', 'A AND B', 2, 'i');");
+    assert_eq!(piece, " B");
+    let nothing: Option<i64> = fetch_one_cell!(conn, "SELECT search('a', 'a', NULL);");
+    assert_eq!(nothing, None);
+    assert!(fetch_error(&conn, "SELECT search('a', 'a', 'q');").contains("unknown flag 'q'"));
+}
+
+#[test]
+fn backtracking_engine_supports_lookaround_and_backreferences() {
+    let conn = get_connection();
+    let doubled: String = fetch_one_cell!(conn, r"SELECT regex_find('(\w)\1', 'hello', 'b');");
+    assert_eq!(doubled, "ll");
+    let price: String = fetch_one_cell!(conn, r"SELECT regex_find('\d+(?=€)', '12 apples for 30€', 'b');");
+    assert_eq!(price, "30");
+    let swapped: String = fetch_one_cell!(conn,
+        r"SELECT sub('(?<=-)(\w+)', '[$1]', 'x-ray y-axis', 'b');");
+    assert_eq!(swapped, "x-[ray] y-[axis]");
+    let repeated: i64 = fetch_one_cell!(conn,
+        r"SELECT count(*) FROM regex_matches('\b(\w+) \1\b', 'it is is a test test', 'ib');");
+    assert_eq!(repeated, 2);
+    // The linear engine explains how to get these features
+    assert!(fetch_error(&conn, r"SELECT search('(\w)\1', 'hello');").contains("'b' flag"));
+    // Catastrophic backtracking is cut off instead of hanging
+    assert!(fetch_error(&conn,
+        r"SELECT search('^(a+)+\1b', 'aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac', 'b');")
+        .contains("backtracking count exceeded"));
+}
@@ -0,0 +1,79 @@
+extern crate rusqlite;
+extern crate glob;
+extern crate sqlite3_extras;
+#[macro_use] mod common;
+use common::get_connection;
+use sqlite3_extras::vector::simd::Isa;
+
+/// Pseudo-random numbers from -1 to 1, scaled by powers of two so the
+/// magnitudes vary
+fn random(n: usize, seed: u64) -> Vec<f64> {
+    let mut state = seed;
+    (0..n).map(|i| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((state >> 11) as f64 / (1u64 << 52) as f64 - 1.0) * f64::from(1 << (i % 7))
+    }).collect()
+}
+
+fn bits(values: &[f64]) -> Vec<u64> {
+    values.iter().map(|x| x.to_bits()).collect()
+}
+
+#[test]
+fn every_instruction_set_gives_the_same_results() {
+    let supported: Vec<Isa> = Isa::ALL.iter().cloned().filter(|isa| isa.is_supported()).collect();
+    assert!(supported.contains(&Isa::Scalar));
+    assert!(Isa::best().is_supported());
+    for n in (0..70).chain(vec![1000, 1531]) {
+        let a = random(n, 1);
+        let b = random(n, n as u64 + 2);
+        for &isa in supported.iter() {
+            let context = format!("{} with {} elements", isa.name(), n);
+            assert_eq!(isa.dot(&a, &b).to_bits(), Isa::Scalar.dot(&a, &b).to_bits(), "dot, {}", context);
+            assert_eq!(isa.l2_squared(&a, &b).to_bits(), Isa::Scalar.l2_squared(&a, &b).to_bits(), "l2, {}", context);
+            assert_eq!(isa.l1(&a, &b).to_bits(), Isa::Scalar.l1(&a, &b).to_bits(), "l1, {}", context);
+            assert_eq!(bits(&isa.add(&a, &b)), bits(&Isa::Scalar.add(&a, &b)), "add, {}", context);
+            assert_eq!(bits(&isa.subtract(&a, &b)), bits(&Isa::Scalar.subtract(&a, &b)), "subtract, {}", context);
+            assert_eq!(bits(&isa.mult(&a, &b)), bits(&Isa::Scalar.mult(&a, &b)), "mult, {}", context);
+            assert_eq!(bits(&isa.div(&a, &b)), bits(&Isa::Scalar.div(&a, &b)), "div, {}", context);
+        }
+    }
+    // Unsupported instruction sets fall back to the scalar code
+    let a = random(33, 3);
+    for isa in Isa::ALL.iter() {
+        assert_eq!(isa.dot(&a, &a).to_bits(), Isa::Scalar.dot(&a, &a).to_bits(), "{}", isa.name());
+    }
+}
+
+#[test]
+fn sums_are_within_the_documented_tolerance() {
+    // The unit roundoff, 2⁻⁵³
+    let epsilon = f64::EPSILON / 2.0;
+    for &n in [1, 7, 8, 9, 100, 768, 10000].iter() {
+        let a = random(n, 5);
+        let b = random(n, 6);
+        // The terms added one by one, as extras.cpp did
+        let terms: Vec<f64> = a.iter().zip(&b).map(|(x, y)| x * y).collect();
+        let plain = terms.iter().fold(0.0, |sum, x| sum + x);
+        let bound = 2.0 * n as f64 * epsilon * terms.iter().map(|x| x.abs()).sum::<f64>();
+        let fast = Isa::best().dot(&a, &b);
+        assert!((fast - plain).abs() <= bound, "{} elements: {} vs {}", n, fast, plain);
+        let plain: f64 = a.iter().zip(&b).fold(0.0, |sum, (x, y)| sum + (x - y).abs());
+        let bound = 2.0 * n as f64 * epsilon * plain;
+        assert!((Isa::best().l1(&a, &b) - plain).abs() <= bound, "{} elements", n);
+    }
+}
+
+#[test]
+fn sql_functions_use_the_kernels() {
+    let conn = get_connection();
+    let counting = (1..=20).map(|i| i.to_string()).collect::<Vec<_>>().join(" ");
+    let dot: f64 = fetch_one_cell!(conn, &format!("SELECT vec_dot(vread('{0}'), vread('{0}'));", counting));
+    assert_eq!(dot, 2870.0);
+    let l1: f64 = fetch_one_cell!(conn, &format!("SELECT vec_l1(vread('{}'), vzero(20));", counting));
+    assert_eq!(l1, 210.0);
+    let same: bool = fetch_one_cell!(conn, &format!(
+        "SELECT \"add\"(vread('{0}'), vread('{0}')) = mult(vread('{0}'), 2)
+            AND div(subtract(vread('{0}'), vread('{0}')), vread('{0}')) = vzero(20);", counting));
+    assert!(same);
+}
@@ -0,0 +1,76 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error, sqlite_version};
+
+#[test]
+fn vec_each_yields_numbered_elements() {
+    let conn = get_connection();
+    let mut stmt = conn.prepare("SELECT idx, value FROM vec_each(vread('1.5 -2 0.25'));").unwrap();
+    let rows: Vec<(i64, f64)> = stmt
+        .query_map(&[], |r| (r.get(0), r.get(1))).unwrap()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(rows, vec![(1, 1.5), (2, -2.0), (3, 0.25)]);
+    let bits: String = fetch_one_cell!(conn, "SELECT group_concat(typeof(value) || value, ' ') FROM vec_each(vread('1 0 1', 'bit'));");
+    assert_eq!(bits, "integer1 integer0 integer1");
+    let none: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM vec_each(NULL);");
+    assert_eq!(none, 0);
+
+    // Per-dimension statistics over a column of vectors
+    conn.execute_batch("
+        CREATE TABLE points(id INTEGER PRIMARY KEY, v BLOB);
+        INSERT INTO points(v) VALUES (vread('1 10')), (vread('3 20')), (vread('5 60'));
+    ").unwrap();
+    let spreads: String = fetch_one_cell!(conn, "
+        SELECT group_concat(idx || ':' || spread, ' ') FROM (
+            SELECT e.idx, max(e.value) - min(e.value) AS spread
+            FROM points, vec_each(points.v) AS e GROUP BY e.idx);");
+    assert_eq!(spreads, "1:4.0 2:50.0");
+    assert!(fetch_error(&conn, "SELECT * FROM vec_each('1 2');").contains("vec_each(): expected a vector, not text"));
+}
+
+#[test]
+fn vec_collect_rebuilds_vectors() {
+    let conn = get_connection();
+    let shown: String = fetch_one_cell!(conn,
+        "SELECT vshow(vec_collect(max(value, 0))) FROM (SELECT value FROM vec_each(vread('1 -2 3')) ORDER BY idx);");
+    assert_eq!(shown, "1.0 0.0 3.0");
+    let typed: String = fetch_one_cell!(conn,
+        "SELECT vec_type(vec_collect(value, 'f32')) FROM vec_each(vread('1 2'));");
+    assert_eq!(typed, "f32");
+    let round_trip: bool = fetch_one_cell!(conn,
+        "SELECT vec_collect(value, 'i8') = vread('4 -5 6', 'i8') FROM vec_each(vread('4 -5 6', 'i8'));");
+    assert!(round_trip);
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT vec_collect(value) FROM vec_each(vzero(0));");
+    assert_eq!(nothing, None);
+
+    assert!(fetch_error(&conn, "SELECT vec_collect(x) FROM (SELECT 1 AS x UNION ALL SELECT NULL);")
+        .contains("vec_collect(): element 2 should be a number, not NULL"));
+    assert!(fetch_error(&conn, "SELECT vec_collect(x, 'i8') FROM (SELECT 1000 AS x);")
+        .contains("vec_collect(): element 1 is 1000, which isn't an i8"));
+
+    // Window functions need SQLite 3.25
+    if sqlite_version(&conn) < 3025000 {
+        return;
+    }
+
+    // A sliding window of consecutive readings
+    conn.execute_batch("
+        CREATE TABLE readings(t INTEGER PRIMARY KEY, x REAL);
+        INSERT INTO readings VALUES (1, 1), (2, 2), (3, 3), (4, 4);
+    ").unwrap();
+    let windows: String = fetch_one_cell!(conn, "
+        SELECT group_concat(w, ' | ') FROM (
+            SELECT vshow(vec_collect(x) OVER (ORDER BY t ROWS BETWEEN 1 PRECEDING AND CURRENT ROW)) AS w
+            FROM readings);");
+    assert_eq!(windows, "1.0 | 1.0 2.0 | 2.0 3.0 | 3.0 4.0");
+
+    // ORDER BY inside an aggregate needs SQLite 3.44
+    if sqlite_version(&conn) < 3044000 {
+        return;
+    }
+    let reversed: String = fetch_one_cell!(conn,
+        "SELECT vshow(vec_collect(value ORDER BY idx DESC)) FROM vec_each(vread('1 -2 3'));");
+    assert_eq!(reversed, "3.0 -2.0 1.0");
+}
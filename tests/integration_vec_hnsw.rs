@@ -0,0 +1,164 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, get_file_connection, fetch_error};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+
+fn rows(conn: &rusqlite::Connection, sql: &str) -> Vec<(i64, f64)> {
+    let mut stmt = conn.prepare(sql).unwrap();
+    let rows = stmt.query_map(&[], |row| (row.get(0), row.get(1))).unwrap();
+    rows.map(|row| row.unwrap()).collect()
+}
+
+/// Reproducible vectors with elements between -1 and 1, as JSON
+fn random_vectors(count: usize, dims: usize, seed: u64) -> Vec<String> {
+    let mut state = seed;
+    (0..count).map(|_| {
+        let elements: Vec<String> = (0..dims).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            format!("{}", (state >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0)
+        }).collect();
+        format!("[{}]", elements.join(","))
+    }).collect()
+}
+
+#[test]
+fn hnsw_indexes_inserts_updates_and_deletes() {
+    let conn = get_connection();
+    conn.execute_batch("
+        CREATE VIRTUAL TABLE idx USING vec_hnsw(dim=2, m=4, ef_construction=20);
+        INSERT INTO idx(rowid, embedding) VALUES
+            (1, vread('0 0')), (2, vread('1 0')), (3, vread('0 2')), (4, vread('5 5'));
+        INSERT INTO idx(embedding) VALUES (vec_f32(3, 3));
+    ").unwrap();
+
+    let found = rows(&conn, "SELECT rowid, distance FROM idx WHERE embedding MATCH vread('0 0') AND k = 3;");
+    assert_eq!(found, vec![(1, 0.0), (2, 1.0), (3, 2.0)]);
+    let found = rows(&conn, "SELECT rowid, distance FROM idx WHERE embedding MATCH vread('3 3') AND k = 1;");
+    assert_eq!(found, vec![(5, 0.0)]);
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(embedding) FROM idx WHERE rowid = 5;");
+    assert_eq!(ty, "f32");
+    // Vectors come back exactly as they went in, even quantized ones
+    conn.execute_batch("INSERT INTO idx(rowid, embedding) VALUES (6, vec_quantize_i8(vread('0.3 0.7'), 0, 1));").unwrap();
+    let same: bool = fetch_one_cell!(conn, "SELECT embedding = vec_quantize_i8(vread('0.3 0.7'), 0, 1) FROM idx WHERE rowid = 6;");
+    assert!(same);
+    conn.execute_batch("DELETE FROM idx WHERE rowid = 6;").unwrap();
+    let count: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM idx;");
+    assert_eq!(count, 5);
+
+    conn.execute_batch("
+        DELETE FROM idx WHERE rowid = 1;
+        UPDATE idx SET embedding = vread('0 0.5') WHERE rowid = 4;
+    ").unwrap();
+    let found = rows(&conn, "SELECT rowid, distance FROM idx WHERE embedding MATCH vread('0 0') AND k = 2;");
+    assert_eq!(found, vec![(4, 0.5), (2, 1.0)]);
+    let nodes: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM idx_nodes;");
+    assert_eq!(nodes, 4);
+    // Moving a row onto another's rowid fails without losing it
+    assert!(fetch_error(&conn, "UPDATE idx SET rowid = 2 WHERE rowid = 4;").contains("there is already a row with rowid 2"));
+    let found = rows(&conn, "SELECT rowid, distance FROM idx WHERE embedding MATCH vread('0 0.5') AND k = 1;");
+    assert_eq!(found, vec![(4, 0.0)]);
+
+    // Rolled back changes are forgotten
+    conn.execute_batch("BEGIN; INSERT INTO idx(rowid, embedding) VALUES (9, vread('0 0')); ROLLBACK;").unwrap();
+    let found = rows(&conn, "SELECT rowid, distance FROM idx WHERE embedding MATCH vread('0 0') AND k = 1;");
+    assert_eq!(found, vec![(4, 0.5)]);
+
+    conn.execute_batch("ALTER TABLE idx RENAME TO renamed;").unwrap();
+    let nodes: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM renamed_nodes;");
+    assert_eq!(nodes, 4);
+    conn.execute_batch("DROP TABLE renamed;").unwrap();
+    let tables: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM sqlite_master;");
+    assert_eq!(tables, 0);
+}
+
+#[test]
+fn hnsw_checks_options_and_vectors() {
+    let conn = get_connection();
+    assert!(fetch_error(&conn, "CREATE VIRTUAL TABLE a USING vec_hnsw(metric=cosine);")
+        .contains("the number of dimensions is required"));
+    assert!(fetch_error(&conn, "CREATE VIRTUAL TABLE a USING vec_hnsw(dim=2, metric=euclid);")
+        .contains("unknown metric 'euclid'"));
+    assert!(fetch_error(&conn, "CREATE VIRTUAL TABLE a USING vec_hnsw(dim=0);")
+        .contains("dim should be a positive integer"));
+    assert!(fetch_error(&conn, "CREATE VIRTUAL TABLE a USING vec_hnsw(dim=2, size=3);")
+        .contains("unknown option 'size'"));
+
+    conn.execute_batch("CREATE VIRTUAL TABLE idx USING vec_hnsw(dim=2, metric=cosine);").unwrap();
+    assert!(fetch_error(&conn, "INSERT INTO idx(embedding) VALUES (vread('1 2 3'));")
+        .contains("the vector has 3 dimensions but the index has 2"));
+    assert!(fetch_error(&conn, "INSERT INTO idx(embedding) VALUES (vzero(2));")
+        .contains("the cosine distance isn't defined for this vector"));
+    assert!(fetch_error(&conn, "INSERT INTO idx(embedding) VALUES ('1 2');")
+        .contains("the embedding should be a vector, not text"));
+    conn.execute_batch("INSERT INTO idx(rowid, embedding) VALUES (1, vread('1 2'));").unwrap();
+    assert!(fetch_error(&conn, "INSERT INTO idx(rowid, embedding) VALUES (1, vread('2 1'));")
+        .contains("there is already a row with rowid 1"));
+    assert!(fetch_error(&conn, "SELECT * FROM idx WHERE embedding MATCH vread('1 2');")
+        .contains("a MATCH needs k too"));
+    assert!(fetch_error(&conn, "SELECT * FROM idx WHERE embedding MATCH vread('1 2 3') AND k = 1;")
+        .contains("the vector has 3 dimensions but the index has 2"));
+
+    // Links that aren't whole rowids are corrupt, and counting the change
+    // makes the index read them again
+    conn.execute_batch("
+        UPDATE idx_edges SET neighbours = X'010203';
+        UPDATE idx_info SET changes = changes + 1;
+    ").unwrap();
+    assert!(fetch_error(&conn, "SELECT * FROM idx WHERE embedding MATCH vread('1 2') AND k = 1;")
+        .contains("node 1: the links on layer 0 are 3 bytes, which isn't a whole number of rowids"));
+}
+
+#[test]
+fn hnsw_recall_matches_brute_force() {
+    let conn = get_connection();
+    conn.execute_batch("
+        CREATE TABLE points(id INTEGER PRIMARY KEY, v BLOB);
+        CREATE VIRTUAL TABLE idx USING vec_hnsw(dim=16);
+    ").unwrap();
+    for (i, json) in random_vectors(1000, 16, 7).iter().enumerate() {
+        let id = i as i64 + 1;
+        conn.execute("INSERT INTO points VALUES (?, vec_from_json(?, 'f32'));", &[&id, json]).unwrap();
+    }
+    conn.execute_batch("INSERT INTO idx(rowid, embedding) SELECT id, v FROM points;").unwrap();
+
+    let mut found = 0;
+    let queries = random_vectors(20, 16, 99);
+    for json in &queries {
+        let exact: HashSet<i64> = rows(&conn, &format!(
+            "SELECT rowid, distance FROM vec_knn('points', 'v', vec_from_json('{}'), 10);", json))
+            .into_iter().map(|(id, _)| id).collect();
+        let approximate = rows(&conn, &format!(
+            "SELECT rowid, distance FROM idx WHERE embedding MATCH vec_from_json('{}') AND k = 10;", json));
+        assert_eq!(approximate.len(), 10);
+        found += approximate.iter().filter(|&&(id, _)| exact.contains(&id)).count();
+    }
+    let recall = found as f64 / (10 * queries.len()) as f64;
+    assert!(recall >= 0.95, "recall was {}", recall);
+}
+
+#[test]
+fn hnsw_index_persists_across_connections() {
+    let path = env::temp_dir().join(format!("hnsw-test-{}.db", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let first = get_file_connection(&path);
+    first.execute_batch("CREATE VIRTUAL TABLE idx USING vec_hnsw(dim=8, metric=l1);").unwrap();
+    for (i, json) in random_vectors(200, 8, 3).iter().enumerate() {
+        let id = i as i64 + 1;
+        first.execute("INSERT INTO idx(rowid, embedding) VALUES (?, vec_from_json(?));", &[&id, json]).unwrap();
+    }
+    let query = "SELECT rowid, distance FROM idx WHERE embedding MATCH vzero(8) AND k = 5;";
+    let before = rows(&first, query);
+
+    let second = get_file_connection(&path);
+    assert_eq!(rows(&second, query), before);
+    second.execute("DELETE FROM idx WHERE rowid = ?;", &[&before[0].0]).unwrap();
+    // The first connection notices the change
+    assert_eq!(rows(&first, query)[..4], before[1..]);
+
+    drop(first);
+    drop(second);
+    fs::remove_file(&path).unwrap();
+}
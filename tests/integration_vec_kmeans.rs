@@ -0,0 +1,81 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+/// 30 points scattered within 1 of each of three centres
+fn three_blobs(conn: &rusqlite::Connection) {
+    conn.execute_batch("CREATE TABLE points(id INTEGER PRIMARY KEY, blob INTEGER, v BLOB);").unwrap();
+    let centres = [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)];
+    let mut state = 7u64;
+    let mut jitter = || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    };
+    for i in 0..90 {
+        let (x, y) = centres[i % 3];
+        conn.execute(&format!("INSERT INTO points(blob, v) VALUES ({}, vec_f64({}, {}));",
+            i % 3, x + jitter(), y + jitter()), &[]).unwrap();
+    }
+}
+
+#[test]
+fn kmeans_finds_clusters() {
+    let conn = get_connection();
+    three_blobs(&conn);
+    conn.execute_batch("CREATE TABLE clusters AS SELECT vec_kmeans(v, 3, 20, 42) AS centroids FROM points;").unwrap();
+    let json: String = fetch_one_cell!(conn, "SELECT mat_to_json(centroids) FROM clusters;");
+    let rows: Vec<Vec<f64>> = json.trim_matches(|c| c == '[' || c == ']').split("],[")
+        .map(|row| row.split(',').map(|x| x.parse().unwrap()).collect())
+        .collect();
+    assert_eq!(rows.len(), 3, "{}", json);
+    for centre in [[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]].iter() {
+        assert!(rows.iter().any(|row| (row[0] - centre[0]).abs() < 0.5 && (row[1] - centre[1]).abs() < 0.5),
+            "no centroid near {:?} in {}", centre, json);
+    }
+    // Every point of a blob is in the same cluster, and each blob has its own
+    let clusters: i64 = fetch_one_cell!(conn, "
+        SELECT count(DISTINCT blob || ':' || vec_nearest_centroid(v, (SELECT centroids FROM clusters))) FROM points;");
+    assert_eq!(clusters, 3);
+    let ids: String = fetch_one_cell!(conn, "
+        SELECT group_concat(id) FROM (SELECT DISTINCT vec_nearest_centroid(v, (SELECT centroids FROM clusters)) AS id
+        FROM points ORDER BY id);");
+    assert_eq!(ids, "1,2,3");
+
+    // The same seed gives the same centroids
+    let same: bool = fetch_one_cell!(conn, "SELECT vec_kmeans(v, 3, 20, 42) = (SELECT centroids FROM clusters) FROM points;");
+    assert!(same);
+}
+
+#[test]
+fn kmeans_handles_small_and_odd_groups() {
+    let conn = get_connection();
+    conn.execute_batch("
+        CREATE TABLE few(g INTEGER, v BLOB);
+        INSERT INTO few VALUES (1, vec_f32(1, 1)), (1, vec_f32(1, 1)), (1, NULL), (1, vec_f32(2, 2)), (2, NULL);
+    ").unwrap();
+    // Only two distinct vectors, so only two centroids
+    let json: String = fetch_one_cell!(conn, "SELECT mat_to_json(vec_kmeans(v, 5)) FROM few WHERE g = 1;");
+    assert!(json == "[[1.0,1.0],[2.0,2.0]]" || json == "[[2.0,2.0],[1.0,1.0]]", "{}", json);
+    // f32 vectors give an f32 matrix: the header and 2x2 4-byte elements
+    let len: i64 = fetch_one_cell!(conn, "SELECT length(vec_kmeans(v, 2, 0)) FROM few WHERE g = 1;");
+    assert_eq!(len, 12 + 16);
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT vec_kmeans(v, 2) FROM few WHERE g = 2;");
+    assert_eq!(nothing, None);
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT vec_kmeans(v, 2) FROM few WHERE g = 3;");
+    assert_eq!(nothing, None);
+
+    let id: i64 = fetch_one_cell!(conn, "SELECT vec_nearest_centroid(vread('2 1.9'), mat_from_json('[[0,0],[2,2],[2,2]]'));");
+    assert_eq!(id, 2);
+    let nothing: Option<i64> = fetch_one_cell!(conn, "SELECT vec_nearest_centroid(vread('1 1'), NULL);");
+    assert_eq!(nothing, None);
+
+    assert!(fetch_error(&conn, "SELECT vec_kmeans(v, 0) FROM few;").contains("k should be at least 1, not 0"));
+    assert!(fetch_error(&conn, "SELECT vec_kmeans(v, 2, -1) FROM few;").contains("iterations can't be negative"));
+    assert!(fetch_error(&conn, "SELECT vec_kmeans(v, 2, 5, 'seed') FROM few;").contains("seed should be an integer, not text"));
+    assert!(fetch_error(&conn, "SELECT vec_kmeans(v, 2) FROM (SELECT vread('1 2') AS v UNION ALL SELECT vread('1'));")
+        .contains("every vector must have 2 dimensions like the first, not 1"));
+    assert!(fetch_error(&conn, "SELECT vec_kmeans(div(vread('0 1'), vread('0 1')), 2);").contains("element 1 is NaN"));
+    assert!(fetch_error(&conn, "SELECT vec_nearest_centroid(vread('1'), mat_identity(2));")
+        .contains("the vector has 1 dimensions but the centroids have 2"));
+}
@@ -0,0 +1,74 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+fn nearest(conn: &rusqlite::Connection, sql: &str) -> Vec<(i64, f64)> {
+    let mut stmt = conn.prepare(sql).unwrap();
+    let rows = stmt.query_map(&[], |row| (row.get(0), row.get(1))).unwrap();
+    rows.map(|row| row.unwrap()).collect()
+}
+
+#[test]
+fn knn_finds_the_nearest_rows_in_order() {
+    let conn = get_connection();
+    conn.execute_batch("
+        CREATE TABLE points(id INTEGER PRIMARY KEY, label TEXT, v BLOB);
+        INSERT INTO points VALUES
+            (1, 'far', vread('10 10')),
+            (2, 'origin', vread('0 0')),
+            (3, 'near', vread('1 1')),
+            (4, 'missing', NULL),
+            (5, 'nearer', vec_f32(0.5, 0)),
+            (6, 'also near', vread('1 1', 'i8'));
+    ").unwrap();
+
+    let rows = nearest(&conn, "SELECT rowid, distance FROM vec_knn('points', 'v', vread('0 0'), 3);");
+    assert_eq!(rows, vec![(2, 0.0), (5, 0.5), (3, 2f64.sqrt())]);
+
+    // Ties are broken by rowid, and a join gets the rest of the row
+    let labels: String = fetch_one_cell!(conn, "
+        SELECT group_concat(label, ',') FROM (
+            SELECT points.label FROM vec_knn('points', 'v', vread('1 1'), 2) AS knn
+            JOIN points ON points.id = knn.rowid ORDER BY knn.distance);");
+    assert_eq!(labels, "near,also near");
+
+    let rows = nearest(&conn, "SELECT rowid, distance FROM vec_knn('points', 'v', vread('1 0'), 2, 'l1');");
+    assert_eq!(rows, vec![(5, 0.5), (2, 1.0)]);
+    // The zero vector has no direction, so cosine skips it
+    let rows = nearest(&conn, "SELECT rowid, distance FROM vec_knn('points', 'v', vread('2 0'), 10, 'cosine');");
+    assert_eq!(rows.len(), 4);
+    assert_eq!(rows[0], (5, 0.0));
+    let rows = nearest(&conn, "SELECT rowid, distance FROM vec_knn('points', 'v', vread('1 1'), 1, 'dot');");
+    assert_eq!(rows, vec![(1, -20.0)]);
+
+    let count: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM vec_knn('points', 'v', vread('0 0'), 100);");
+    assert_eq!(count, 5);
+    let count: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM vec_knn('points', 'v', vread('0 0'), 0);");
+    assert_eq!(count, 0);
+    let count: i64 = fetch_one_cell!(conn, "SELECT count(*) FROM vec_knn('points', 'v', NULL, 5);");
+    assert_eq!(count, 0);
+}
+
+#[test]
+fn knn_reports_bad_arguments_and_rows() {
+    let conn = get_connection();
+    conn.execute_batch("
+        CREATE TABLE \"odd \"\"name\"(v);
+        INSERT INTO \"odd \"\"name\" VALUES (vread('1 2')), (vread('1 2 3'));
+        CREATE TABLE words(v);
+        INSERT INTO words VALUES ('not a vector');
+    ").unwrap();
+    assert!(fetch_error(&conn, "SELECT * FROM vec_knn('odd \"name', 'v', vread('0 0'), 5);")
+        .contains("vec_knn(): row 2 has 3 dimensions but the query has 2"));
+    assert!(fetch_error(&conn, "SELECT * FROM vec_knn('words', 'v', vread('0 0'), 5);")
+        .contains("row 1 has text, not a vector"));
+    assert!(fetch_error(&conn, "SELECT * FROM vec_knn('nowhere', 'v', vread('0 0'), 5);")
+        .contains("no such table: nowhere"));
+    assert!(fetch_error(&conn, "SELECT * FROM vec_knn('words', 'v', vread('0 0'), 5, 'euclid');")
+        .contains("unknown metric 'euclid'"));
+    assert!(fetch_error(&conn, "SELECT * FROM vec_knn('words', 'v', vread('0 0'), -1);")
+        .contains("k can't be negative"));
+    assert!(fetch_error(&conn, "SELECT * FROM vec_knn('words', 'v', vread('0 0'));")
+        .contains("requires a table, a column, a query vector and k"));
+}
@@ -0,0 +1,91 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn vectors_have_a_self_describing_header() {
+    let conn = get_connection();
+    let hex: String = fetch_one_cell!(conn, "SELECT hex(vec_f32(1, -2.5));");
+    assert_eq!(hex, "58560101020000000000803F000020C0");
+    let hex: String = fetch_one_cell!(conn, "SELECT hex(vec_f64(1));");
+    assert_eq!(hex, "5856010201000000000000000000F03F");
+    let (ty, dims): (String, i64) = conn.query_row(
+        "SELECT vec_type(v), vec_dims(v) FROM (SELECT vec_f64(1, 2, 3) AS v);", &[],
+        |r| (r.get(0), r.get(1))).unwrap();
+    assert_eq!((ty.as_str(), dims), ("f64", 3));
+    // Converting keeps the values
+    let hex: String = fetch_one_cell!(conn, "SELECT hex(vec_f32(vec_f64(1, -2.5)));");
+    assert_eq!(hex, "58560101020000000000803F000020C0");
+    let dims: i64 = fetch_one_cell!(conn, "SELECT vec_dims(vec_f32());");
+    assert_eq!(dims, 0);
+    // i8 and bit vectors can be read even without functions to make them yet
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(X'585601030200000001FF');");
+    assert_eq!(ty, "i8");
+    let dims: i64 = fetch_one_cell!(conn, "SELECT vec_dims(X'58560104090000000501');");
+    assert_eq!(dims, 9);
+    let nothing: Option<String> = fetch_one_cell!(conn, "SELECT vec_type(NULL);");
+    assert_eq!(nothing, None);
+
+    assert!(fetch_error(&conn, "SELECT vec_dims(X'0000803F');").contains("not a vector"));
+    assert!(fetch_error(&conn, "SELECT vec_dims(X'5856020101000000');").contains("version 2"));
+    assert!(fetch_error(&conn, "SELECT vec_dims(X'5856010900000000');").contains("vec_dims()"));
+    assert!(fetch_error(&conn, "SELECT vec_dims(X'58560101020000000000803F');").contains("corrupt"));
+    assert!(fetch_error(&conn, "SELECT vec_f32(1, 'two');").contains("argument 2"));
+    assert!(fetch_error(&conn, "SELECT vec_type('1 2 3');").contains("expected a vector"));
+}
+
+#[test]
+fn legacy_vectors_are_imported_with_their_element_type() {
+    let conn = get_connection();
+    let hex: String = fetch_one_cell!(conn, "SELECT hex(vec_import(X'000000000000F03F0000000000000040', 'f64'));");
+    assert_eq!(hex, "5856010202000000000000000000F03F0000000000000040");
+    let dims: i64 = fetch_one_cell!(conn, "SELECT vec_dims(vec_import(X'0000803F00000040', 'f32'));");
+    assert_eq!(dims, 2);
+    let dims: i64 = fetch_one_cell!(conn, "SELECT vec_dims(vec_import(X'FF01', 'bit'));");
+    assert_eq!(dims, 16);
+    assert!(fetch_error(&conn, "SELECT vec_import(X'0000803F00', 'f32');").contains("whole number"));
+    assert!(fetch_error(&conn, "SELECT vec_import(X'0000803F', 'float');").contains("unknown element type"));
+}
+
+#[test]
+fn vectors_round_trip_through_text_and_json() {
+    let conn = get_connection();
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(vread('  3 7\t6 1.2 -0.1 1e-7 '));");
+    assert_eq!(shown, "3.0 7.0 6.0 1.2 -0.1 1e-7");
+    let same: i64 = fetch_one_cell!(conn,
+        "SELECT vread(vshow(v), 'f32') = v FROM (SELECT vec_f32(0.1, 1.0 / 3, 16777217, -1e-30) AS v);");
+    assert_eq!(same, 1);
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(vread('0.1 -128 1', 'f32'));");
+    assert_eq!(shown, "0.1 -128.0 1.0");
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(vread('-128 0 127', 'i8')) || '|' || vshow(vread('1 0 1', 'bit'));");
+    assert_eq!(shown, "-128 0 127|1 0 1");
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(vread('1 2'));");
+    assert_eq!(ty, "f64");
+
+    let json: String = fetch_one_cell!(conn, "SELECT vec_to_json(vec_from_json('[1, 2.5, -3e-5]'));");
+    assert_eq!(json, "[1.0,2.5,-3e-5]");
+    let total: f64 = fetch_one_cell!(conn, "SELECT sum(value) FROM json_each(vec_to_json(vread('1 2 3.5')));");
+    assert_eq!(total, 6.5);
+    let json: String = fetch_one_cell!(conn, "SELECT vec_to_json(vec_from_json('[3, -4]', 'i8'));");
+    assert_eq!(json, "[3,-4]");
+    let json: String = fetch_one_cell!(conn, "SELECT vec_to_json(vread(''));");
+    assert_eq!(json, "[]");
+
+    let zeros: String = fetch_one_cell!(conn, "SELECT vshow(vzero(3)) || '|' || vshow(vone(2, 'i8'));");
+    assert_eq!(zeros, "0.0 0.0 0.0|1 1");
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT vread(NULL);");
+    assert_eq!(nothing, None);
+
+    assert!(fetch_error(&conn, "SELECT vread('1 2 three 4');").contains("element 3 is 'three'"));
+    assert!(fetch_error(&conn, "SELECT vread('1,2');").contains("isn't a number"));
+    assert!(fetch_error(&conn, "SELECT vread('1 200', 'i8');").contains("element 2 is 200"));
+    assert!(fetch_error(&conn, "SELECT vread('0.5', 'bit');").contains("isn't a bit"));
+    assert!(fetch_error(&conn, "SELECT vec_from_json('{\"a\": 1}');").contains("expected a JSON array"));
+    assert!(fetch_error(&conn, "SELECT vec_from_json('[1, \"2\"]');").contains("element 2"));
+    assert!(fetch_error(&conn, "SELECT vec_from_json('[1,');").contains("vec_from_json()"));
+    assert!(fetch_error(&conn, "SELECT vec_to_json(vread('1 inf'));").contains("can't represent"));
+    assert!(fetch_error(&conn, "SELECT vzero(-1);").contains("negative"));
+    assert!(fetch_error(&conn, "SELECT vzero(4000000000);").contains("too big"));
+    assert!(fetch_error(&conn, "SELECT vone(2, 'f16');").contains("unknown element type"));
+}
@@ -0,0 +1,71 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error, sqlite_version};
+
+#[test]
+fn vector_aggregates_combine_groups() {
+    let conn = get_connection();
+    conn.execute_batch("
+        CREATE TABLE points (grp INTEGER, v BLOB);
+        INSERT INTO points VALUES (1, vread('1 10')), (1, vread('3 -10')), (1, NULL), (2, vread('5 5'));
+    ").unwrap();
+    let shown: String = fetch_one_cell!(conn,
+        "SELECT group_concat(shown, '|') FROM (
+             SELECT vshow(vec_sum(v)) || '/' || vshow(vec_avg(v)) || '/' || vshow(vec_min(v)) || '/' || vshow(vec_max(v)) AS shown
+             FROM points GROUP BY grp ORDER BY grp);");
+    assert_eq!(shown, "4.0 0.0/2.0 0.0/1.0 -10.0/3.0 10.0|5.0 5.0/5.0 5.0/5.0 5.0/5.0 5.0");
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT vec_avg(v) FROM points WHERE grp = 3;");
+    assert_eq!(nothing, None);
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT vec_max(v) FROM points WHERE v IS NULL;");
+    assert_eq!(nothing, None);
+
+    // Compensated summation keeps the 1s that naive summation would lose
+    let shown: String = fetch_one_cell!(conn,
+        "SELECT vshow(vec_sum(v)) FROM (SELECT vec_f64(1e16) AS v UNION ALL SELECT vec_f64(1)
+         UNION ALL SELECT vec_f64(1) UNION ALL SELECT vec_f64(-1e16));");
+    assert_eq!(shown, "2.0");
+    let ty: String = fetch_one_cell!(conn,
+        "SELECT vec_type(vec_sum(v)) || vec_type(vec_max(v)) FROM (SELECT vread('100 100', 'i8') AS v UNION ALL SELECT vread('100 1', 'i8'));");
+    assert_eq!(ty, "f64i8");
+    let ty: String = fetch_one_cell!(conn,
+        "SELECT vec_type(vec_avg(v)) FROM (SELECT vec_f32(1) AS v UNION ALL SELECT vec_f32(2));");
+    assert_eq!(ty, "f32");
+
+    conn.execute_batch("INSERT INTO points VALUES (2, vread('1 2 3'));").unwrap();
+    assert!(fetch_error(&conn, "SELECT vec_sum(v) FROM points WHERE grp = 2;").contains("must have 2 dimensions"));
+    assert!(fetch_error(&conn, "SELECT vec_min(grp) FROM points;").contains("vec_min(): expected a vector"));
+}
+
+#[test]
+fn vector_aggregates_work_as_sliding_windows() {
+    let conn = get_connection();
+    // Window functions need SQLite 3.25
+    if sqlite_version(&conn) < 3025000 {
+        return;
+    }
+    conn.execute_batch("
+        CREATE TABLE daily (day INTEGER PRIMARY KEY, v BLOB);
+        INSERT INTO daily VALUES (1, vread('1 8')), (2, vread('3 2')), (3, NULL), (4, vread('5 4')), (5, vread('2 6'));
+    ").unwrap();
+    let shown: String = fetch_one_cell!(conn, "
+        SELECT group_concat(shown, '|') FROM (
+            SELECT coalesce(vshow(vec_avg(v) OVER w), 'NULL') || '/' || coalesce(vshow(vec_min(v) OVER w), 'NULL')
+                || '/' || coalesce(vshow(vec_max(v) OVER w), 'NULL') || '/' || coalesce(vshow(vec_sum(v) OVER w), 'NULL') AS shown
+            FROM daily WINDOW w AS (ORDER BY day ROWS BETWEEN 1 PRECEDING AND CURRENT ROW) ORDER BY day);");
+    assert_eq!(shown, [
+        "1.0 8.0/1.0 8.0/1.0 8.0/1.0 8.0",
+        "2.0 5.0/1.0 2.0/3.0 8.0/4.0 10.0",
+        "3.0 2.0/3.0 2.0/3.0 2.0/3.0 2.0",
+        "5.0 4.0/5.0 4.0/5.0 4.0/5.0 4.0",
+        "3.5 5.0/2.0 4.0/5.0 6.0/7.0 10.0",
+    ].join("|"));
+    let shown: String = fetch_one_cell!(conn, "
+        SELECT coalesce(vshow(vec_sum(v) OVER (ORDER BY day ROWS BETWEEN 1 PRECEDING AND 1 PRECEDING)), 'NULL')
+        FROM daily WHERE day = 4;");
+    assert_eq!(shown, "NULL");
+    // The default frame only grows
+    let shown: String = fetch_one_cell!(conn,
+        "SELECT group_concat(shown, '|') FROM (SELECT vshow(vec_max(v) OVER (ORDER BY day)) AS shown FROM daily ORDER BY day);");
+    assert_eq!(shown, "1.0 8.0|3.0 8.0|3.0 8.0|5.0 8.0|5.0 8.0");
+}
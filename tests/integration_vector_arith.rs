@@ -0,0 +1,73 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn arithmetic_broadcasts_numbers_over_vectors() {
+    let conn = get_connection();
+    // ADD is a keyword, so add() has to be quoted
+    let cases = [
+        ("vshow(\"add\"(vread('1 2 3'), vread('10 20 30')))", "11.0 22.0 33.0"),
+        ("vshow(subtract(10, vread('1 2 3')))", "9.0 8.0 7.0"),
+        ("vshow(mult(vread('1 2 3'), 2))", "2.0 4.0 6.0"),
+        ("vshow(div(vread('1 2 3'), vread('2 4 8')))", "0.5 0.5 0.375"),
+        ("vshow(pow(vread('1 2 3'), 2))", "1.0 4.0 9.0"),
+        ("vshow(vmin(vread('1 5 3'), vread('4 2 6')))", "1.0 2.0 3.0"),
+        ("vshow(vmax(vread('1 5 3'), 2))", "2.0 5.0 3.0"),
+        ("vshow(vabs(vread('-1.5 0 2')))", "1.5 0.0 2.0"),
+        ("vshow(vclip(vread('-5 0.5 5'), 0, 1))", "0.0 0.5 1.0"),
+        ("vshow(vclip(vread('-5 0.5 5'), vread('-10 1 0'), vread('10 2 3')))", "-5.0 1.0 3.0"),
+        // Only numbers gives a number
+        ("\"add\"(1, 2.5)", "3.5"),
+        ("pow(2, 10)", "1024.0"),
+        ("vabs(-3)", "3.0"),
+    ];
+    for &(expr, expected) in cases.iter() {
+        let result: String = fetch_one_cell!(conn, &format!("SELECT CAST({} AS TEXT);", expr));
+        assert_eq!(result, expected, "{}", expr);
+    }
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT \"add\"(vread('1 2'), NULL);");
+    assert_eq!(nothing, None);
+}
+
+#[test]
+fn arithmetic_keeps_element_types_and_checks_dimensions() {
+    let conn = get_connection();
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(mult(vec_f32(1, 2), 0.5));");
+    assert_eq!(ty, "f32");
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(\"add\"(vec_f32(1, 2), vec_f64(1, 2)));");
+    assert_eq!(ty, "f64");
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(\"add\"(vread('1 -2', 'i8'), vread('1 1', 'bit')));");
+    assert_eq!(shown, "2 -1");
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(\"add\"(vread('1 -2', 'i8'), vread('1 1', 'bit')));");
+    assert_eq!(ty, "i8");
+
+    assert!(fetch_error(&conn, "SELECT \"add\"(vread('1 2 3'), vread('1 2'));")
+        .contains("argument 2 has 2 dimensions but argument 1 has 3"));
+    assert!(fetch_error(&conn, "SELECT div(vread('1 2', 'i8'), 2);").contains("isn't an i8"));
+    assert!(fetch_error(&conn, "SELECT \"add\"(vread('1 1', 'bit'), vread('1 0', 'bit'));").contains("isn't a bit"));
+    assert!(fetch_error(&conn, "SELECT \"add\"('1 2', 1);").contains("vread()"));
+    assert!(fetch_error(&conn, "SELECT mult(X'0000803F', 2);").contains("argument 1: not a vector"));
+}
+
+#[test]
+fn math_functions_work_on_numbers_and_vectors() {
+    let conn = get_connection();
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(sin(vread('3 7 6 1.2')));");
+    assert_eq!(shown, "0.1411200080598672 0.6569865987187891 -0.27941549819892586 0.9320390859672263");
+    let x: f64 = fetch_one_cell!(conn, "SELECT sqrt(16);");
+    assert_eq!(x, 4.0);
+    let x: f64 = fetch_one_cell!(conn, "SELECT ln(exp(2));");
+    assert_eq!(x, 2.0);
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(sqrt(vec_f32(4, 2)));");
+    assert_eq!(shown, "2.0 1.4142135");
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(cbrt(vec_f32(8, 27)));");
+    assert_eq!(ty, "f32");
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(sqrt(vread('9 16', 'i8')));");
+    assert_eq!(shown, "3 4");
+    let nothing: Option<f64> = fetch_one_cell!(conn, "SELECT sin(NULL);");
+    assert_eq!(nothing, None);
+    assert!(fetch_error(&conn, "SELECT sqrt(vread('2', 'i8'));").contains("sqrt(): element 1"));
+    assert!(fetch_error(&conn, "SELECT sin(X'0102');").contains("sin(): argument 1: not a vector"));
+}
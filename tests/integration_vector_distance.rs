@@ -0,0 +1,48 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn distances_between_vectors() {
+    let conn = get_connection();
+    let cases = [
+        ("vec_dot(vread('1 2 3'), vread('4 5 6'))", 32.0),
+        ("vec_cosine_similarity(vread('1 0'), vread('0 2'))", 0.0),
+        ("vec_cosine_similarity(vread('1 2'), vread('2 4'))", 1.0),
+        ("vec_cosine_distance(vread('1 1'), vread('-1 -1'))", 2.0),
+        ("vec_l2(vread('0 0'), vread('3 4'))", 5.0),
+        ("vec_l2_squared(vread('0 0'), vread('3 4'))", 25.0),
+        ("vec_l1(vread('1 -1'), vread('4 3'))", 7.0),
+        ("vec_linf(vread('1 -1'), vread('4 4'))", 5.0),
+        ("vec_jaccard(vread('1 1 0 0', 'bit'), vread('1 0 1 0', 'bit'))", 2.0 / 3.0),
+        ("vec_jaccard(vread('1 2'), vread('2 2'))", 0.25),
+        // Element types can be mixed
+        ("vec_dot(vread('1 -2', 'i8'), vec_f32(0.5, 0.25))", 0.0),
+        ("vec_l1(vread('1 0 1', 'bit'), vread('0 0 3', 'i8'))", 3.0),
+    ];
+    for &(expr, expected) in cases.iter() {
+        let result: f64 = fetch_one_cell!(conn, &format!("SELECT {};", expr));
+        assert!((result - expected).abs() < 1e-12, "{} gave {}", expr, result);
+    }
+    let bits: i64 = fetch_one_cell!(conn, "SELECT vec_hamming(vread('1 0 1 1 0 0 1 0 1', 'bit'), vread('1 1 1 0 0 0 1 0 0', 'bit'));");
+    assert_eq!(bits, 3);
+    let zero: f64 = fetch_one_cell!(conn, "SELECT vec_jaccard(vzero(3, 'bit'), vzero(3, 'bit'));");
+    assert_eq!(zero, 0.0);
+}
+
+#[test]
+fn distances_check_their_arguments() {
+    let conn = get_connection();
+    let nothing: Option<f64> = fetch_one_cell!(conn, "SELECT vec_cosine_similarity(vzero(2), vread('1 2'));");
+    assert_eq!(nothing, None);
+    let nothing: Option<f64> = fetch_one_cell!(conn, "SELECT vec_l2(vread('1 2'), NULL);");
+    assert_eq!(nothing, None);
+
+    for function in ["vec_dot", "vec_cosine_distance", "vec_l2", "vec_hamming", "vec_jaccard"].iter() {
+        let error = fetch_error(&conn, &format!("SELECT {}(vread('1 2 3'), vec_f32(1, 2));", function));
+        assert!(error.contains(&format!("{}(): the first vector has 3 dimensions but the second has 2", function)), "{}", error);
+    }
+    assert!(fetch_error(&conn, "SELECT vec_jaccard(vread('1 -1'), vread('1 1'));").contains("negative"));
+    assert!(fetch_error(&conn, "SELECT vec_l1('1 2', vread('1 2'));").contains("expected a vector, not text"));
+}
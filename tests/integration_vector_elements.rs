@@ -0,0 +1,101 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn slice_concat_and_sort_vectors() {
+    let conn = get_connection();
+    let cases = [
+        ("vec_slice(vread('1 2 3 4 5'), 2, 4)", "2.0 3.0 4.0"),
+        ("vec_slice(vread('1 2 3 4 5'), -2, -1)", "4.0 5.0"),
+        ("vec_slice(vread('1 2 3 4 5'), -10, 2)", "1.0 2.0"),
+        ("vec_slice(vread('1 2 3 4 5'), 4, 10)", "4.0 5.0"),
+        ("vec_slice(vread('1 2 3 4 5'), 4, 2)", ""),
+        ("vec_slice(vread('1 0 1 1', 'bit'), 2, 3)", "0 1"),
+        ("vec_concat(vread('1 2'), vread('3'), vread('4 5'))", "1.0 2.0 3.0 4.0 5.0"),
+        ("vec_concat(vread('1 0', 'bit'), vread('-3', 'i8'))", "1 0 -3"),
+        ("vec_sort(vread('3 -1 2 0', 'i8'))", "-1 0 2 3"),
+        ("vec_sort(vec_f32(2.5, -1, 9e999, -9e999))", "-inf -1.0 2.5 inf"),
+    ];
+    for &(expr, expected) in cases.iter() {
+        let shown: String = fetch_one_cell!(conn, &format!("SELECT vshow({});", expr));
+        assert_eq!(shown, expected, "{}", expr);
+    }
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(vec_concat(vec_f32(1), vread('2')));");
+    assert_eq!(ty, "f64");
+    // q8 vectors keep their range and codes
+    let same: bool = fetch_one_cell!(conn, "SELECT vec_slice(vec_sort(q), 1, 3) = vec_quantize_i8(vread('0 0.5 1'), 0, 1)
+        FROM (SELECT vec_quantize_i8(vread('1 0.5 2 0'), 0, 1) AS q);");
+    assert!(same);
+    let same: bool = fetch_one_cell!(conn, "SELECT vec_concat(vec_quantize_i8(vread('0.25'), 0, 1), vec_quantize_i8(vread('1 0'), 0, 1))
+        = vec_quantize_i8(vread('0.25 1 0'), 0, 1);");
+    assert!(same);
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT vec_concat(vread('1'), NULL);");
+    assert_eq!(nothing, None);
+
+    assert!(fetch_error(&conn, "SELECT vec_slice(vread('1 2'), 0, 1);").contains("positions count from 1"));
+    assert!(fetch_error(&conn, "SELECT vec_slice(vread('1 2'), 1.5, 2);").contains("start should be an integer, not a real"));
+    assert!(fetch_error(&conn, "SELECT vec_concat();").contains("expected at least one vector"));
+    assert!(fetch_error(&conn, "SELECT vec_concat(vread('1'), 2);").contains("expected a vector, not an integer"));
+}
+
+#[test]
+fn norms_normalization_and_softmax() {
+    let conn = get_connection();
+    let cases = [
+        ("vec_norm(vread('3 -4'))", 5.0),
+        ("vec_norm(vread('3 -4'), 1)", 7.0),
+        ("vec_norm(vread('3 -4'), 9e999)", 4.0),
+        ("vec_norm(vread('3 -4'), 3)", 91f64.cbrt()),
+        ("vec_norm(vread('1 1 0 1', 'bit'))", 3f64.sqrt()),
+        ("vec_norm(vec_normalize(vread('1 2 3')))", 1.0),
+        ("vec_dot(vec_softmax(vread('1 2 3')), vone(3))", 1.0),
+    ];
+    for &(expr, expected) in cases.iter() {
+        let result: f64 = fetch_one_cell!(conn, &format!("SELECT {};", expr));
+        assert!((result - expected).abs() < 1e-12, "{} gave {}", expr, result);
+    }
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(vec_normalize(vread('3 4', 'i8')));");
+    assert_eq!(shown, "0.6 0.8");
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(vec_softmax(vec_f32(1, 2)));");
+    assert_eq!(ty, "f32");
+    // Large logits don't overflow
+    let json: String = fetch_one_cell!(conn, "SELECT vec_to_json(vec_softmax(vread('1000 1000')));");
+    assert_eq!(json, "[0.5,0.5]");
+    // Infinite logits take all the probability, shared between ties
+    let json: String = fetch_one_cell!(conn, "SELECT vec_to_json(vec_softmax(vread('1 inf -inf inf')));");
+    assert_eq!(json, "[0.0,0.5,0.0,0.5]");
+    let json: String = fetch_one_cell!(conn, "SELECT vec_to_json(vec_softmax(vread('-inf -inf')));");
+    assert_eq!(json, "[0.5,0.5]");
+    let json: String = fetch_one_cell!(conn, "SELECT vec_to_json(vec_softmax(vread('0 -inf')));");
+    assert_eq!(json, "[1.0,0.0]");
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT vec_normalize(vzero(3));");
+    assert_eq!(nothing, None);
+
+    assert!(fetch_error(&conn, "SELECT vec_norm(vread('1 2'), 0);").contains("p should be positive, not 0"));
+    assert!(fetch_error(&conn, "SELECT vec_norm(vread('1 2'), 'two');").contains("p should be a number, not text"));
+}
+
+#[test]
+fn positions_of_the_largest_and_smallest_elements() {
+    let conn = get_connection();
+    let argmax: i64 = fetch_one_cell!(conn, "SELECT vec_argmax(vread('0.5 3 -1 3'));");
+    assert_eq!(argmax, 2);
+    let argmin: i64 = fetch_one_cell!(conn, "SELECT vec_argmin(vread('0.5 3 -1 3'));");
+    assert_eq!(argmin, 3);
+    let argmax: i64 = fetch_one_cell!(conn, "SELECT vec_argmax(div(vread('0 1 2'), vread('0 1 1')));");
+    assert_eq!(argmax, 3);
+    let nothing: Option<i64> = fetch_one_cell!(conn, "SELECT vec_argmax(vzero(0));");
+    assert_eq!(nothing, None);
+
+    let top: String = fetch_one_cell!(conn, "SELECT vec_topk_indices(vread('0.1 0.7 0.2 0.7'), 3);");
+    assert_eq!(top, "[2,4,3]");
+    let top: String = fetch_one_cell!(conn, "SELECT vec_topk_indices(vread('5 6'), 10);");
+    assert_eq!(top, "[2,1]");
+    let top: String = fetch_one_cell!(conn, "SELECT vec_topk_indices(vread('5 6'), 0);");
+    assert_eq!(top, "[]");
+    let total: i64 = fetch_one_cell!(conn, "SELECT sum(value) FROM json_each(vec_topk_indices(vread('1 2 3'), 2));");
+    assert_eq!(total, 5);
+    assert!(fetch_error(&conn, "SELECT vec_topk_indices(vread('1'), -1);").contains("k can't be negative"));
+}
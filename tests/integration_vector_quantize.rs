@@ -0,0 +1,96 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn quantize_and_dequantize_vectors() {
+    let conn = get_connection();
+    let ty: String = fetch_one_cell!(conn, "SELECT vec_type(vec_quantize_i8(vread('-1 0 0.5 1 2'), -1, 1));");
+    assert_eq!(ty, "q8");
+    // The header, the range and one byte per element
+    let len: i64 = fetch_one_cell!(conn, "SELECT length(vec_quantize_i8(vread('-1 0 0.5 1 2'), -1, 1));");
+    assert_eq!(len, 8 + 8 + 5);
+    let json: String = fetch_one_cell!(conn, "SELECT vec_to_json(vec_dequantize(vec_quantize_i8(vread('-1 0 0.5 1 2'), -1, 1)));");
+    let values: Vec<f64> = json.trim_matches(|c| c == '[' || c == ']').split(',').map(|x| x.parse().unwrap()).collect();
+    // Every value is within half a step of the original, clamped to the range
+    for (x, expected) in values.iter().zip(&[-1.0, 0.0, 0.5, 1.0, 1.0]) {
+        assert!((x - expected).abs() <= 1.0 / 255.0 + 1e-6, "{:?}", values);
+    }
+    // Without a range, the ends of the vector are kept exactly
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(vec_dequantize(vec_quantize_i8(vread('3 -2 5'))));");
+    assert!(shown.ends_with(" -2.0 5.0"), "{}", shown);
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(vec_quantize_i8(vread('3 -2 5')));");
+    assert!(shown.ends_with(" -2.0 5.0"), "{}", shown);
+
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(vec_quantize_binary(vread('0.5 -1 0 2')));");
+    assert_eq!(shown, "1 0 0 1");
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(vec_dequantize(vec_quantize_binary(vread('0.5 -1 0 2'))));");
+    assert_eq!(shown, "1.0 0.0 0.0 1.0");
+    // Bits are 0 and 1 to the distance functions too
+    let same: bool = fetch_one_cell!(conn, "SELECT vec_dot(vec_dequantize(b), q) = vec_dot(b, q)
+        FROM (SELECT vec_quantize_binary(vread('0.5 -1 0 2')) AS b, vread('3 5 7 11') AS q);");
+    assert!(same);
+    let shown: String = fetch_one_cell!(conn, "SELECT vshow(vec_dequantize(vread('1 2', 'i8')));");
+    assert_eq!(shown, "1.0 2.0");
+    let nothing: Option<Vec<u8>> = fetch_one_cell!(conn, "SELECT vec_quantize_i8(NULL, 0, 1);");
+    assert_eq!(nothing, None);
+}
+
+#[test]
+fn distances_work_on_quantized_vectors() {
+    let conn = get_connection();
+    conn.execute_batch("
+        CREATE TABLE docs(id INTEGER PRIMARY KEY, embedding BLOB, embedding_q8 BLOB, embedding_bits BLOB);
+        INSERT INTO docs(id, embedding) VALUES
+            (1, vread('0.9 0.1 -0.3')), (2, vread('-0.5 0.5 0.5')), (3, vread('0.2 -0.8 0.1')), (4, vread('0.8 0.2 -0.2'));
+        UPDATE docs SET embedding_q8 = vec_quantize_i8(embedding, -1, 1), embedding_bits = vec_quantize_binary(embedding);
+    ").unwrap();
+    let pairs: Vec<(f64, f64)> = {
+        let mut stmt = conn.prepare("SELECT vec_l2(embedding_q8, vread('1 0 0')), vec_l2(embedding, vread('1 0 0')) FROM docs;").unwrap();
+        let rows = stmt.query_map(&[], |row| (row.get(0), row.get(1))).unwrap();
+        rows.map(|row| row.unwrap()).collect()
+    };
+    for (quantized, exact) in pairs {
+        assert!((quantized - exact).abs() < 0.01, "{} vs {}", quantized, exact);
+    }
+    let nearest: Vec<i64> = {
+        let mut stmt = conn.prepare("SELECT rowid FROM vec_knn('docs', 'embedding_q8', vread('1 0 0'), 2);").unwrap();
+        let rows = stmt.query_map(&[], |row| row.get(0)).unwrap();
+        rows.map(|row| row.unwrap()).collect()
+    };
+    assert_eq!(nearest, vec![1, 4]);
+    // Computing on the codes gives what the dequantized vectors give, to
+    // within the precision of their f32 elements
+    for function in ["vec_dot", "vec_l2", "vec_l2_squared", "vec_cosine_similarity", "vec_cosine_distance"].iter() {
+        let pairs: Vec<(f64, f64)> = {
+            let mut stmt = conn.prepare(&format!("
+                SELECT {0}(a.embedding_q8, b.embedding_q8), {0}(vec_dequantize(a.embedding_q8), vec_dequantize(b.embedding_q8))
+                FROM docs a, docs b;", function)).unwrap();
+            let rows = stmt.query_map(&[], |row| (row.get(0), row.get(1))).unwrap();
+            rows.map(|row| row.unwrap()).collect()
+        };
+        assert_eq!(pairs.len(), 16);
+        for (codes, values) in pairs {
+            assert!((codes - values).abs() < 1e-6, "{}: {} vs {}", function, codes, values);
+        }
+    }
+    let nothing: Option<f64> = fetch_one_cell!(conn, "SELECT vec_cosine_similarity(q, q) FROM (SELECT vec_quantize_i8(vzero(3), 0, 1) AS q);");
+    assert_eq!(nothing, None);
+    let signs: i64 = fetch_one_cell!(conn, "SELECT vec_hamming(a.embedding_bits, b.embedding_bits) FROM docs a, docs b WHERE a.id = 1 AND b.id = 2;");
+    assert_eq!(signs, 2);
+    let same: i64 = fetch_one_cell!(conn, "SELECT vec_hamming(a.embedding_bits, b.embedding_bits) FROM docs a, docs b WHERE a.id = 1 AND b.id = 4;");
+    assert_eq!(same, 0);
+}
+
+#[test]
+fn quantizing_checks_its_arguments() {
+    let conn = get_connection();
+    assert!(fetch_error(&conn, "SELECT vec_quantize_i8(vread('1 2'), 1, 1);").contains("vec_quantize_i8(): min should be less than max"));
+    assert!(fetch_error(&conn, "SELECT vec_quantize_i8(vread('1 2'), 'a', 1);").contains("vec_quantize_i8(): min should be a number, not text"));
+    assert!(fetch_error(&conn, "SELECT vec_quantize_binary('1 2');").contains("expected a vector, not text"));
+    assert!(fetch_error(&conn, "SELECT vec_import(x'00', 'q8');").contains("q8 vectors always have a header"));
+    // A q8 vector whose range runs backwards, from 1 to 0
+    assert!(fetch_error(&conn, "SELECT vec_dequantize(x'58560105010000000000803F0000000000');")
+        .contains("corrupt vector"));
+}
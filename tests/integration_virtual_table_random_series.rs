@@ -0,0 +1,74 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn random_series_is_reproducible() {
+    let conn = get_connection();
+    let first: String = fetch_one_cell!(conn,
+        "SELECT group_concat(value) FROM random_series(20, 'normal', 0, 1, 42);");
+    let second: String = fetch_one_cell!(conn,
+        "SELECT group_concat(value) FROM random_series(20, 'normal', 0, 1, 42);");
+    assert_eq!(first, second);
+    let other_seed: String = fetch_one_cell!(conn,
+        "SELECT group_concat(value) FROM random_series(20, 'normal', 0, 1, 43);");
+    assert!(first != other_seed);
+    // Each row depends only on its rowid, not on which rows came before
+    let fifth: f64 = fetch_one_cell!(conn,
+        "SELECT value FROM random_series(20, 'normal', 0, 1, 42) WHERE rowid = 5;");
+    let fifth_of_ten: f64 = fetch_one_cell!(conn,
+        "SELECT value FROM random_series(10, 'normal', 0, 1, 42) WHERE rowid = 5;");
+    assert_eq!(fifth, fifth_of_ten);
+}
+
+#[test]
+fn random_series_follows_the_distribution() {
+    let conn = get_connection();
+    let (count, low, high): (i64, f64, f64) = conn.query_row(
+        "SELECT count(*), min(value), max(value) FROM random_series(1000, 'uniform', 2, 3, 7);",
+        &[], |r| (r.get(0), r.get(1), r.get(2))).unwrap();
+    assert_eq!(count, 1000);
+    assert!(low >= 2.0 && high < 3.0);
+    let mean: f64 = fetch_one_cell!(conn,
+        "SELECT avg(value) FROM random_series(10000, 'normal', 5, 2, 7);");
+    assert!((mean - 5.0).abs() < 0.1);
+    let mean: f64 = fetch_one_cell!(conn,
+        "SELECT avg(value) FROM random_series(10000, 'exponential', 4);");
+    assert!((mean - 0.25).abs() < 0.01);
+    for &lambda in [2.5, 40.0].iter() {
+        let mean: f64 = fetch_one_cell!(conn, &format!(
+            "SELECT avg(value) FROM random_series(10000, 'poisson', {}, 7);", lambda));
+        assert!((mean - lambda).abs() < lambda.sqrt() * 0.05, "poisson({}) mean {}", lambda, mean);
+    }
+    for &(n, p) in [(20, 0.1), (1000, 0.3), (1000, 0.9)].iter() {
+        let (mean, low, high): (f64, i64, i64) = conn.query_row(&format!(
+            "SELECT avg(value), min(value), max(value) FROM random_series(10000, 'binomial', {}, {}, 7);", n, p),
+            &[], |r| (r.get(0), r.get(1), r.get(2))).unwrap();
+        let expected = n as f64 * p;
+        assert!((mean - expected).abs() < (expected * (1.0 - p)).sqrt() * 0.05,
+            "binomial({}, {}) mean {}", n, p, mean);
+        assert!(low >= 0 && high <= n);
+    }
+    let (ones, low, high): (i64, i64, i64) = conn.query_row(
+        "SELECT sum(value = 1), min(value), max(value) FROM random_series(10000, 'zipf', 100, 1.5, 7);",
+        &[], |r| (r.get(0), r.get(1), r.get(2))).unwrap();
+    // P(X = 1) = 1 / H(100, 1.5) which is about 0.41
+    assert!(ones > 3900 && ones < 4300, "zipf ones {}", ones);
+    assert!(low >= 1 && high <= 100);
+}
+
+#[test]
+fn random_series_rejects_bad_arguments() {
+    let conn = get_connection();
+    assert!(fetch_error(&conn, "SELECT * FROM random_series(10, 'cauchy', 0, 1);")
+        .contains("unknown distribution"));
+    assert!(fetch_error(&conn, "SELECT * FROM random_series(10, 'normal', 0, -1);")
+        .contains("std_dev >= 0"));
+    assert!(fetch_error(&conn, "SELECT * FROM random_series(10, 'exponential', 1, 2, 3);")
+        .contains("takes 1 parameters and a seed"));
+    assert!(fetch_error(&conn, "SELECT * FROM random_series(5, 'uniform', 'a', 'b');")
+        .contains("requires numbers after the distribution, but argument 3 is text"));
+    assert!(fetch_error(&conn, "SELECT * FROM random_series(5, 'uniform', 0, 1, NULL);")
+        .contains("argument 5 is NULL"));
+}
@@ -0,0 +1,46 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn regex_matches_lists_every_match() {
+    let conn = get_connection();
+    let mut stmt = conn.prepare(
+        r"SELECT idx, start, end, text, groups FROM regex_matches('(\d+)(?P<unit>ms|s)', 'took 35ms then 2s');").unwrap();
+    let rows: Vec<(i64, i64, i64, String, String)> = stmt
+        .query_map(&[], |r| (r.get(0), r.get(1), r.get(2), r.get(3), r.get(4))).unwrap()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(rows, vec![
+        (1, 6, 10, "35ms".to_string(), r#"{"1":"35","unit":"ms"}"#.to_string()),
+        (2, 16, 18, "2s".to_string(), r#"{"1":"2","unit":"s"}"#.to_string())
+    ]);
+    // Positions count characters, like substr()
+    let consistent: i64 = fetch_one_cell!(conn,
+        r"SELECT count(*) FROM regex_matches('\w+', 'ünïcödé wörds') WHERE substr('ünïcödé wörds', start, end - start) = text;");
+    assert_eq!(consistent, 2);
+}
+
+#[test]
+fn regex_matches_works_as_a_lateral_join() {
+    let conn = get_connection();
+    conn.execute_batch("
+        CREATE TABLE logs(id INTEGER PRIMARY KEY, line TEXT);
+        INSERT INTO logs(line) VALUES ('a 10ms b 20ms'), ('nothing'), (NULL), ('c 5ms');
+    ").unwrap();
+    let total: i64 = fetch_one_cell!(conn,
+        r"SELECT sum(regex_capture('\d+', m.text, 0)) FROM logs, regex_matches('(\d+)ms', logs.line) AS m;");
+    assert_eq!(total, 35);
+    let per_line: String = fetch_one_cell!(conn,
+        r"SELECT group_concat(id || ':' || n) FROM (
+            SELECT logs.id, count(m.idx) AS n FROM logs LEFT JOIN regex_matches('\d+ms', logs.line) AS m
+            GROUP BY logs.id);");
+    assert_eq!(per_line, "1:2,2:0,3:0,4:1");
+}
+
+#[test]
+fn regex_matches_reports_bad_patterns() {
+    let conn = get_connection();
+    assert!(fetch_error(&conn, "SELECT * FROM regex_matches('(', 'abc');").contains("regex_matches()"));
+}
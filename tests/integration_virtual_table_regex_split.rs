@@ -0,0 +1,40 @@
+extern crate rusqlite;
+extern crate glob;
+#[macro_use] mod common;
+use common::{get_connection, fetch_error};
+
+#[test]
+fn regex_split_yields_numbered_pieces() {
+    let conn = get_connection();
+    let mut stmt = conn.prepare(r"SELECT idx, value FROM regex_split('\s*,\s*', 'a ,b,  c,');").unwrap();
+    let rows: Vec<(i64, String)> = stmt
+        .query_map(&[], |r| (r.get(0), r.get(1))).unwrap()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(rows, vec![
+        (1, "a".to_string()), (2, "b".to_string()), (3, "c".to_string()), (4, "".to_string())
+    ]);
+    // Tokenizing a column with irregular whitespace
+    conn.execute_batch("
+        CREATE TABLE lines(id INTEGER PRIMARY KEY, line TEXT);
+        INSERT INTO lines(line) VALUES ('x  y\tz'), ('w');
+    ").unwrap();
+    let tokens: String = fetch_one_cell!(conn,
+        r"SELECT group_concat(lines.id || value, ' ') FROM lines, regex_split('\s+', lines.line);");
+    assert_eq!(tokens, "1x 1y 1z 2w");
+    assert!(fetch_error(&conn, "SELECT * FROM regex_split('[', 'abc');").contains("regex_split()"));
+}
+
+#[test]
+fn regex_split_part_picks_one_piece() {
+    let conn = get_connection();
+    let second: String = fetch_one_cell!(conn, r"SELECT regex_split_part('\s+', 'alpha  beta   gamma', 2);");
+    assert_eq!(second, "beta");
+    let last: String = fetch_one_cell!(conn, r"SELECT regex_split_part('\s+', 'alpha  beta   gamma', -1);");
+    assert_eq!(last, "gamma");
+    let missing: Option<String> = fetch_one_cell!(conn, r"SELECT regex_split_part('\s+', 'alpha beta', 3);");
+    assert_eq!(missing, None);
+    let missing: Option<String> = fetch_one_cell!(conn, r"SELECT regex_split_part('\s+', 'alpha beta', -3);");
+    assert_eq!(missing, None);
+    assert!(fetch_error(&conn, r"SELECT regex_split_part(',', 'a,b', 0);").contains("count from 1"));
+}